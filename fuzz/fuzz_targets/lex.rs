@@ -0,0 +1,12 @@
+//! Feeds arbitrary bytes to the lexer. It should always give back tokens or an error, never panic.
+//!
+//! Run with `cargo fuzz run lex` from the repository root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tiny_basic_compiler::lang::create_lexer;
+
+fuzz_target!(|data: &[u8]| {
+    let source = String::from_utf8_lossy(data);
+    let _ = create_lexer().parse_stream(&source).collect::<Result<Vec<_>, _>>();
+});
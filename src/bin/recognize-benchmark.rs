@@ -0,0 +1,73 @@
+//! Times [Grammar::parse] against [Grammar::recognize] on the same 10k token input. Run it with
+//! `cargo run --release --bin recognize-benchmark`.
+
+use std::time::{Duration, Instant};
+
+use tiny_basic_compiler::grammar::{Grammar, GrammarBuilder, Rule};
+
+#[derive(Debug)]
+enum Token
+{
+    Number,
+    Plus,
+}
+
+impl Token
+{
+    fn is_number(&self) -> bool
+    {
+        matches!(self, Self::Number)
+    }
+
+    fn is_plus(&self) -> bool
+    {
+        matches!(self, Self::Plus)
+    }
+}
+
+/// `1 + 1 + 1 ...`, 10k tokens long.
+fn input() -> Vec<Token>
+{
+    let mut input = vec![Token::Number];
+    while input.len() < 10_000
+    {
+        input.push(Token::Plus);
+        input.push(Token::Number);
+    }
+    input
+}
+
+/// Runs `f` a bunch of times on a fresh input and returns the fastest run.
+fn time(f: impl Fn(Vec<Token>)) -> Duration
+{
+    (0..50).map(|_|
+    {
+        let input = input();
+        let start = Instant::now();
+        f(input);
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn main()
+{
+    // E -> E + n | n
+    let mut grammar_builder = GrammarBuilder::<Token>::new();
+    let e = grammar_builder.id();
+    let grammar: Grammar<'_, Token> = grammar_builder
+        .add_rule(Rule::new(e)
+            .add_nonterminating_symbol(e)
+            .add_terminating_symbol(&Token::is_plus)
+            .add_terminating_symbol(&Token::is_number))
+        .add_rule(Rule::new(e).add_terminating_symbol(&Token::is_number))
+        .build()
+        .unwrap();
+
+    assert!(grammar.recognize(input()).is_ok());
+
+    let parse = time(|input| { grammar.parse(input).unwrap(); });
+    let recognize = time(|input| grammar.recognize(input).unwrap());
+    println!("parse:     {:?}", parse);
+    println!("recognize: {:?}", recognize);
+    println!("speedup:   {:.1}x", parse.as_secs_f64() / recognize.as_secs_f64());
+}
@@ -0,0 +1,128 @@
+//! An interactive Tiny BASIC prompt. Run it with `cargo run --bin repl`.
+//!
+//! A line with a number is added to the program, replacing any line with the same number, and a
+//! number on its own deletes that line. Anything else runs straight away. On top of the usual
+//! statements, `LIST` shows the program, `RUN` runs it from the top with every variable cleared,
+//! `NEW` throws it away and `QUIT` leaves.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+use tiny_basic_compiler::lang::ast::{Program, Statement};
+use tiny_basic_compiler::lang::create_lexer;
+use tiny_basic_compiler::lang::interpreter::{Interpreter, StdioBasicIO};
+use tiny_basic_compiler::lang::parser::Parser;
+use tiny_basic_compiler::lang::token::Token;
+
+struct Repl
+{
+    /// The source of each numbered line, without the number. Kept as text so the program can be
+    /// parsed again from scratch after every change.
+    lines: BTreeMap<usize, String>,
+    /// Where statements typed in without a number run, so variables stick around between them.
+    interpreter: Interpreter<StdioBasicIO>,
+}
+
+impl Repl
+{
+    fn new() -> Self
+    {
+        Self
+        {
+            lines: BTreeMap::new(),
+            interpreter: Interpreter::new(Program::new(), StdioBasicIO::new()),
+        }
+    }
+
+    /// Parses every numbered line into a [Program], in order.
+    fn program(&self) -> Result<Program>
+    {
+        let source: String = self.lines.iter()
+            .map(|(number, line)| format!("{} {}\n", number, line))
+            .collect();
+        let tokens: Vec<Token> = create_lexer().parse_stream(&source).collect::<Result<_, _>>()?;
+        Parser::new(tokens).parse()
+    }
+
+    /// Handles one line typed at the prompt. Returns false once it's time to quit.
+    fn handle(&mut self, input: &str) -> Result<bool>
+    {
+        let input = input.trim();
+        // These aren't keywords, so the lexer would read them as variables.
+        match input.to_ascii_uppercase().as_str()
+        {
+            "QUIT" => return Ok(false),
+            "NEW" => {
+                self.lines.clear();
+                return Ok(true);
+            },
+            _ => (),
+        }
+
+        let tokens: Vec<Token> = create_lexer().parse_stream(input).collect::<Result<_, _>>()?;
+        match tokens.as_slice()
+        {
+            [] => return Ok(true),
+            [Token::Number(number)] => {
+                self.lines.remove(number);
+                return Ok(true);
+            },
+            [Token::Number(number), ..] => {
+                // Parsed now so mistakes show up as soon as the line is typed.
+                Parser::new(tokens.clone()).parse()?;
+                let statement = input.trim_start_matches(|x: char| x.is_ascii_digit()).trim_start();
+                self.lines.insert(*number, statement.to_string());
+                return Ok(true);
+            },
+            _ => (),
+        }
+
+        let program = Parser::new(tokens).parse()?;
+        let Some(line) = program.instructions().first() else { return Ok(true) };
+        match line.statement()
+        {
+            Statement::List => {
+                for line in self.program()?.instructions()
+                {
+                    println!("{}", line);
+                }
+            },
+            Statement::Run => {
+                self.interpreter = Interpreter::new(self.program()?, StdioBasicIO::new());
+                self.interpreter.run()?;
+            },
+            statement => self.interpreter.execute_statement(statement)?,
+        }
+        Ok(true)
+    }
+}
+
+fn main()
+{
+    let mut repl = Repl::new();
+    loop
+    {
+        print!("> ");
+        let _ = std::io::stdout().flush();
+
+        // Locked one line at a time, so INPUT can read from stdin too.
+        let mut input = String::new();
+        match std::io::stdin().lock().read_line(&mut input)
+        {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(error) => {
+                eprintln!("{}", error);
+                break;
+            },
+        }
+
+        match repl.handle(&input)
+        {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(error) => println!("?{:#}", error),
+        }
+    }
+}
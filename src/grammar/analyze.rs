@@ -0,0 +1,313 @@
+//! Looks for rules that send a parser around in circles without reading any input.
+//!
+//! Left recursion (`expression -> expression + term`) is fine for [Grammar::parse], but it sends
+//! the [RecursiveDescentEngine](crate::grammar::RecursiveDescentEngine) into infinite recursion.
+//! Unit rule cycles (`A -> B`, `B -> A`) are a problem for every engine, since a tree can be
+//! wrapped in them forever.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::grammar::FirstSymbol;
+use crate::grammar::Grammar;
+use crate::grammar::Id;
+use crate::grammar::SymbolSchema;
+
+/// What kind of cycle a [GrammarCycle] is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CycleKind
+{
+    /// A symbol can start with itself, directly (`A -> A b`) or through other symbols
+    /// (`A -> B a`, `B -> A b`).
+    LeftRecursion,
+    /// Rules that just replace one symbol with another lead back to where they started.
+    UnitCycle,
+}
+
+/// A cycle found by [Grammar::analyze].
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[error("{}: {}", match .kind { CycleKind::LeftRecursion => "Left recursion", CycleKind::UnitCycle => "Unit rule cycle" }, .path.join(" -> "))]
+pub struct GrammarCycle
+{
+    pub kind: CycleKind,
+    /// The names of the symbols around the cycle, starting and ending with the same one. Symbols
+    /// without a [name](crate::grammar::GrammarBuilder::name) are written as their [Id].
+    pub path: Vec<String>,
+}
+
+/// Everything [Grammar::analyze] found. None of it stops the grammar from being built unless
+/// [GrammarBuilder::deny_cycles](crate::grammar::GrammarBuilder::deny_cycles) was used.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct GrammarReport
+{
+    pub cycles: Vec<GrammarCycle>,
+}
+
+impl GrammarReport
+{
+    pub fn is_empty(&self) -> bool
+    {
+        self.cycles.is_empty()
+    }
+}
+
+impl Display for GrammarReport
+{
+    /// Writes each cycle on its own line.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        for cycle in &self.cycles
+        {
+            writeln!(f, "{}", cycle)?;
+        }
+        Ok(())
+    }
+}
+
+/// A symbol a rule can start with, and whether the rule is nothing but that symbol.
+type Corner = (Id, bool);
+
+impl<'a, L> Grammar<'a, L>
+{
+    /// Finds the left recursion and unit rule cycles in the grammar.
+    ///
+    /// Unit cycles are reported first. Every unit cycle is also left recursion, but it's only
+    /// reported once. Each symbol shows up in at most one cycle of each kind, so a big tangle of
+    /// rules gives a few representative cycles instead of every path through it.
+    pub fn analyze(&self) -> GrammarReport
+    {
+        let first_sets = self.compute_first_sets();
+        let can_be_empty = |id: &Id| first_sets.get(id).is_some_and(|x| x.contains(&FirstSymbol::Empty));
+
+        // The symbols each symbol can start with, in the order the rules were added.
+        let mut order: Vec<Id> = Vec::new();
+        let mut left_corners: HashMap<Id, Vec<Corner>> = HashMap::new();
+        for rule in self.rules()
+        {
+            if !left_corners.contains_key(&rule.input_symbol())
+            {
+                order.push(rule.input_symbol());
+            }
+            let corners = left_corners.entry(rule.input_symbol()).or_default();
+            let is_unit = rule.replacement_symbols().len() == 1;
+            for symbol in rule.replacement_symbols()
+            {
+                let SymbolSchema::Nonterminating(id) = symbol else { break };
+                if !corners.contains(&(*id, is_unit))
+                {
+                    corners.push((*id, is_unit));
+                }
+                if !can_be_empty(id)
+                {
+                    break;
+                }
+            }
+        }
+
+        let mut report = GrammarReport::default();
+        let mut in_unit_cycle = HashSet::new();
+        for symbol in &order
+        {
+            if in_unit_cycle.contains(symbol)
+            {
+                continue;
+            }
+            if let Some(path) = shortest_cycle(*symbol, &left_corners, true)
+            {
+                in_unit_cycle.extend(path.iter().copied());
+                report.cycles.push(self.cycle(CycleKind::UnitCycle, &path));
+            }
+        }
+
+        let mut in_left_cycle = HashSet::new();
+        for symbol in &order
+        {
+            if in_left_cycle.contains(symbol) || in_unit_cycle.contains(symbol)
+            {
+                continue;
+            }
+            if let Some(path) = shortest_cycle(*symbol, &left_corners, false)
+            {
+                in_left_cycle.extend(path.iter().copied());
+                report.cycles.push(self.cycle(CycleKind::LeftRecursion, &path));
+            }
+        }
+
+        report
+    }
+
+    fn cycle(&self, kind: CycleKind, path: &[Id]) -> GrammarCycle
+    {
+        GrammarCycle
+        {
+            kind,
+            path: path.iter()
+                .map(|x| self.symbol_name(*x).map_or_else(|| x.to_string(), String::from))
+                .collect(),
+        }
+    }
+}
+
+/// Finds the shortest way from `start` back to itself through `left_corners`, only following unit
+/// rules if `units_only` is set. The path starts and ends with `start`.
+fn shortest_cycle(start: Id, left_corners: &HashMap<Id, Vec<Corner>>, units_only: bool) -> Option<Vec<Id>>
+{
+    let mut came_from: HashMap<Id, Id> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(id) = queue.pop_front()
+    {
+        let corners = left_corners.get(&id).into_iter().flatten();
+        for (next, is_unit) in corners
+        {
+            if units_only && !is_unit
+            {
+                continue;
+            }
+
+            if *next == start
+            {
+                let mut path = vec![start, id];
+                while let Some(previous) = came_from.get(path.last().unwrap())
+                {
+                    path.push(*previous);
+                }
+                // `start` is never put in `came_from`, so the walk back stops there.
+                path.reverse();
+                return Some(path);
+            }
+
+            if !came_from.contains_key(next)
+            {
+                came_from.insert(*next, id);
+                queue.push_back(*next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::grammar::{GrammarBuildError, GrammarBuilder};
+
+    use super::*;
+
+    fn is_a(c: &char) -> bool
+    {
+        *c == 'a'
+    }
+
+    fn is_b(c: &char) -> bool
+    {
+        *c == 'b'
+    }
+
+    #[test]
+    fn test_direct_left_recursion()
+    {
+        // expression -> expression + a | a
+        let mut builder = GrammarBuilder::<char>::new();
+        let expression = builder.id();
+        builder
+            .rule(expression, |r| r.nt(expression).t(&is_b).t(&is_a))
+            .rule(expression, |r| r.t(&is_a))
+            .name(expression, "expression");
+        let report = builder.build().unwrap().analyze();
+
+        assert_eq!(report.cycles, vec![GrammarCycle
+        {
+            kind: CycleKind::LeftRecursion,
+            path: vec![String::from("expression"), String::from("expression")],
+        }]);
+        assert_eq!(report.to_string(), "Left recursion: expression -> expression\n");
+    }
+
+    #[test]
+    fn test_indirect_cycles()
+    {
+        // s -> x a, x -> y b, y -> x | a. x and y go around in a unit cycle, and s isn't part of
+        // any cycle.
+        let mut builder = GrammarBuilder::<char>::new();
+        let s = builder.id();
+        let x = builder.id();
+        let y = builder.id();
+        builder
+            .rule(s, |r| r.nt(x).t(&is_a))
+            .rule(x, |r| r.nt(y).t(&is_b))
+            .rule(y, |r| r.nt(x))
+            .rule(y, |r| r.t(&is_a))
+            .name(s, "s")
+            .name(x, "x")
+            .name(y, "y");
+        let report = builder.build().unwrap().analyze();
+
+        assert_eq!(report.cycles, vec![GrammarCycle
+        {
+            kind: CycleKind::LeftRecursion,
+            path: vec![String::from("x"), String::from("y"), String::from("x")],
+        }]);
+
+        // a -> b, b -> a is a unit cycle, and only reported once.
+        let mut builder = GrammarBuilder::<char>::new();
+        let a = builder.id();
+        let b = builder.id();
+        builder
+            .rule(a, |r| r.nt(b))
+            .rule(b, |r| r.nt(a))
+            .rule(b, |r| r.t(&is_b))
+            .name(a, "A")
+            .name(b, "B");
+        let report = builder.build().unwrap().analyze();
+
+        assert_eq!(report.cycles, vec![GrammarCycle
+        {
+            kind: CycleKind::UnitCycle,
+            path: vec![String::from("A"), String::from("B"), String::from("A")],
+        }]);
+    }
+
+    #[test]
+    fn test_clean_grammar_has_empty_report()
+    {
+        // s -> a s | b
+        let mut builder = GrammarBuilder::<char>::new();
+        let s = builder.id();
+        builder
+            .rule(s, |r| r.t(&is_a).nt(s))
+            .rule(s, |r| r.t(&is_b));
+        let report = builder.build().unwrap().analyze();
+
+        assert!(report.is_empty());
+        assert_eq!(report.to_string(), "");
+    }
+
+    #[test]
+    fn test_deny_cycles()
+    {
+        let mut builder = GrammarBuilder::<char>::new();
+        let s = builder.id();
+        builder
+            .rule(s, |r| r.nt(s).t(&is_a))
+            .rule(s, |r| r.t(&is_a));
+        assert!(builder.build().is_ok());
+
+        let mut builder = GrammarBuilder::<char>::new();
+        let s = builder.id();
+        builder
+            .rule(s, |r| r.nt(s).t(&is_a))
+            .rule(s, |r| r.t(&is_a))
+            .deny_cycles();
+        let Err(GrammarBuildError::Cycle(cycle)) = builder.build()
+        else
+        {
+            panic!("expected the cycle to be an error");
+        };
+        assert_eq!(cycle.kind, CycleKind::LeftRecursion);
+        assert_eq!(cycle.path, vec![s.to_string(), s.to_string()]);
+    }
+}
@@ -0,0 +1,504 @@
+//! Loads a [Grammar] from BNF text, so it can be changed without recompiling.
+//!
+//! ```text
+//! # Comments start with a # and go to the end of the line.
+//! line ::= NUMBER statement NEWLINE
+//!        | statement NEWLINE
+//! statement ::= "PRINT" NUMBER | "END"
+//! ```
+//!
+//! Every name on the left of a `::=` is a non-terminating symbol, and the first one is the start
+//! symbol. Any other name has to be a terminal in the [TerminalRegistry], and quoted literals are
+//! looked up with its literal hook.
+//!
+//! [Grammar::to_bnf] goes the other way, and writes any grammar out in the same syntax.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::grammar::Grammar;
+use crate::grammar::GrammarBuilder;
+use crate::grammar::Id;
+use crate::grammar::Rule;
+use crate::grammar::SymbolSchema;
+use crate::grammar::Terminal;
+use crate::grammar::TokenRecognizer;
+
+/// Looks up the recognizer for a quoted literal. See [TerminalRegistry::literals].
+type LiteralHook<'a, L> = Box<dyn Fn(&str) -> Option<TokenRecognizer<'a, L>> + 'a>;
+
+/// The terminating symbols a BNF grammar can use, by name.
+pub struct TerminalRegistry<'a, L>
+{
+    terminals: HashMap<String, TokenRecognizer<'a, L>>,
+    literal_hook: Option<LiteralHook<'a, L>>,
+}
+
+impl<'a, L> TerminalRegistry<'a, L>
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            terminals: HashMap::new(),
+            literal_hook: None,
+        }
+    }
+
+    /// Lets the BNF use `name` (like `NUMBER`) for tokens matching `recognizer`.
+    pub fn terminal(mut self, name: impl Into<String>, recognizer: TokenRecognizer<'a, L>) -> Self
+    {
+        self.terminals.insert(name.into(), recognizer);
+        self
+    }
+
+    /// Sets the function used to look up quoted literals. It gets the text between the quotes
+    /// (`PRINT` for `"PRINT"`) and returns [None] if there's no such literal.
+    ///
+    /// The hook should hand back the same recognizer every time it's asked for the same literal.
+    pub fn literals(mut self, hook: impl Fn(&str) -> Option<TokenRecognizer<'a, L>> + 'a) -> Self
+    {
+        self.literal_hook = Some(Box::new(hook));
+        self
+    }
+
+    fn literal(&self, text: &str) -> Option<TokenRecognizer<'a, L>>
+    {
+        self.literal_hook.as_ref().and_then(|hook| hook(text))
+    }
+}
+
+impl<L> Default for TerminalRegistry<'_, L>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Why some BNF text couldn't be loaded, and where in the text it went wrong. Lines and columns
+/// start at 1, and columns count characters.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{line}:{column}: {kind}")]
+pub struct BnfError
+{
+    pub line: usize,
+    pub column: usize,
+    pub kind: BnfErrorKind,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BnfErrorKind
+{
+    #[error("Unexpected character {0:?}")]
+    UnexpectedCharacter(char),
+    #[error("Missing the closing \" of a literal")]
+    UnterminatedLiteral,
+    #[error("Expected the name of a rule")]
+    ExpectedRuleName,
+    #[error("Expected ::= after the rule name")]
+    ExpectedDefinition,
+    #[error("{0} isn't a terminal and doesn't have any rules")]
+    UnknownSymbol(String),
+    #[error("No literal \"{0}\" is registered")]
+    UnknownLiteral(String),
+    #[error("There aren't any rules")]
+    NoRules,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum BnfToken
+{
+    Name(String),
+    Literal(String),
+    Definition,
+    Alternative,
+}
+
+/// A token along with the line and column it starts at.
+type Positioned<T> = (T, usize, usize);
+
+/// A rule's name and the symbols in one of its alternatives.
+type BnfRule = (String, Vec<Positioned<BnfToken>>);
+
+impl<'a, L> Grammar<'a, L>
+{
+    /// Builds a grammar from BNF `text`, see the [module docs](self) for the syntax. Each
+    /// non-terminating symbol is named after its rule, see [Grammar::symbol_name], and every
+    /// terminal is registered as a [terminal class](GrammarBuilder::terminal_class) so the grammar
+    /// can be [compiled](Grammar::compile).
+    pub fn from_bnf(text: &str, terminals: &TerminalRegistry<'a, L>) -> Result<Grammar<'a, L>, BnfError>
+    {
+        let rules = parse_rules(&tokenize(text)?)?;
+        if rules.is_empty()
+        {
+            return Err(BnfError { line: 1, column: 1, kind: BnfErrorKind::NoRules });
+        }
+
+        let mut builder = GrammarBuilder::new();
+        let mut ids: HashMap<&str, Id> = HashMap::new();
+        for (lhs, _) in &rules
+        {
+            if !ids.contains_key(lhs.as_str())
+            {
+                let id = builder.id();
+                builder.name(id, lhs.as_str());
+                ids.insert(lhs, id);
+            }
+        }
+
+        let mut classes: Vec<String> = Vec::new();
+        for (lhs, symbols) in &rules
+        {
+            let mut rule = Rule::new(ids[lhs.as_str()]);
+            for (symbol, line, column) in symbols
+            {
+                let error = |kind| BnfError { line: *line, column: *column, kind };
+                let (class, recognizer) = match symbol
+                {
+                    BnfToken::Name(name) => match ids.get(name.as_str())
+                    {
+                        Some(id) =>
+                        {
+                            rule = rule.add_nonterminating_symbol(*id);
+                            continue;
+                        },
+                        None => (name.clone(), *terminals.terminals.get(name)
+                            .ok_or_else(|| error(BnfErrorKind::UnknownSymbol(name.clone())))?),
+                    },
+                    BnfToken::Literal(text) => (format!("\"{}\"", text), terminals.literal(text)
+                        .ok_or_else(|| error(BnfErrorKind::UnknownLiteral(text.clone())))?),
+                    // parse_rules only keeps names and literals.
+                    BnfToken::Definition | BnfToken::Alternative => unreachable!(),
+                };
+
+                if !classes.contains(&class)
+                {
+                    builder = builder.terminal_class(class.clone(), recognizer);
+                    classes.push(class.clone());
+                }
+                rule = rule.add_terminating_symbol_described(class, recognizer);
+            }
+            builder = builder.add_rule(rule);
+        }
+
+        // Every Id came from this builder and there's at least one rule, so this can't fail.
+        Ok(builder.build().unwrap())
+    }
+
+    /// Writes the grammar out as BNF, with one line per non-terminating symbol and its
+    /// alternatives separated by `|`. The start symbol comes first, then the rest in the order
+    /// their [Id]s were made. Alternatives stay in the order they were added.
+    ///
+    /// Symbols are written with their [names](Grammar::symbol_name), or their [Id] if they don't
+    /// have one. Terminals are written with their description, or the name of their
+    /// [terminal class](GrammarBuilder::terminal_class), or `?` if they have neither.
+    ///
+    /// A grammar loaded with [Grammar::from_bnf] can be loaded again from this text. Other
+    /// grammars can too, as long as every symbol is named and every terminal's description is a
+    /// name in the [TerminalRegistry].
+    pub fn to_bnf(&self) -> String
+    {
+        let mut symbols: Vec<Id> = Vec::new();
+        for rule in self.rules()
+        {
+            if !symbols.contains(&rule.input_symbol())
+            {
+                symbols.push(rule.input_symbol());
+            }
+        }
+        symbols.sort_by_key(|x| (*x != self.start_symbol(), *x));
+
+        let mut text = String::new();
+        for symbol in symbols
+        {
+            let alternatives: Vec<String> = self.rules()
+                .filter(|x| x.input_symbol() == symbol)
+                .map(|x| self.bnf_alternative(x))
+                .collect();
+            text.push_str(&format!("{} ::= {}\n", self.bnf_name(symbol), alternatives.join(" | ")));
+        }
+        text
+    }
+
+    fn bnf_name(&self, symbol: Id) -> String
+    {
+        self.symbol_name(symbol).map_or_else(|| symbol.to_string(), String::from)
+    }
+
+    fn bnf_alternative(&self, rule: &Rule<'a, L>) -> String
+    {
+        let symbols: Vec<String> = rule.replacement_symbols()
+            .iter()
+            .enumerate()
+            .map(|(position, symbol)| match symbol
+            {
+                SymbolSchema::Nonterminating(id) => self.bnf_name(*id),
+                SymbolSchema::Terminating(recognizer) => rule.description(position)
+                    .or_else(|| self.terminal_classes()
+                        .iter()
+                        .find(|(_, x)| Terminal::new(*x) == Terminal::new(*recognizer))
+                        .map(|(name, _)| name.as_str()))
+                    .unwrap_or("?")
+                    .to_string(),
+            })
+            .collect();
+        symbols.join(" ")
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Positioned<BnfToken>>, BnfError>
+{
+    let mut tokens = Vec::new();
+    for (line_index, text_line) in text.lines().enumerate()
+    {
+        let mut chars = text_line.char_indices().enumerate().peekable();
+        while let Some((column_index, (byte_index, c))) = chars.next()
+        {
+            let (line, column) = (line_index + 1, column_index + 1);
+            let error = |kind| BnfError { line, column, kind };
+            let token = match c
+            {
+                '#' => break,
+                c if c.is_whitespace() => continue,
+                '|' => BnfToken::Alternative,
+                ':' if text_line[byte_index..].starts_with("::=") =>
+                {
+                    chars.nth(1);
+                    BnfToken::Definition
+                },
+                '"' =>
+                {
+                    let rest = &text_line[byte_index + 1..];
+                    let end = rest.find('"').ok_or(error(BnfErrorKind::UnterminatedLiteral))?;
+                    let literal = &rest[..end];
+                    // Skip the literal and its closing quote.
+                    for _ in 0..literal.chars().count() + 1
+                    {
+                        chars.next();
+                    }
+                    BnfToken::Literal(String::from(literal))
+                },
+                c if c.is_alphanumeric() || c == '_' =>
+                {
+                    let mut name = String::from(c);
+                    while let Some((_, (_, c))) = chars.next_if(|(_, (_, c))| c.is_alphanumeric() || *c == '_' || *c == '-')
+                    {
+                        name.push(c);
+                    }
+                    BnfToken::Name(name)
+                },
+                c => return Err(error(BnfErrorKind::UnexpectedCharacter(c))),
+            };
+            tokens.push((token, line, column));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Splits the tokens into one list of symbols per alternative, along with the rule's name.
+fn parse_rules(tokens: &[Positioned<BnfToken>]) -> Result<Vec<BnfRule>, BnfError>
+{
+    let mut rules = Vec::new();
+    let mut i = 0;
+    while i < tokens.len()
+    {
+        let (token, line, column) = &tokens[i];
+        let BnfToken::Name(lhs) = token else
+        {
+            return Err(BnfError { line: *line, column: *column, kind: BnfErrorKind::ExpectedRuleName });
+        };
+        match tokens.get(i + 1)
+        {
+            Some((BnfToken::Definition, _, _)) => (),
+            Some((_, line, column)) => return Err(BnfError { line: *line, column: *column, kind: BnfErrorKind::ExpectedDefinition }),
+            None => return Err(BnfError { line: *line, column: *column, kind: BnfErrorKind::ExpectedDefinition }),
+        }
+        i += 2;
+
+        let mut symbols = Vec::new();
+        loop
+        {
+            let starts_next_rule = matches!(
+                (tokens.get(i), tokens.get(i + 1)),
+                (Some((BnfToken::Name(_), _, _)), Some((BnfToken::Definition, _, _))));
+            match tokens.get(i)
+            {
+                None => break,
+                Some(_) if starts_next_rule => break,
+                Some((BnfToken::Alternative, _, _)) => rules.push((lhs.clone(), std::mem::take(&mut symbols))),
+                Some((BnfToken::Definition, line, column)) => return Err(BnfError { line: *line, column: *column, kind: BnfErrorKind::ExpectedRuleName }),
+                Some(symbol) => symbols.push(symbol.clone()),
+            }
+            i += 1;
+        }
+        rules.push((lhs.clone(), symbols));
+    }
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::grammar::export::to_sexpr;
+    use crate::grammar::ParseError;
+    use crate::lang::create_lexer;
+    use crate::lang::token::{Keyword, Symbol, Token};
+
+    use super::*;
+
+    fn is_print(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::Print)
+    }
+
+    fn is_end(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::End)
+    }
+
+    fn registry() -> TerminalRegistry<'static, Token>
+    {
+        TerminalRegistry::new()
+            .terminal("NUMBER", &Token::is_number)
+            .terminal("VARIABLE", &Token::is_variable)
+            .terminal("NEWLINE", &Token::is_newline)
+            .literals(|text| match text
+            {
+                "PRINT" => Some(&is_print),
+                "END" => Some(&is_end),
+                _ => None,
+            })
+    }
+
+    const BNF: &str = "\
+        # A very small piece of Tiny BASIC.\n\
+        line ::= NUMBER statement NEWLINE\n\
+               | statement NEWLINE   # No line number\n\
+        statement ::= \"PRINT\" value | \"END\"\n\
+        value ::= NUMBER | VARIABLE\n";
+
+    #[test]
+    fn test_from_bnf_parses_tokens()
+    {
+        let grammar = Grammar::from_bnf(BNF, &registry()).unwrap();
+        assert_eq!(grammar.rules().count(), 6);
+        assert_eq!(grammar.symbol_name(grammar.start_symbol()), Some("line"));
+
+        // Compiling uses up the grammar, so grab the names first.
+        let names: HashMap<Id, String> = grammar.rules()
+            .map(|x| (x.input_symbol(), String::from(grammar.symbol_name(x.input_symbol()).unwrap())))
+            .collect();
+        let compiled = grammar.compile().unwrap();
+        let parse = |source: &str|
+        {
+            let tokens: Vec<Token> = create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap();
+            compiled.parse(tokens).map(|tree| to_sexpr(&tree, |id| names[&id].clone()))
+        };
+
+        assert_eq!(parse("10 PRINT A\n").unwrap(), "(line 10 (statement PRINT (value A)) \n)");
+        assert_eq!(parse("END\n").unwrap(), "(line (statement END) \n)");
+        assert!(parse("10 END").is_err());
+    }
+
+    #[test]
+    fn test_from_bnf_error_positions()
+    {
+        let error = |text: &str| Grammar::from_bnf(text, &registry()).err().unwrap();
+
+        assert_eq!(error("line ::= NUMBER\nvalue ::= NUMBR"),
+            BnfError { line: 2, column: 11, kind: BnfErrorKind::UnknownSymbol(String::from("NUMBR")) });
+        assert_eq!(error("line ::= \"GOTO\""),
+            BnfError { line: 1, column: 10, kind: BnfErrorKind::UnknownLiteral(String::from("GOTO")) });
+        assert_eq!(error("line ::= \"PRINT"),
+            BnfError { line: 1, column: 10, kind: BnfErrorKind::UnterminatedLiteral });
+        assert_eq!(error("line NUMBER"),
+            BnfError { line: 1, column: 6, kind: BnfErrorKind::ExpectedDefinition });
+        assert_eq!(error("  # nothing here\n"),
+            BnfError { line: 1, column: 1, kind: BnfErrorKind::NoRules });
+        assert_eq!(error("line ::= NUMBER ; NUMBER").to_string(), "1:17: Unexpected character ';'");
+    }
+
+    #[test]
+    fn test_to_bnf_round_trips()
+    {
+        let grammar = Grammar::from_bnf(BNF, &registry()).unwrap();
+        let text = grammar.to_bnf();
+        assert_eq!(text, "\
+            line ::= NUMBER statement NEWLINE | statement NEWLINE\n\
+            statement ::= \"PRINT\" value | \"END\"\n\
+            value ::= NUMBER | VARIABLE\n");
+
+        assert_eq!(Grammar::from_bnf(&text, &registry()).unwrap().to_bnf(), text);
+    }
+
+    fn is_plus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Plus)
+    }
+
+    fn is_minus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Minus)
+    }
+
+    fn is_times(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Times)
+    }
+
+    fn is_divide(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Divide)
+    }
+
+    #[test]
+    fn test_to_bnf_tiny_basic_expressions()
+    {
+        // The rules are added out of order, so the start symbol has to be moved to the front.
+        let (builder, _) = crate::grammar! {
+            start expression;
+            factor ::= number | variable;
+            expression ::= expression plus term | expression minus term | [minus]? term;
+            term ::= term times factor | term divide factor | factor;
+
+            terminal number = Token::is_number;
+            terminal variable = Token::is_variable;
+            terminal plus = is_plus;
+            terminal minus = is_minus;
+            terminal times = is_times;
+            terminal divide = is_divide;
+        };
+        let text = builder.build().unwrap().to_bnf();
+        assert_eq!(text, "\
+            expression ::= expression plus term | expression minus term | minus term | term\n\
+            factor ::= number | variable\n\
+            term ::= term times factor | term divide factor | factor\n");
+
+        let registry = TerminalRegistry::new()
+            .terminal("number", &Token::is_number)
+            .terminal("variable", &Token::is_variable)
+            .terminal("plus", &is_plus)
+            .terminal("minus", &is_minus)
+            .terminal("times", &is_times)
+            .terminal("divide", &is_divide);
+        let grammar = Grammar::from_bnf(&text, &registry).unwrap();
+        assert_eq!(grammar.to_bnf(), text);
+        // The shift-reduce parser only reduces once per token, so a number stops at factor and
+        // never makes it up to expression.
+        let result = grammar.try_parse(create_lexer().parse_stream("1").map(|x| x.unwrap()));
+        assert!(matches!(result, Err(ParseError::WrongStart { expected, .. }) if expected == grammar.start_symbol()));
+        assert!(grammar.parse(create_lexer().parse_stream("1+2*A").map(|x| x.unwrap())).is_none());
+    }
+
+    #[test]
+    fn test_to_bnf_without_names()
+    {
+        let mut builder = GrammarBuilder::<Token>::new();
+        let s = builder.id();
+        builder.rule(s, |r| r.t(&is_plus).t_desc("a number", &Token::is_number));
+        let grammar = builder.build().unwrap();
+        assert_eq!(grammar.to_bnf(), format!("{} ::= ? a number\n", s));
+    }
+}
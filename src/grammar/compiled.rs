@@ -0,0 +1,516 @@
+//! Compiles a [Grammar] into [SLR(1)](https://en.wikipedia.org/wiki/Simple_LR_parser) parse
+//! tables, so parsing is a table lookup per token instead of matching every rule against the
+//! stack.
+//!
+//! Building the tables needs to know every kind of token ahead of time, so each terminating
+//! symbol used in a rule has to be registered with [GrammarBuilder::terminal_class](crate::grammar::GrammarBuilder::terminal_class).
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use thiserror::Error;
+
+use crate::grammar::FollowSymbol;
+use crate::grammar::Grammar;
+use crate::grammar::GrammarNodeData;
+use crate::grammar::GrammarTree;
+use crate::grammar::Id;
+use crate::grammar::ParseEngine;
+use crate::grammar::SymbolSchema;
+use crate::grammar::Terminal;
+use crate::grammar::TokenRecognizer;
+
+/// Why a [Grammar] couldn't be compiled into parse tables.
+///
+/// Rules are numbered in the order they were added to the [GrammarBuilder](crate::grammar::GrammarBuilder), starting from 0.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TableConflict
+{
+    /// After seeing `terminal`, the parser can't tell whether to shift it or reduce `rule`.
+    /// Accepting the input counts as shifting the end of the input.
+    #[error("Shift-reduce conflict on {terminal}: can't tell whether to shift or reduce rule {rule}")]
+    ShiftReduce
+    {
+        terminal: String,
+        rule: usize,
+    },
+    /// After seeing `terminal`, the parser could reduce either rule.
+    #[error("Reduce-reduce conflict on {terminal}: can't tell whether to reduce rule {first} or rule {second}")]
+    ReduceReduce
+    {
+        terminal: String,
+        first: usize,
+        second: usize,
+    },
+    /// The rule uses a recognizer that wasn't registered with [GrammarBuilder::terminal_class](crate::grammar::GrammarBuilder::terminal_class).
+    #[error("Rule {rule} uses a terminating symbol that isn't a registered terminal class")]
+    UnregisteredTerminal
+    {
+        rule: usize,
+    },
+}
+
+/// A column in the action table.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+enum Lookahead
+{
+    /// The index of a terminal class.
+    Class(usize),
+    EndOfInput,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Action
+{
+    /// Push the token and go to this state.
+    Shift(usize),
+    /// Replace the end of the stack with this rule's input symbol.
+    Reduce(usize),
+    /// The input matched the start symbol.
+    Accept,
+}
+
+/// A symbol in a rule, with terminals replaced by their class index.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum CompiledSymbol
+{
+    Terminal(usize),
+    Nonterminal(Id),
+}
+
+/// An LR(0) item: a rule index and how much of the rule we've seen so far.
+type Item = (usize, usize);
+
+/// A [Grammar] compiled into SLR(1) parse tables. Created with [Grammar::compile].
+pub struct CompiledGrammar<'a, L>
+{
+    terminal_classes: Vec<(String, TokenRecognizer<'a, L>)>,
+    /// The input symbol and number of replacement symbols of every rule.
+    rules: Vec<(Id, usize)>,
+    actions: Vec<HashMap<Lookahead, Action>>,
+    gotos: Vec<HashMap<Id, usize>>,
+}
+
+impl<'a, L> Grammar<'a, L>
+{
+    /// Builds SLR(1) parse tables for the grammar.
+    ///
+    /// Fails if the grammar is ambiguous (or just needs more lookahead than SLR(1) has), or if a
+    /// rule uses a recognizer that wasn't registered as a terminal class.
+    pub fn compile(self) -> Result<CompiledGrammar<'a, L>, TableConflict>
+    {
+        let terminal_classes = self.terminal_classes().to_vec();
+        let class_names: Vec<&str> = terminal_classes.iter().map(|(name, _)| name.as_str()).collect();
+        let lookahead_name = |lookahead: Lookahead| match lookahead
+        {
+            Lookahead::Class(index) => class_names[index].to_owned(),
+            Lookahead::EndOfInput => String::from("end of input"),
+        };
+        let class_of = |terminal: Terminal<'_, L>| terminal_classes
+            .iter()
+            .position(|(_, recognizer)| Terminal::new(*recognizer) == terminal);
+
+        // Swap the recognizers for their classes. The augmented start rule (S' -> S) goes at the
+        // end, so the grammar's own rules keep their indices.
+        let mut lhs: Vec<Id> = Vec::new();
+        let mut rhs: Vec<Vec<CompiledSymbol>> = Vec::new();
+        for (rule_index, rule) in self.rules().enumerate()
+        {
+            let mut symbols = Vec::new();
+            for symbol in rule.replacement_symbols()
+            {
+                symbols.push(match symbol
+                {
+                    SymbolSchema::Terminating(recognizer) => CompiledSymbol::Terminal(
+                        class_of(Terminal::new(*recognizer))
+                            .ok_or(TableConflict::UnregisteredTerminal { rule: rule_index })?),
+                    SymbolSchema::Nonterminating(id) => CompiledSymbol::Nonterminal(*id),
+                });
+            }
+            lhs.push(rule.input_symbol());
+            rhs.push(symbols);
+        }
+        let augmented_rule = rhs.len();
+        rhs.push(vec![CompiledSymbol::Nonterminal(self.start_symbol())]);
+
+        let mut rules_for: HashMap<Id, Vec<usize>> = HashMap::new();
+        for (rule_index, id) in lhs.iter().enumerate()
+        {
+            rules_for.entry(*id).or_default().push(rule_index);
+        }
+
+        let closure = |kernel: BTreeSet<Item>| -> BTreeSet<Item>
+        {
+            let mut items = kernel.clone();
+            let mut stack: Vec<Item> = kernel.into_iter().collect();
+            while let Some((rule, dot)) = stack.pop()
+            {
+                if let Some(CompiledSymbol::Nonterminal(id)) = rhs[rule].get(dot)
+                {
+                    for next_rule in rules_for.get(id).into_iter().flatten()
+                    {
+                        if items.insert((*next_rule, 0))
+                        {
+                            stack.push((*next_rule, 0));
+                        }
+                    }
+                }
+            }
+            items
+        };
+
+        // Build every item set the parser can be in, along with the transitions between them.
+        let mut states: Vec<BTreeSet<Item>> = vec![closure(BTreeSet::from([(augmented_rule, 0)]))];
+        let mut state_index: HashMap<BTreeSet<Item>, usize> = HashMap::from([(states[0].clone(), 0)]);
+        let mut actions: Vec<HashMap<Lookahead, Action>> = Vec::new();
+        let mut gotos: Vec<HashMap<Id, usize>> = Vec::new();
+
+        let mut state = 0;
+        while state < states.len()
+        {
+            // Group the items by the symbol after the dot, keeping them in order so the state
+            // numbers come out the same every time.
+            let mut transitions: Vec<(CompiledSymbol, BTreeSet<Item>)> = Vec::new();
+            for (rule, dot) in &states[state]
+            {
+                let Some(symbol) = rhs[*rule].get(*dot) else
+                {
+                    continue;
+                };
+                match transitions.iter_mut().find(|(x, _)| x == symbol)
+                {
+                    Some((_, kernel)) => { kernel.insert((*rule, dot + 1)); },
+                    None => transitions.push((*symbol, BTreeSet::from([(*rule, dot + 1)]))),
+                }
+            }
+
+            let mut state_actions = HashMap::new();
+            let mut state_gotos = HashMap::new();
+            for (symbol, kernel) in transitions
+            {
+                let target = closure(kernel);
+                let target = match state_index.get(&target)
+                {
+                    Some(index) => *index,
+                    None => {
+                        states.push(target.clone());
+                        state_index.insert(target, states.len() - 1);
+                        states.len() - 1
+                    },
+                };
+
+                match symbol
+                {
+                    CompiledSymbol::Terminal(class) => { state_actions.insert(Lookahead::Class(class), Action::Shift(target)); },
+                    CompiledSymbol::Nonterminal(id) => { state_gotos.insert(id, target); },
+                }
+            }
+
+            actions.push(state_actions);
+            gotos.push(state_gotos);
+            state += 1;
+        }
+
+        // Now fill in the reductions, using the FOLLOW sets to decide which tokens each rule can be
+        // reduced on.
+        let follow_sets = self.compute_follow_sets();
+        for (state, items) in states.iter().enumerate()
+        {
+            for (rule, dot) in items
+            {
+                if *dot != rhs[*rule].len()
+                {
+                    continue;
+                }
+
+                if *rule == augmented_rule
+                {
+                    set_action(&mut actions[state], Lookahead::EndOfInput, Action::Accept, &lookahead_name)?;
+                    continue;
+                }
+
+                let mut lookaheads: Vec<Lookahead> = Vec::new();
+                for follow in follow_sets.get(&lhs[*rule]).into_iter().flatten()
+                {
+                    match follow
+                    {
+                        FollowSymbol::EndOfInput => lookaheads.push(Lookahead::EndOfInput),
+                        // Every terminal in a rule has a class by now.
+                        FollowSymbol::Terminal(terminal) => lookaheads.push(Lookahead::Class(class_of(*terminal).unwrap())),
+                    }
+                }
+                // Sorted so the same conflict gets reported every time.
+                lookaheads.sort();
+
+                for lookahead in lookaheads
+                {
+                    set_action(&mut actions[state], lookahead, Action::Reduce(*rule), &lookahead_name)?;
+                }
+            }
+        }
+
+        Ok(CompiledGrammar
+        {
+            terminal_classes,
+            rules: lhs.into_iter().zip(rhs.iter().map(Vec::len)).collect(),
+            actions,
+            gotos,
+        })
+    }
+}
+
+/// Adds an action to the table, failing if there's already a different one there.
+fn set_action(
+    actions: &mut HashMap<Lookahead, Action>,
+    lookahead: Lookahead,
+    action: Action,
+    lookahead_name: &impl Fn(Lookahead) -> String) -> Result<(), TableConflict>
+{
+    let Some(existing) = actions.get(&lookahead) else
+    {
+        actions.insert(lookahead, action);
+        return Ok(());
+    };
+
+    match (*existing, action)
+    {
+        (existing, action) if existing == action => Ok(()),
+        (Action::Reduce(first), Action::Reduce(second)) => Err(TableConflict::ReduceReduce
+        {
+            terminal: lookahead_name(lookahead),
+            first,
+            second,
+        }),
+        (Action::Reduce(rule), _) | (_, Action::Reduce(rule)) => Err(TableConflict::ShiftReduce
+        {
+            terminal: lookahead_name(lookahead),
+            rule,
+        }),
+        // We only ever add reductions to a state that already has actions.
+        _ => unreachable!(),
+    }
+}
+
+impl<L> CompiledGrammar<'_, L>
+{
+    /// Parses the input, which has to match the grammar's start symbol exactly.
+    ///
+    /// Produces the same [GrammarTree] as the other [ParseEngine]s.
+    pub fn parse(&self, input: impl IntoIterator<Item = L>) -> anyhow::Result<GrammarTree<L>>
+    {
+        let mut input = input.into_iter();
+        let mut next_token = input.next();
+        let mut token_index = 0;
+        let mut states: Vec<usize> = vec![0];
+        let mut trees: Vec<GrammarTree<L>> = Vec::new();
+
+        loop
+        {
+            let lookahead = match &next_token
+            {
+                None => Lookahead::EndOfInput,
+                Some(token) => Lookahead::Class(self.classify(token)
+                    .ok_or_else(|| anyhow!("Token {} doesn't belong to any terminal class", token_index))?),
+            };
+
+            // There's always at least the starting state on the stack.
+            let state = *states.last().unwrap();
+            match self.actions[state].get(&lookahead)
+            {
+                None => match lookahead
+                {
+                    Lookahead::Class(class) => bail!("Unexpected {} at token {}", self.terminal_classes[class].0, token_index),
+                    Lookahead::EndOfInput => bail!("Unexpected end of input"),
+                },
+                Some(Action::Shift(target)) => {
+                    trees.push(GrammarTree::Leaf(next_token.take().unwrap()));
+                    states.push(*target);
+                    next_token = input.next();
+                    token_index += 1;
+                },
+                Some(Action::Reduce(rule)) => {
+                    let (symbol, len) = self.rules[*rule];
+                    let children = trees.split_off(trees.len() - len)
+                        .into_iter()
+                        .map(Box::new)
+                        .collect();
+                    states.truncate(states.len() - len);
+                    trees.push(GrammarTree::Node(GrammarNodeData { symbol, children }));
+
+                    let state = *states.last().unwrap();
+                    states.push(self.gotos[state][&symbol]);
+                },
+                Some(Action::Accept) => return Ok(trees.pop().unwrap()),
+            }
+        }
+    }
+
+    /// Finds the first terminal class that recognizes the token.
+    fn classify(&self, token: &L) -> Option<usize>
+    {
+        self.terminal_classes.iter().position(|(_, recognizer)| recognizer(token))
+    }
+}
+
+impl<L> ParseEngine for CompiledGrammar<'_, L>
+{
+    type Lang = L;
+
+    fn parse_input(&self, input: impl IntoIterator<Item = L>) -> anyhow::Result<GrammarTree<L>>
+    {
+        self.parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fmt::{self, Display};
+
+    use crate::grammar::export::to_sexpr;
+    use crate::grammar::{GrammarBuilder, Rule};
+
+    use super::*;
+
+    #[derive(Debug)]
+    enum MockLangToken
+    {
+        Number(usize),
+        Plus,
+    }
+
+    impl MockLangToken
+    {
+        fn is_number(&self) -> bool
+        {
+            matches!(self, Self::Number(_))
+        }
+
+        fn is_plus(&self) -> bool
+        {
+            matches!(self, Self::Plus)
+        }
+    }
+
+    impl Display for MockLangToken
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+        {
+            match self
+            {
+                Self::Number(number) => write!(f, "{}", number),
+                Self::Plus => write!(f, "+"),
+            }
+        }
+    }
+
+    type Recognizer<'a> = &'a (dyn Fn(&MockLangToken) -> bool + Sync);
+
+    #[test]
+    fn test_compiled_grammar_parses_left_recursion()
+    {
+        let is_number: Recognizer<'_> = &MockLangToken::is_number;
+        let is_plus: Recognizer<'_> = &MockLangToken::is_plus;
+
+        // E -> E + n
+        // E -> n
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let e = grammar_builder.id();
+        let grammar = grammar_builder
+            .terminal_class("number", is_number)
+            .terminal_class("plus", is_plus)
+            .add_rule(Rule::new(e)
+                .add_nonterminating_symbol(e)
+                .add_terminating_symbol(is_plus)
+                .add_terminating_symbol(is_number))
+            .add_rule(Rule::new(e).add_terminating_symbol(is_number))
+            .build()
+            .unwrap();
+
+        let compiled = grammar.compile().unwrap();
+        let tree = compiled.parse(vec![
+            MockLangToken::Number(1),
+            MockLangToken::Plus,
+            MockLangToken::Number(2),
+            MockLangToken::Plus,
+            MockLangToken::Number(3),
+        ]).unwrap();
+        assert_eq!(to_sexpr(&tree, |_| String::from("e")), "(e (e (e 1) + 2) + 3)");
+
+        assert!(compiled.parse(vec![MockLangToken::Number(1), MockLangToken::Plus]).is_err());
+        assert!(compiled.parse(vec![MockLangToken::Plus]).is_err());
+        assert!(compiled.parse(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_compile_reports_shift_reduce_conflict()
+    {
+        let is_number: Recognizer<'_> = &MockLangToken::is_number;
+        let is_plus: Recognizer<'_> = &MockLangToken::is_plus;
+
+        // E -> E + E
+        // E -> n
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let e = grammar_builder.id();
+        let grammar = grammar_builder
+            .terminal_class("number", is_number)
+            .terminal_class("plus", is_plus)
+            .add_rule(Rule::new(e)
+                .add_nonterminating_symbol(e)
+                .add_terminating_symbol(is_plus)
+                .add_nonterminating_symbol(e))
+            .add_rule(Rule::new(e).add_terminating_symbol(is_number))
+            .build()
+            .unwrap();
+
+        assert_eq!(grammar.compile().err(), Some(TableConflict::ShiftReduce
+        {
+            terminal: String::from("plus"),
+            rule: 0,
+        }));
+    }
+
+    #[test]
+    fn test_compile_reports_reduce_reduce_conflict()
+    {
+        let is_number: Recognizer<'_> = &MockLangToken::is_number;
+
+        // S -> A
+        // S -> B
+        // A -> n
+        // B -> n
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let s = grammar_builder.id();
+        let a = grammar_builder.id();
+        let b = grammar_builder.id();
+        let grammar = grammar_builder
+            .terminal_class("number", is_number)
+            .add_rule(Rule::new(s).add_nonterminating_symbol(a))
+            .add_rule(Rule::new(s).add_nonterminating_symbol(b))
+            .add_rule(Rule::new(a).add_terminating_symbol(is_number))
+            .add_rule(Rule::new(b).add_terminating_symbol(is_number))
+            .build()
+            .unwrap();
+
+        assert_eq!(grammar.compile().err(), Some(TableConflict::ReduceReduce
+        {
+            terminal: String::from("end of input"),
+            first: 2,
+            second: 3,
+        }));
+    }
+
+    #[test]
+    fn test_compile_requires_terminal_classes()
+    {
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let s = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(s).add_terminating_symbol(&MockLangToken::is_number))
+            .build()
+            .unwrap();
+
+        assert_eq!(grammar.compile().err(), Some(TableConflict::UnregisteredTerminal { rule: 0 }));
+    }
+}
@@ -0,0 +1,76 @@
+//! Errors produced while parsing with a [Grammar](crate::grammar::Grammar).
+
+use thiserror::Error;
+
+use crate::grammar::{GrammarCycle, GrammarTree, Id, Mismatch};
+
+/// Why a run of input couldn't be parsed into a single [GrammarTree].
+#[derive(Debug, Error)]
+pub enum ParseError<L>
+{
+    /// There weren't any tokens to parse.
+    #[error("Expected at least one token to parse")]
+    EmptyInput,
+    /// The input didn't reduce down to a single tree. Contains whatever was left on the stack.
+    ///
+    /// `closest` is the rule that came closest to matching part of the stack, if any.
+    #[error("Couldn't reduce the input to a single tree, {} trees were left on the stack", .stack.len())]
+    Unreduced { stack: Vec<GrammarTree<L>>, closest: Option<ClosestRule> },
+    /// The input reduced to a single tree, but of `found` instead of the grammar's start symbol.
+    #[error("Expected the input to reduce to {expected}, but it reduced to {found}")]
+    WrongStart { tree: GrammarTree<L>, expected: Id, found: Id },
+    /// The parse went over its [ParseLimits](crate::grammar::ParseLimits).
+    #[error("Parse went over its limits after {steps} steps with {stack_depth} trees on the stack")]
+    BudgetExceeded { steps: usize, stack_depth: usize },
+}
+
+/// The rule that matched the most symbols of a stuck parse stack before going wrong. See
+/// [Grammar::closest_rule](crate::grammar::Grammar::closest_rule).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ClosestRule
+{
+    /// The symbol the rule would have reduced to.
+    pub rule_symbol: Id,
+    /// Where on the stack the rule was lined up.
+    pub stack_position: usize,
+    /// What went wrong, relative to `stack_position`.
+    pub mismatch: Mismatch,
+}
+
+/// Errors from running a fallible token stream (like the lexer's output) straight into a parse.
+/// See [Grammar::parse_results](crate::grammar::Grammar::parse_results).
+#[derive(Debug, Error)]
+pub enum PipelineError<E, L>
+{
+    /// The token stream produced an error at `token_index` instead of a token.
+    #[error("Error reading token {token_index}: {error}")]
+    Lex { token_index: usize, error: E },
+    /// Every token was read, but the parse failed.
+    #[error(transparent)]
+    Parse(#[from] ParseError<L>),
+}
+
+/// Why a [GrammarBuilder](crate::grammar::GrammarBuilder) couldn't build a grammar.
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+pub enum GrammarBuildError
+{
+    #[error("A grammar needs at least one rule")]
+    NoRules,
+    /// A rule used an [Id] from some other builder. It would never match anything, so this is
+    /// always a mistake. `rule_index` counts every rule added, starting at 0.
+    #[error("Rule {rule_index} uses {id}, which came from a different GrammarBuilder (generator {})", .id.generator())]
+    ForeignId { id: Id, rule_index: usize },
+    /// The symbol passed to [GrammarBuilder::start](crate::grammar::GrammarBuilder::start) doesn't
+    /// have any rules.
+    #[error("The start symbol {0} doesn't have any rules")]
+    UnknownStartSymbol(Id),
+    /// A [GrammarSpec](crate::grammar::GrammarSpec) used a name that isn't a terminal and
+    /// doesn't have any rules.
+    #[error("{0} isn't a terminal and doesn't have any rules")]
+    UnknownSymbol(&'static str),
+    /// The grammar has a cycle, and [GrammarBuilder::deny_cycles](crate::grammar::GrammarBuilder::deny_cycles)
+    /// was used. Only the first cycle [Grammar::analyze](crate::grammar::Grammar::analyze) found is
+    /// reported.
+    #[error(transparent)]
+    Cycle(GrammarCycle),
+}
@@ -0,0 +1,326 @@
+//! Converts [GrammarTree]s into stable textual forms, so parse trees can be diffed or handed off
+//! to external tools.
+//!
+//! [to_sexpr] names nodes with a `name_fn` that maps each symbol [Id] to a name, and writes leaves
+//! with their [Display] implementation. It walks the tree with an explicit stack instead of
+//! recursion, so deep trees can't overflow the call stack.
+//!
+//! With the `serde` feature, trees also implement `Serialize`. Wrap one in `WithNames` to write
+//! symbol names instead of numbers. Either way the output only depends on the tree and the
+//! names, so the same tree always produces the same text.
+
+#[cfg(feature = "serde")]
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+#[cfg(feature = "serde")]
+use crate::grammar::GrammarNodeData;
+use crate::grammar::GrammarTree;
+use crate::grammar::Id;
+
+/// A piece of work left to do while writing out a tree.
+enum ExportStep<'a, L>
+{
+    /// Write out this subtree.
+    Tree(&'a GrammarTree<L>),
+    /// Close the node we opened earlier.
+    Close,
+}
+
+/// Writes the tree as an S-expression, like `(expression (term (factor 42)))`.
+///
+/// Each node becomes a list starting with its name, followed by its children.
+pub fn to_sexpr<L>(tree: &GrammarTree<L>, name_fn: impl Fn(Id) -> String) -> String
+    where L: Display
+{
+    to_sexpr_with(tree, |x| x.to_string(), name_fn)
+}
+
+/// Same as [to_sexpr], but leaves are written with `leaf_fn` instead of needing [Display].
+pub fn to_sexpr_with<L>(tree: &GrammarTree<L>, leaf_fn: impl Fn(&L) -> String, name_fn: impl Fn(Id) -> String) -> String
+{
+    let mut output = String::new();
+    let mut steps = vec![ExportStep::Tree(tree)];
+    // Siblings are separated by a space, but the first thing in a list isn't.
+    let mut first_in_list = true;
+
+    while let Some(step) = steps.pop()
+    {
+        match step
+        {
+            ExportStep::Close => {
+                output.push(')');
+                first_in_list = false;
+            },
+            ExportStep::Tree(tree) => {
+                if !first_in_list
+                {
+                    output.push(' ');
+                }
+
+                match tree
+                {
+                    GrammarTree::Leaf(leaf) => {
+                        output.push_str(&leaf_fn(leaf));
+                        first_in_list = false;
+                    },
+                    GrammarTree::Node(node) => {
+                        output.push('(');
+                        output.push_str(&name_fn(node.symbol));
+                        first_in_list = false;
+                        steps.push(ExportStep::Close);
+                        // Pushed in reverse so the first child gets written first.
+                        steps.extend(node.children.iter().rev().map(|x| ExportStep::Tree(x)));
+                    },
+                }
+            },
+        }
+    }
+
+    output
+}
+
+/// Serializes a [GrammarTree], or part of one, with each symbol written as its name from `names`.
+/// Symbols without a name are written as their [Id::index], the same as without the wrapper.
+///
+/// `names` is usually [Grammar::symbol_names](crate::grammar::Grammar::symbol_names).
+#[cfg(feature = "serde")]
+pub struct WithNames<'a, T>
+{
+    pub value: &'a T,
+    pub names: &'a HashMap<Id, String>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, T> WithNames<'a, T>
+{
+    pub fn new(value: &'a T, names: &'a HashMap<Id, String>) -> Self
+    {
+        Self { value, names }
+    }
+
+    /// The same names, wrapped around something else.
+    fn wrap<U>(&self, value: &'a U) -> WithNames<'a, U>
+    {
+        WithNames { value, names: self.names }
+    }
+}
+
+/// Leaves are written as themselves, and nodes as `{"symbol":3,"children":[...]}`.
+#[cfg(feature = "serde")]
+impl<L: Serialize> Serialize for GrammarTree<L>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        WithNames::new(self, &HashMap::new()).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<L: Serialize> Serialize for GrammarNodeData<L>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        WithNames::new(self, &HashMap::new()).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<L: Serialize> Serialize for WithNames<'_, GrammarTree<L>>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        match self.value
+        {
+            GrammarTree::Leaf(leaf) => leaf.serialize(serializer),
+            GrammarTree::Node(node) => self.wrap(node).serialize(serializer),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<L: Serialize> Serialize for WithNames<'_, GrammarNodeData<L>>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        let mut node = serializer.serialize_struct("GrammarNodeData", 2)?;
+        node.serialize_field("symbol", &self.wrap(&self.value.symbol))?;
+        node.serialize_field("children", &self.wrap(&self.value.children))?;
+        node.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<L: Serialize> Serialize for WithNames<'_, Vec<Box<GrammarTree<L>>>>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        serializer.collect_seq(self.value.iter().map(|x| self.wrap(&**x)))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for WithNames<'_, Id>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        match self.names.get(self.value)
+        {
+            Some(name) => serializer.serialize_str(name),
+            None => serializer.serialize_u64(self.value.index() as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fmt;
+
+    #[cfg(feature = "serde")]
+    use serde::Serialize;
+
+    use crate::grammar::{GrammarBuilder, GrammarNodeData, Rule};
+
+    use super::*;
+
+    #[derive(Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
+    enum MockLangToken
+    {
+        Number(usize),
+        Plus,
+    }
+
+    impl MockLangToken
+    {
+        fn is_number(&self) -> bool
+        {
+            matches!(self, Self::Number(_))
+        }
+
+        fn is_plus(&self) -> bool
+        {
+            matches!(self, Self::Plus)
+        }
+    }
+
+    impl Display for MockLangToken
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+        {
+            match self
+            {
+                Self::Number(number) => write!(f, "{}", number),
+                Self::Plus => write!(f, "+"),
+            }
+        }
+    }
+
+    fn node<L>(symbol: Id, children: Vec<GrammarTree<L>>) -> GrammarTree<L>
+    {
+        GrammarTree::Node(GrammarNodeData
+        {
+            symbol,
+            children: children.into_iter().map(Box::new).collect(),
+        })
+    }
+
+    #[test]
+    fn test_export_parsed_tree()
+    {
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let sum = grammar_builder.id();
+        let rule = Rule::new(sum)
+            .add_terminating_symbol(&MockLangToken::is_number)
+            .add_terminating_symbol(&MockLangToken::is_plus)
+            .add_terminating_symbol(&MockLangToken::is_number);
+        let grammar = grammar_builder.add_rule(rule).build().unwrap();
+
+        let tree = grammar.parse(vec![
+            MockLangToken::Number(1),
+            MockLangToken::Plus,
+            MockLangToken::Number(2),
+        ]).unwrap();
+
+        let name_fn = |_| String::from("sum");
+        assert_eq!(to_sexpr(&tree, name_fn), "(sum 1 + 2)");
+    }
+
+    #[test]
+    fn test_export_nested_tree()
+    {
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let expression = grammar_builder.id();
+        let term = grammar_builder.id();
+        let factor = grammar_builder.id();
+        let name_fn = |id| String::from(match id
+        {
+            x if x == expression => "expression",
+            x if x == term => "term",
+            x if x == factor => "factor",
+            _ => unreachable!(),
+        });
+
+        let tree = node(expression, vec![
+            node(term, vec![
+                node(factor, vec![GrammarTree::Leaf(MockLangToken::Number(42))]),
+            ]),
+            GrammarTree::Leaf(MockLangToken::Plus),
+            node(term, vec![]),
+        ]);
+
+        assert_eq!(to_sexpr(&tree, name_fn), "(expression (term (factor 42)) + (term))");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_parsed_tree()
+    {
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let expression = grammar_builder.id();
+        let sum = grammar_builder.id();
+        grammar_builder
+            .rule(sum, |r| r.t(&MockLangToken::is_number).t(&MockLangToken::is_plus).t(&MockLangToken::is_number))
+            .rule(expression, |r| r.nt(sum).t(&MockLangToken::is_plus).t(&MockLangToken::is_number))
+            .start(expression)
+            .name(expression, "expression");
+        let grammar = grammar_builder.build().unwrap();
+
+        let tree = grammar.parse(vec![
+            MockLangToken::Number(1),
+            MockLangToken::Plus,
+            MockLangToken::Number(2),
+            MockLangToken::Plus,
+            MockLangToken::Number(3),
+        ]).unwrap();
+
+        // Without names every symbol is a number. `sum` doesn't have a name, so it stays one.
+        assert_eq!(
+            serde_json::to_string(&tree).unwrap(),
+            concat!(
+                r#"{"symbol":0,"children":["#,
+                r#"{"symbol":1,"children":[{"Number":1},"Plus",{"Number":2}]},"#,
+                r#""Plus",{"Number":3}]}"#,
+            )
+        );
+        assert_eq!(
+            serde_json::to_string(&WithNames::new(&tree, grammar.symbol_names())).unwrap(),
+            concat!(
+                r#"{"symbol":"expression","children":["#,
+                r#"{"symbol":1,"children":[{"Number":1},"Plus",{"Number":2}]},"#,
+                r#""Plus",{"Number":3}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_export_leaf()
+    {
+        let tree = GrammarTree::Leaf("say \"hi\"\n");
+        assert_eq!(to_sexpr(&tree, |_| String::new()), "say \"hi\"\n");
+    }
+}
@@ -0,0 +1,317 @@
+//! Generates random sentences from a [Grammar], so the parsers can be tested against input that's
+//! valid by construction.
+
+use std::collections::HashMap;
+
+use crate::grammar::Grammar;
+use crate::grammar::Id;
+use crate::grammar::Rule;
+use crate::grammar::SymbolSchema;
+use crate::grammar::Terminal;
+
+/// How far [Grammar::generate] is allowed to go.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct GenLimits
+{
+    /// Once the derivation gets this deep, we only pick the alternatives that finish the quickest.
+    /// Sentences still go a little deeper than this while they wrap up.
+    pub max_depth: usize,
+}
+
+impl Default for GenLimits
+{
+    fn default() -> Self
+    {
+        Self { max_depth: 8 }
+    }
+}
+
+/// A terminating symbol that [Grammar::generate] needs a token for.
+pub struct TerminalDescription<'g, 'a, L>
+{
+    terminal: Terminal<'a, L>,
+    description: Option<&'g str>,
+    class: Option<&'g str>,
+}
+
+impl<'g, 'a, L> TerminalDescription<'g, 'a, L>
+{
+    /// The recognizer the token has to match.
+    pub fn terminal(&self) -> Terminal<'a, L>
+    {
+        self.terminal
+    }
+
+    /// The description the symbol was given with
+    /// [Rule::add_terminating_symbol_described], if it has one.
+    pub fn description(&self) -> Option<&'g str>
+    {
+        self.description
+    }
+
+    /// The name of the [terminal class](crate::grammar::GrammarBuilder::terminal_class) the
+    /// recognizer was registered under, if it was registered.
+    pub fn class(&self) -> Option<&'g str>
+    {
+        self.class
+    }
+}
+
+impl<'a, L> Grammar<'a, L>
+{
+    /// Generates a random sentence of the grammar, starting from the
+    /// [start symbol](Grammar::start_symbol). `materialize` turns each terminating symbol into an
+    /// actual token.
+    ///
+    /// Alternatives are picked with `rng`, which should return a new random number every call
+    /// (something like `|| rng.next_u32()`). Alternatives that can never finish are never picked.
+    ///
+    /// Returns [None] if the start symbol can't produce a finite sentence at all.
+    pub fn generate(
+        &self,
+        rng: &mut impl FnMut() -> u32,
+        limits: GenLimits,
+        materialize: impl Fn(&TerminalDescription<'_, 'a, L>) -> L) -> Option<Vec<L>>
+    {
+        let heights = self.derivation_heights();
+        let mut sentence = Vec::new();
+
+        // Each frame is a rule we're partway through, the position of the next symbol in it and
+        // how deep the rule is. Using a stack instead of recursion keeps deep sentences from
+        // overflowing the call stack.
+        let start = self.pick_rule(self.start_symbol(), 0, limits, &heights, rng)?;
+        let mut frames: Vec<(&Rule<'a, L>, usize, usize)> = vec![(start, 0, 0)];
+
+        while let Some((rule, position, depth)) = frames.pop()
+        {
+            let Some(symbol) = rule.replacement_symbols().get(position)
+            else
+            {
+                continue;
+            };
+            frames.push((rule, position + 1, depth));
+
+            match symbol
+            {
+                SymbolSchema::Terminating(recognizer) => {
+                    let terminal = Terminal::new(*recognizer);
+                    let class = self.terminal_classes()
+                        .iter()
+                        .find(|(_, x)| Terminal::new(*x) == terminal)
+                        .map(|(name, _)| name.as_str());
+                    sentence.push(materialize(&TerminalDescription
+                    {
+                        terminal,
+                        description: rule.description(position),
+                        class,
+                    }));
+                },
+                SymbolSchema::Nonterminating(id) => {
+                    // Every symbol in a rule we picked can finish, so this always finds one.
+                    let next = self.pick_rule(*id, depth + 1, limits, &heights, rng)?;
+                    frames.push((next, 0, depth + 1));
+                },
+            }
+        }
+
+        Some(sentence)
+    }
+
+    /// Picks a random rule for `symbol` out of the ones that can finish. Past the depth limit we
+    /// only pick from the ones that finish the quickest.
+    fn pick_rule(
+        &self,
+        symbol: Id,
+        depth: usize,
+        limits: GenLimits,
+        heights: &HashMap<Id, usize>,
+        rng: &mut impl FnMut() -> u32) -> Option<&Rule<'a, L>>
+    {
+        let mut candidates: Vec<(&Rule<'a, L>, usize)> = self.rules()
+            .filter(|x| x.input_symbol() == symbol)
+            .filter_map(|x| Some((x, rule_height(x, heights)?)))
+            .collect();
+
+        if depth >= limits.max_depth
+        {
+            let lowest = candidates.iter().map(|(_, height)| *height).min()?;
+            candidates.retain(|(_, height)| *height == lowest);
+        }
+
+        if candidates.is_empty()
+        {
+            return None;
+        }
+        Some(candidates[rng() as usize % candidates.len()].0)
+    }
+
+    /// The height of the shortest derivation tree for every symbol that can produce a finite
+    /// sentence. Symbols that can't are left out.
+    fn derivation_heights(&self) -> HashMap<Id, usize>
+    {
+        let mut heights: HashMap<Id, usize> = HashMap::new();
+        let mut changed = true;
+        while changed
+        {
+            changed = false;
+            for rule in self.rules()
+            {
+                let Some(height) = rule_height(rule, &heights)
+                else
+                {
+                    continue;
+                };
+
+                let current = heights.entry(rule.input_symbol()).or_insert(usize::MAX);
+                if height < *current
+                {
+                    *current = height;
+                    changed = true;
+                }
+            }
+        }
+        heights
+    }
+}
+
+/// The height of the shortest derivation tree that starts with `rule`, or [None] if one of its
+/// symbols doesn't have a height yet.
+fn rule_height<L>(rule: &Rule<'_, L>, heights: &HashMap<Id, usize>) -> Option<usize>
+{
+    let mut height = 1;
+    for symbol in rule.replacement_symbols()
+    {
+        if let SymbolSchema::Nonterminating(id) = symbol
+        {
+            height = height.max(heights.get(id)? + 1);
+        }
+    }
+    Some(height)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::Rng;
+
+    use crate::grammar::{GrammarBuilder, GrammarTree};
+    use crate::lang::token::{Symbol, Token};
+    use crate::test_rng::test_rng;
+
+    use super::*;
+
+    fn is_plus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Plus)
+    }
+
+    fn is_minus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Minus)
+    }
+
+    /// Random numbers for [Grammar::generate], the same every run.
+    fn rng(seed: u64) -> impl FnMut() -> u32
+    {
+        let mut rng = test_rng(seed);
+        move || rng.random()
+    }
+
+    fn materialize(terminal: &TerminalDescription<'_, '_, Token>) -> Token
+    {
+        match terminal.class()
+        {
+            Some("number") => Token::Number(7),
+            Some("plus") => Token::Symbol(Symbol::Plus),
+            Some("minus") => Token::Symbol(Symbol::Minus),
+            x => panic!("unexpected terminal class {:?}", x),
+        }
+    }
+
+    fn count_leaves(tree: GrammarTree<Token>) -> usize
+    {
+        let mut leaves = 0;
+        tree.map(|_| leaves += 1);
+        leaves
+    }
+
+    #[test]
+    fn test_generated_sentences_parse()
+    {
+        // Terminals are compared by identity, so each recognizer has to be the same reference
+        // everywhere it's used.
+        let number: &(dyn Fn(&Token) -> bool + Sync) = &Token::is_number;
+        let plus: &(dyn Fn(&Token) -> bool + Sync) = &is_plus;
+        let minus: &(dyn Fn(&Token) -> bool + Sync) = &is_minus;
+
+        // E -> E + n | E - n | n
+        let mut builder = GrammarBuilder::<Token>::new();
+        let e = builder.id();
+        builder
+            .rule(e, |r| r.nt(e).t(plus).t(number))
+            .rule(e, |r| r.nt(e).t(minus).t(number))
+            .rule(e, |r| r.t(number));
+        let grammar = builder
+            .terminal_class("number", number)
+            .terminal_class("plus", plus)
+            .terminal_class("minus", minus)
+            .build()
+            .unwrap();
+
+        let mut numbers = rng(1);
+        let limits = GenLimits { max_depth: 3 };
+        let mut longest = 0;
+        for _ in 0..100
+        {
+            let sentence = grammar.generate(&mut numbers, limits, materialize).unwrap();
+            let len = sentence.len();
+            longest = longest.max(len);
+
+            let tree = grammar.parse(sentence).unwrap();
+            let GrammarTree::Node(node) = &tree else { panic!("expected a node") };
+            assert_eq!(node.symbol(), e);
+            // The whole sentence ended up in one tree.
+            assert_eq!(count_leaves(tree), len);
+        }
+
+        // Three levels of E -> E + n, then E -> n once we hit the limit.
+        assert_eq!(longest, 7);
+    }
+
+    #[test]
+    fn test_generate_uses_descriptions()
+    {
+        let mut builder = GrammarBuilder::<Token>::new();
+        let s = builder.id();
+        builder.rule(s, |r| r.t_desc("a number", &Token::is_number).t(&is_plus));
+        let grammar = builder.build().unwrap();
+
+        let sentence = grammar.generate(&mut rng(2), GenLimits::default(), |terminal|
+        {
+            assert_eq!(terminal.class(), None);
+            if terminal.terminal().matches(&Token::Number(1))
+            {
+                assert_eq!(terminal.description(), Some("a number"));
+                Token::Number(1)
+            }
+            else
+            {
+                assert_eq!(terminal.description(), None);
+                Token::Symbol(Symbol::Plus)
+            }
+        });
+        assert_eq!(sentence.unwrap(), vec![Token::Number(1), Token::Symbol(Symbol::Plus)]);
+    }
+
+    #[test]
+    fn test_generate_never_finishing_grammar()
+    {
+        // S -> S + has no finite sentences.
+        let mut builder = GrammarBuilder::<Token>::new();
+        let s = builder.id();
+        builder.rule(s, |r| r.nt(s).t(&is_plus));
+        let grammar = builder.build().unwrap();
+
+        assert!(grammar.generate(&mut rng(3), GenLimits::default(), |_| Token::NewLine).is_none());
+    }
+}
@@ -2,16 +2,46 @@
 //! For any arbitrary language and converting it into a generic [GrammarTree] structure that 
 //! can be converted into whatever your language IR is (AST or whatever).
 
+pub mod export;
+mod analyze;
+mod bnf;
+mod compiled;
+mod error;
+mod generate;
 mod rule;
 mod grammar;
+pub mod parallel;
+mod recursive_descent;
+mod sets;
+mod spec;
+mod trace;
+mod tree_shape;
+
+use std::convert::Infallible;
 
 use getset::CopyGetters;
-use getset::Getters;
 pub use rule::*;
 pub use grammar::Grammar;
 pub use grammar::GrammarBuilder;
+pub use grammar::DelimitedParse;
+pub use grammar::DelimiterMode;
+pub use grammar::ParseLimits;
+pub use analyze::*;
+pub use bnf::*;
+pub use compiled::*;
+pub use error::*;
+pub use generate::*;
+pub use recursive_descent::RecursiveDescentEngine;
+pub use sets::*;
+pub use spec::*;
+pub use trace::*;
+pub use tree_shape::*;
 
 /// An abstract tree representing the results from parsing a number of [Rule]s.
+///
+/// This is the only parse tree type in the crate. [Grammar::parse] produces it and
+/// [ParseGrammarTree] implementations consume it, so there's nothing to convert in between.
+#[derive(Debug)]
 pub enum GrammarTree<L>
 {
     Leaf(L),
@@ -19,7 +49,7 @@ pub enum GrammarTree<L>
 }
 
 /// Data contained in a non-leaf [GrammarTree] node.
-#[derive(CopyGetters)]
+#[derive(Debug, CopyGetters)]
 pub struct GrammarNodeData<L>
 {
     /// The ID of the non-terminating symbol that makes up this rule.
@@ -37,10 +67,355 @@ impl<L> GrammarNodeData<L>
     }
 }
 
-pub trait ParseGrammarTree
+/// Work left to do while walking a [GrammarTree] in [GrammarTree::try_map].
+enum MapStep<L>
+{
+    /// Map this subtree.
+    Visit(GrammarTree<L>),
+    /// Collect the last `usize` mapped subtrees into a node with the given symbol.
+    Build(Id, usize),
+}
+
+impl<L> GrammarTree<L>
+{
+    /// Converts every leaf in the tree with `f`, keeping the shape of the tree and the symbol
+    /// [Id]s of every node.
+    ///
+    /// Leaves are visited in order, left to right.
+    pub fn map<M>(self, mut f: impl FnMut(L) -> M) -> GrammarTree<M>
+    {
+        match self.try_map(|x| Ok::<M, Infallible>(f(x)))
+        {
+            Ok(tree) => tree,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [GrammarTree::map], but `f` can fail. We stop at the first error and return it.
+    ///
+    /// This is done with an explicit stack instead of recursion so deep trees can't overflow the
+    /// call stack.
+    pub fn try_map<M, E>(self, mut f: impl FnMut(L) -> Result<M, E>) -> Result<GrammarTree<M>, E>
+    {
+        let mut steps = vec![MapStep::Visit(self)];
+        let mut output: Vec<GrammarTree<M>> = Vec::new();
+
+        while let Some(step) = steps.pop()
+        {
+            match step
+            {
+                MapStep::Visit(GrammarTree::Leaf(leaf)) => output.push(GrammarTree::Leaf(f(leaf)?)),
+                MapStep::Visit(GrammarTree::Node(node)) => {
+                    steps.push(MapStep::Build(node.symbol, node.children.len()));
+                    // Pushed in reverse so the first child gets popped (and mapped) first.
+                    steps.extend(node.children.into_iter().rev().map(|x| MapStep::Visit(*x)));
+                },
+                MapStep::Build(symbol, count) => {
+                    let children = output.split_off(output.len() - count)
+                        .into_iter()
+                        .map(Box::new)
+                        .collect();
+                    output.push(GrammarTree::Node(GrammarNodeData { symbol, children }));
+                },
+            }
+        }
+
+        // We always finish by building the root, so there's exactly one tree left.
+        Ok(output.pop().unwrap())
+    }
+
+    /// Writes the tree on a single line as an S-expression, like `(sum 1 + 2)`. Leaves are
+    /// written with `leaf_fmt` and nodes are named with `node_fmt`.
+    ///
+    /// Handy for `assert_eq!` in tests. See [export] for other formats.
+    pub fn to_sexp(&self, leaf_fmt: impl Fn(&L) -> String, node_fmt: impl Fn(Id) -> String) -> String
+    {
+        export::to_sexpr_with(self, leaf_fmt, node_fmt)
+    }
+}
+
+/// Two trees are equal if they have the same shape, the same symbol on every node and equal
+/// leaves. Like [GrammarTree::try_map], this uses an explicit stack so deep trees are fine.
+impl<L: PartialEq> PartialEq for GrammarTree<L>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        let mut to_compare = vec![(self, other)];
+        while let Some(pair) = to_compare.pop()
+        {
+            match pair
+            {
+                (Self::Leaf(a), Self::Leaf(b)) if a == b => (),
+                (Self::Node(a), Self::Node(b)) if a.symbol == b.symbol && a.children.len() == b.children.len() => {
+                    to_compare.extend(a.children.iter().map(Box::as_ref).zip(b.children.iter().map(Box::as_ref)));
+                },
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<L: Eq> Eq for GrammarTree<L> {}
+
+/// Something that can sit on the shift-reduce parse stack. [Grammar::parse] keeps whole
+/// [GrammarTree]s on it, but [Grammar::recognize] only needs to know which symbol each entry is.
+pub(crate) trait StackEntry<L>: Sized
+{
+    /// What gets shifted onto the stack. Usually `L`, but [Grammar::parse_ref] shifts `&L`.
+    type Input;
+
+    fn leaf(token: Self::Input) -> Self;
+    /// Combines `children` into a single entry for `symbol`.
+    fn node(symbol: Id, children: impl Iterator<Item = Self>) -> Self;
+    /// The token, if this entry is a leaf.
+    fn token(&self) -> Option<&L>;
+    /// The non-terminating symbol, if this entry isn't a leaf.
+    fn symbol(&self) -> Option<Id>;
+}
+
+impl<L> StackEntry<L> for GrammarTree<L>
+{
+    type Input = L;
+
+    fn leaf(token: L) -> Self
+    {
+        Self::Leaf(token)
+    }
+
+    fn node(symbol: Id, children: impl Iterator<Item = Self>) -> Self
+    {
+        Self::Node(GrammarNodeData { symbol, children: children.map(Box::new).collect() })
+    }
+
+    fn token(&self) -> Option<&L>
+    {
+        match self
+        {
+            Self::Leaf(token) => Some(token),
+            Self::Node(_) => None,
+        }
+    }
+
+    fn symbol(&self) -> Option<Id>
+    {
+        match self
+        {
+            Self::Leaf(_) => None,
+            Self::Node(node) => Some(node.symbol),
+        }
+    }
+}
+
+/// Lets a tree of borrowed tokens be matched against rules written for owned ones.
+impl<'t, L> StackEntry<L> for GrammarTree<&'t L>
+{
+    type Input = &'t L;
+
+    fn leaf(token: &'t L) -> Self
+    {
+        Self::Leaf(token)
+    }
+
+    fn node(symbol: Id, children: impl Iterator<Item = Self>) -> Self
+    {
+        Self::Node(GrammarNodeData { symbol, children: children.map(Box::new).collect() })
+    }
+
+    fn token(&self) -> Option<&L>
+    {
+        match self
+        {
+            Self::Leaf(token) => Some(*token),
+            Self::Node(_) => None,
+        }
+    }
+
+    fn symbol(&self) -> Option<Id>
+    {
+        match self
+        {
+            Self::Leaf(_) => None,
+            Self::Node(node) => Some(node.symbol),
+        }
+    }
+}
+
+/// Something that can parse a stream of tokens into a [GrammarTree], like a [Grammar].
+pub trait ParseEngine
 {
     type Lang;
 
-    fn parse(from: GrammarTree<Self::Lang>) -> anyhow::Result<Self>
-        where Self: Sized;
+    fn parse_input(&self, input: impl IntoIterator<Item = Self::Lang>) -> anyhow::Result<GrammarTree<Self::Lang>>;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::Arc;
+
+    use crate::lang::token::{Keyword, Token};
+
+    use super::*;
+
+    fn label(token: Token) -> &'static str
+    {
+        match token
+        {
+            Token::Keyword(_) => "keyword",
+            Token::Variable(_) => "variable",
+            Token::StringVariable(_) => "string variable",
+            Token::Number(_) => "number",
+            Token::String(_) => "string",
+            Token::Symbol(_) => "symbol",
+            Token::NewLine => "newline",
+        }
+    }
+
+    fn node<L>(symbol: Id, children: Vec<GrammarTree<L>>) -> GrammarTree<L>
+    {
+        GrammarTree::Node(GrammarNodeData
+        {
+            symbol,
+            children: children.into_iter().map(Box::new).collect(),
+        })
+    }
+
+    /// Renders the tree as nested lists of labels, so we can check shape and order at once.
+    fn render(tree: GrammarTree<&'static str>, ids: &[Id]) -> String
+    {
+        match tree
+        {
+            GrammarTree::Leaf(label) => label.to_owned(),
+            GrammarTree::Node(node) => {
+                let index = ids.iter().position(|x| *x == node.symbol()).unwrap();
+                let children: Vec<String> = node.children()
+                    .into_iter()
+                    .map(|x| render(*x, ids))
+                    .collect();
+                format!("({} {})", index, children.join(" "))
+            },
+        }
+    }
+
+    #[test]
+    fn test_tree_equality()
+    {
+        let mut id_generator = IdGenerator::new();
+        let line = id_generator.id();
+        let statement = id_generator.id();
+        let tree = || node(line, vec![
+            GrammarTree::Leaf(Token::Number(10)),
+            node(statement, vec![GrammarTree::Leaf(Token::Keyword(Keyword::Return))]),
+        ]);
+
+        assert_eq!(tree(), tree());
+
+        let other_symbol = node(line, vec![
+            GrammarTree::Leaf(Token::Number(10)),
+            node(line, vec![GrammarTree::Leaf(Token::Keyword(Keyword::Return))]),
+        ]);
+        assert_ne!(tree(), other_symbol);
+
+        let other_leaf = node(line, vec![
+            GrammarTree::Leaf(Token::Number(20)),
+            node(statement, vec![GrammarTree::Leaf(Token::Keyword(Keyword::Return))]),
+        ]);
+        assert_ne!(tree(), other_leaf);
+
+        let extra_child = node(line, vec![
+            GrammarTree::Leaf(Token::Number(10)),
+            node(statement, vec![GrammarTree::Leaf(Token::Keyword(Keyword::Return))]),
+            GrammarTree::Leaf(Token::NewLine),
+        ]);
+        assert_ne!(tree(), extra_child);
+        assert_ne!(tree(), GrammarTree::Leaf(Token::Number(10)));
+    }
+
+    #[test]
+    fn test_map_preserves_shape_and_order()
+    {
+        let mut id_generator = IdGenerator::new();
+        let line = id_generator.id();
+        let statement = id_generator.id();
+
+        let tree = node(line, vec![
+            GrammarTree::Leaf(Token::Number(10)),
+            node(statement, vec![
+                GrammarTree::Leaf(Token::Keyword(Keyword::Print)),
+                GrammarTree::Leaf(Token::String(Arc::from("hi"))),
+            ]),
+            GrammarTree::Leaf(Token::NewLine),
+        ]);
+
+        let mapped = tree.map(label);
+        assert_eq!(render(mapped, &[line, statement]), "(0 number (1 keyword string) newline)");
+    }
+
+    #[test]
+    fn test_map_leaf()
+    {
+        let tree = GrammarTree::Leaf(Token::NewLine).map(label);
+        assert_eq!(render(tree, &[]), "newline");
+    }
+
+    #[test]
+    fn test_try_map_stops_at_first_error()
+    {
+        let mut id_generator = IdGenerator::new();
+        let line = id_generator.id();
+
+        let tree = node(line, vec![
+            GrammarTree::Leaf(Token::Number(10)),
+            GrammarTree::Leaf(Token::String(Arc::from("bad"))),
+            GrammarTree::Leaf(Token::String(Arc::from("worse"))),
+        ]);
+
+        let mut visited = 0;
+        let result = tree.try_map(|x| {
+            visited += 1;
+            match x
+            {
+                Token::String(s) => Err(s),
+                x => Ok(label(x)),
+            }
+        });
+
+        assert_eq!(result.err(), Some(Arc::from("bad")));
+        assert_eq!(visited, 2);
+    }
+
+    #[test]
+    fn test_to_sexp()
+    {
+        let mut id_generator = IdGenerator::new();
+        let line = id_generator.id();
+        let statement = id_generator.id();
+        let empty = id_generator.id();
+
+        let tree = node(line, vec![
+            GrammarTree::Leaf(Token::Number(10)),
+            node(statement, vec![
+                GrammarTree::Leaf(Token::Keyword(Keyword::Print)),
+                GrammarTree::Leaf(Token::String(Arc::from("hi"))),
+            ]),
+            node(empty, Vec::new()),
+            GrammarTree::Leaf(Token::NewLine),
+        ]);
+
+        let names = [(line, "line"), (statement, "statement"), (empty, "empty")];
+        let node_fmt = |id| names.iter().find(|(x, _)| *x == id).unwrap().1.to_owned();
+        let leaf_fmt = |token: &Token| match token
+        {
+            Token::Number(number) => number.to_string(),
+            Token::String(string) => format!("{:?}", string),
+            Token::NewLine => String::from("CR"),
+            x => format!("{:?}", x),
+        };
+
+        assert_eq!(
+            tree.to_sexp(leaf_fmt, node_fmt),
+            "(line 10 (statement Keyword(Print) \"hi\") (empty) CR)"
+        );
+    }
 }
@@ -0,0 +1,142 @@
+//! Parses lots of independent inputs at once, spread over a few threads.
+//!
+//! A [Grammar] only holds `Sync` recognizers, so it can be shared between threads without any
+//! locking. Each thread gets a run of the inputs and parses them one after another.
+
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::grammar::Grammar;
+use crate::grammar::GrammarTree;
+use crate::grammar::ParseError;
+
+/// Parses each line on its own with [Grammar::try_parse], using as many threads as there are
+/// CPUs. The results are in the same order as `lines`, and they're exactly what parsing the
+/// lines one at a time would give.
+pub fn parse_lines<L: Send>(grammar: &Grammar<'_, L>, lines: Vec<Vec<L>>) -> Vec<Result<GrammarTree<L>, ParseError<L>>>
+{
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    parse_lines_on(grammar, lines, threads)
+}
+
+/// Same as [parse_lines], but with `threads` threads. Zero threads is treated as one.
+pub fn parse_lines_on<L: Send>(grammar: &Grammar<'_, L>, lines: Vec<Vec<L>>, threads: usize) -> Vec<Result<GrammarTree<L>, ParseError<L>>>
+{
+    // Round up, so there's never more than `threads` chunks.
+    let chunk_size = lines.len().div_ceil(threads.max(1)).max(1);
+    let mut chunks: Vec<Vec<Vec<L>>> = Vec::new();
+    let mut lines = lines.into_iter().peekable();
+    while lines.peek().is_some()
+    {
+        chunks.push(lines.by_ref().take(chunk_size).collect());
+    }
+
+    thread::scope(|scope|
+    {
+        let handles: Vec<_> = chunks.into_iter()
+            .map(|chunk| scope.spawn(move ||
+            {
+                chunk.into_iter()
+                    .map(|line| grammar.try_parse(line))
+                    .collect::<Vec<_>>()
+            }))
+            .collect();
+
+        handles.into_iter()
+            // A panic in a recognizer is passed on to the caller, same as parsing on one thread.
+            .flat_map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::grammar::export::to_sexpr;
+    use crate::grammar::{CompiledGrammar, GrammarBuilder};
+    use crate::lang::token::{Keyword, Symbol, Token};
+
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_grammar_types_are_send_and_sync()
+    {
+        assert_send_sync::<Grammar<'static, Token>>();
+        assert_send_sync::<CompiledGrammar<'static, Token>>();
+        assert_send_sync::<GrammarTree<Token>>();
+        assert_send_sync::<ParseError<Token>>();
+    }
+
+    fn is_print(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::Print)
+    }
+
+    fn is_plus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Plus)
+    }
+
+    /// What a parse result looks like, so the results can be compared.
+    fn describe(result: &Result<GrammarTree<Token>, ParseError<Token>>) -> String
+    {
+        match result
+        {
+            Ok(tree) => to_sexpr(tree, |id| id.to_string()),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parallel_matches_sequential()
+    {
+        // line -> PRINT sum newline, sum -> sum + number | number
+        let mut builder = GrammarBuilder::<Token>::new();
+        let line = builder.id();
+        let sum = builder.id();
+        builder
+            .rule(line, |r| r.t(&is_print).nt(sum).t(&Token::is_newline))
+            .rule(sum, |r| r.nt(sum).t(&is_plus).t(&Token::is_number))
+            .rule(sum, |r| r.t(&Token::is_number));
+        let grammar = builder.build().unwrap();
+
+        // Every seventh line is missing its PRINT, so some of them fail.
+        let lines: Vec<Vec<Token>> = (0..1000)
+            .map(|i|
+            {
+                let mut tokens = Vec::new();
+                if i % 7 != 0
+                {
+                    tokens.push(Token::Keyword(Keyword::Print));
+                }
+                tokens.push(Token::Number(1));
+                for _ in 0..i % 5
+                {
+                    tokens.push(Token::Symbol(Symbol::Plus));
+                    tokens.push(Token::Number(i));
+                }
+                tokens.push(Token::NewLine);
+                tokens
+            })
+            .collect();
+
+        let sequential: Vec<String> = lines.iter()
+            .map(|line| describe(&grammar.try_parse(line.clone())))
+            .collect();
+        assert!(sequential.iter().any(|x| x.starts_with('(')));
+        assert!(sequential.iter().any(|x| !x.starts_with('(')));
+
+        for threads in [0, 1, 3, 8]
+        {
+            let parallel: Vec<String> = parse_lines_on(&grammar, lines.clone(), threads)
+                .iter()
+                .map(describe)
+                .collect();
+            assert_eq!(parallel, sequential, "with {} threads", threads);
+        }
+        assert_eq!(parse_lines(&grammar, lines).len(), 1000);
+        assert!(parse_lines(&grammar, Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+//! Defines the [RecursiveDescentEngine], a top-down alternative to the shift-reduce parser in
+//! [Grammar::parse].
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::grammar::Grammar;
+use crate::grammar::GrammarNodeData;
+use crate::grammar::GrammarTree;
+use crate::grammar::Id;
+use crate::grammar::ParseEngine;
+use crate::grammar::Rule;
+use crate::grammar::SymbolSchema;
+
+/// How deep we let rules nest before giving up, unless told otherwise.
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// A top-down parser built from the same [Rule]s as a [Grammar].
+///
+/// Starting from the [Grammar::start_symbol], we try each rule for a symbol in the order they were
+/// added, and take the first one that matches. If a rule fails partway through, we backtrack to
+/// where it started and try the next one. The tokens are buffered so we can go back over them.
+///
+/// Rules are tried in order and the first match wins, so put longer rules before rules that match
+/// a prefix of them.
+///
+/// Left recursive rules (`A -> A b`) would recurse forever, so they're rejected by
+/// [RecursiveDescentEngine::new].
+pub struct RecursiveDescentEngine<'g, L>
+{
+    /// The rules for each symbol, in the order they were added to the grammar.
+    alternatives: HashMap<Id, Vec<&'g Rule<'g, L>>>,
+    start_symbol: Id,
+    max_depth: usize,
+}
+
+/// Which rules matched which tokens, before we move the tokens into a [GrammarTree].
+enum Derivation
+{
+    /// The token at this index.
+    Leaf(usize),
+    Node(Id, Vec<Derivation>),
+}
+
+impl<'g, L> RecursiveDescentEngine<'g, L>
+{
+    /// Creates a new [RecursiveDescentEngine] for `grammar`.
+    ///
+    /// Fails if any rule in the grammar is left recursive.
+    pub fn new(grammar: &'g Grammar<'_, L>) -> Result<Self>
+    {
+        check_left_recursion(grammar)?;
+
+        let mut alternatives: HashMap<Id, Vec<&'g Rule<'g, L>>> = HashMap::new();
+        for rule in grammar.rules()
+        {
+            alternatives.entry(rule.input_symbol()).or_default().push(rule);
+        }
+
+        Ok(Self
+        {
+            alternatives,
+            start_symbol: grammar.start_symbol(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        })
+    }
+
+    /// Sets how deeply rules can nest before parsing fails.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self
+    {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Tries to match `symbol` starting at `position`.
+    ///
+    /// Returns the derivation and the position after it, or [None] if no rule matched.
+    fn expand(&self, symbol: Id, position: usize, depth: usize, tokens: &[L]) -> Result<Option<(Derivation, usize)>>
+    {
+        if depth > self.max_depth
+        {
+            bail!("Rules nested more than {} levels deep", self.max_depth);
+        }
+
+        let Some(rules) = self.alternatives.get(&symbol) else
+        {
+            return Ok(None);
+        };
+
+        'rules: for rule in rules
+        {
+            let mut children = Vec::new();
+            let mut current = position;
+            for replacement in rule.replacement_symbols()
+            {
+                match replacement
+                {
+                    SymbolSchema::Terminating(recognizer) => {
+                        match tokens.get(current)
+                        {
+                            Some(token) if recognizer(token) => {
+                                children.push(Derivation::Leaf(current));
+                                current += 1;
+                            },
+                            // Backtrack and try the next rule.
+                            _ => continue 'rules,
+                        }
+                    },
+                    SymbolSchema::Nonterminating(id) => {
+                        match self.expand(*id, current, depth + 1, tokens)?
+                        {
+                            Some((child, end)) => {
+                                children.push(child);
+                                current = end;
+                            },
+                            None => continue 'rules,
+                        }
+                    },
+                }
+            }
+
+            return Ok(Some((Derivation::Node(symbol, children), current)));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<L> ParseEngine for RecursiveDescentEngine<'_, L>
+{
+    type Lang = L;
+
+    fn parse_input(&self, input: impl IntoIterator<Item = L>) -> Result<GrammarTree<L>>
+    {
+        let tokens: Vec<L> = input.into_iter().collect();
+        let (derivation, end) = self.expand(self.start_symbol, 0, 0, &tokens)?
+            .ok_or_else(|| anyhow!("The input didn't match any rule for the start symbol"))?;
+
+        if end != tokens.len()
+        {
+            bail!("Unexpected token at index {}, expected the end of the input", end);
+        }
+
+        let mut tokens: Vec<Option<L>> = tokens.into_iter().map(Some).collect();
+        Ok(build_tree(derivation, &mut tokens))
+    }
+}
+
+/// Moves the tokens into a [GrammarTree] with the shape of the derivation.
+fn build_tree<L>(derivation: Derivation, tokens: &mut [Option<L>]) -> GrammarTree<L>
+{
+    match derivation
+    {
+        // Every token shows up in the derivation at most once, so it's still there.
+        Derivation::Leaf(index) => GrammarTree::Leaf(tokens[index].take().unwrap()),
+        Derivation::Node(symbol, children) => GrammarTree::Node(GrammarNodeData
+        {
+            symbol,
+            children: children.into_iter()
+                .map(|x| Box::new(build_tree(x, tokens)))
+                .collect(),
+        }),
+    }
+}
+
+/// Fails if a symbol can derive a sequence starting with itself without consuming any tokens.
+///
+/// See [Grammar::analyze].
+fn check_left_recursion<L>(grammar: &Grammar<'_, L>) -> Result<()>
+{
+    // Unit cycles are left recursive too, so every cycle counts.
+    if let Some(cycle) = grammar.analyze().cycles.first()
+    {
+        bail!("The rules for symbol {} are left recursive. {}", cycle.path[0], cycle);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fmt::{self, Display};
+
+    use crate::grammar::export::to_sexpr;
+    use crate::grammar::GrammarBuilder;
+
+    use super::*;
+
+    #[derive(Debug)]
+    enum MockLangToken
+    {
+        A,
+        B,
+    }
+
+    impl MockLangToken
+    {
+        fn is_a(&self) -> bool
+        {
+            matches!(self, Self::A)
+        }
+
+        fn is_b(&self) -> bool
+        {
+            matches!(self, Self::B)
+        }
+    }
+
+    impl Display for MockLangToken
+    {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+        {
+            match self
+            {
+                Self::A => write!(f, "a"),
+                Self::B => write!(f, "b"),
+            }
+        }
+    }
+
+    fn render(tree: &GrammarTree<MockLangToken>, names: &[(Id, &str)]) -> String
+    {
+        to_sexpr(tree, |id| {
+            let (_, name) = names.iter().find(|(x, _)| *x == id).unwrap();
+            name.to_string()
+        })
+    }
+
+    #[test]
+    fn test_recursive_descent_matches_shift_reduce()
+    {
+        // S -> X b
+        // X -> a
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let s = grammar_builder.id();
+        let x = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(s).add_nonterminating_symbol(x).add_terminating_symbol(&MockLangToken::is_b))
+            .add_rule(Rule::new(x).add_terminating_symbol(&MockLangToken::is_a))
+            .build()
+            .unwrap();
+        let names = [(s, "s"), (x, "x")];
+
+        let engine = RecursiveDescentEngine::new(&grammar).unwrap();
+        let top_down = engine.parse_input(vec![MockLangToken::A, MockLangToken::B]).unwrap();
+        let bottom_up = grammar.parse_input(vec![MockLangToken::A, MockLangToken::B]).unwrap();
+
+        assert_eq!(render(&top_down, &names), "(s (x a) b)");
+        assert_eq!(render(&top_down, &names), render(&bottom_up, &names));
+    }
+
+    #[test]
+    fn test_recursive_descent_backtracks()
+    {
+        // S -> a a b
+        // S -> a b
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let s = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(s)
+                .add_terminating_symbol(&MockLangToken::is_a)
+                .add_terminating_symbol(&MockLangToken::is_a)
+                .add_terminating_symbol(&MockLangToken::is_b))
+            .add_rule(Rule::new(s)
+                .add_terminating_symbol(&MockLangToken::is_a)
+                .add_terminating_symbol(&MockLangToken::is_b))
+            .build()
+            .unwrap();
+
+        let engine = RecursiveDescentEngine::new(&grammar).unwrap();
+        let tree = engine.parse_input(vec![MockLangToken::A, MockLangToken::B]).unwrap();
+        assert_eq!(render(&tree, &[(s, "s")]), "(s a b)");
+
+        assert!(engine.parse_input(vec![MockLangToken::B]).is_err());
+        assert!(engine.parse_input(vec![MockLangToken::A, MockLangToken::B, MockLangToken::B]).is_err());
+    }
+
+    #[test]
+    fn test_recursive_descent_right_recursion()
+    {
+        // L -> a L
+        // L -> b
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let list = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(list).add_terminating_symbol(&MockLangToken::is_a).add_nonterminating_symbol(list))
+            .add_rule(Rule::new(list).add_terminating_symbol(&MockLangToken::is_b))
+            .build()
+            .unwrap();
+
+        let engine = RecursiveDescentEngine::new(&grammar).unwrap();
+        let tree = engine.parse_input(vec![MockLangToken::A, MockLangToken::A, MockLangToken::B]).unwrap();
+        assert_eq!(render(&tree, &[(list, "l")]), "(l a (l a (l b)))");
+
+        // Too deep for the limit.
+        let engine = engine.with_max_depth(1);
+        assert!(engine.parse_input(vec![MockLangToken::A, MockLangToken::A, MockLangToken::B]).is_err());
+    }
+
+    #[test]
+    fn test_recursive_descent_rejects_left_recursion()
+    {
+        // L -> E L a
+        // L -> b
+        // E -> ε
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let list = grammar_builder.id();
+        let empty = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(list)
+                .add_nonterminating_symbol(empty)
+                .add_nonterminating_symbol(list)
+                .add_terminating_symbol(&MockLangToken::is_a))
+            .add_rule(Rule::new(list).add_terminating_symbol(&MockLangToken::is_b))
+            .add_rule(Rule::new(empty))
+            .build()
+            .unwrap();
+
+        assert!(RecursiveDescentEngine::new(&grammar).is_err());
+    }
+}
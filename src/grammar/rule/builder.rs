@@ -0,0 +1,45 @@
+//! Defines [RuleBuilder], the shorthand used by [GrammarBuilder::rule](crate::grammar::GrammarBuilder::rule).
+use crate::grammar::{Id, Rule, TokenRecognizer};
+
+/// Builds up a single [Rule] a symbol at a time, with shorter names than [Rule]'s own methods.
+///
+/// There aren't any optional or repeated symbols in our grammars, so there's no shorthand for
+/// them either. Write out one rule per alternative instead.
+pub struct RuleBuilder<'a, L>
+{
+    rule: Rule<'a, L>,
+}
+
+impl<'a, L> RuleBuilder<'a, L>
+{
+    /// Starts a rule that replaces `lhs`.
+    pub fn new(lhs: Id) -> Self
+    {
+        Self { rule: Rule::new(lhs) }
+    }
+
+    /// Adds a terminating symbol. Same as [Rule::add_terminating_symbol].
+    pub fn t(self, recognizer: TokenRecognizer<'a, L>) -> Self
+    {
+        Self { rule: self.rule.add_terminating_symbol(recognizer) }
+    }
+
+    /// Adds a terminating symbol with a description for error messages, like `"PRINT"`. Same as
+    /// [Rule::add_terminating_symbol_described].
+    pub fn t_desc(self, description: impl Into<String>, recognizer: TokenRecognizer<'a, L>) -> Self
+    {
+        Self { rule: self.rule.add_terminating_symbol_described(description, recognizer) }
+    }
+
+    /// Adds a non-terminating symbol. Same as [Rule::add_nonterminating_symbol].
+    pub fn nt(self, symbol: Id) -> Self
+    {
+        Self { rule: self.rule.add_nonterminating_symbol(symbol) }
+    }
+
+    /// Finishes the rule.
+    pub fn build(self) -> Rule<'a, L>
+    {
+        self.rule
+    }
+}
@@ -1,6 +1,9 @@
 //! [Id]s are used as unique identifiers for symbols in rules and stuff.
-use std::{fmt::Debug, hash::Hash};
-use std::sync::atomic::AtomicUsize;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 /// A generator for creating [Id]s. Only [Id]s from the same [IdGenerator] can be compared to each
 /// other. 
@@ -10,39 +13,122 @@ use std::sync::atomic::AtomicUsize;
 
 pub struct IdGenerator
 {
-    id: usize,
-    idx: usize,
+    id: u32,
+    idx: u32,
 }
 
 impl IdGenerator
 {
     pub fn new() -> Self
     {
-        static GEN_ID: AtomicUsize = AtomicUsize::new(0);
+        static GEN_ID: AtomicU32 = AtomicU32::new(0);
+        // Gives up instead of wrapping around and handing out the same generator id twice.
+        let id = GEN_ID
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| x.checked_add(1))
+            .expect("Ran out of IdGenerators");
         Self
         {
-            id: GEN_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            id,
             idx: 0
         }
     }
 
+    /// Checks if `id` was handed out by this generator.
+    pub fn issued(&self, id: Id) -> bool
+    {
+        id.generator_id == self.id && id.index() < self.idx as usize
+    }
+
     pub fn id(&mut self) -> Id
     {
-        let old_id = self.idx;
+        assert!(self.idx < Id::MAX, "Ran out of Ids for this IdGenerator");
         self.idx += 1;
         Id 
         {
             generator_id: self.id,
-            id: old_id,
+            // The index we're handing out, plus one. Can't be 0, since we just added 1.
+            id: NonZeroU32::new(self.idx).unwrap(),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+/// Ids are ordered by generator, then by the order they were handed out in.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
 pub struct Id
 {
-    generator_id: usize,
-    id: usize,
+    generator_id: u32,
+    /// The index plus one, so [Option<Id>] can use 0 for [None] and stay the same size.
+    id: NonZeroU32,
+}
+
+impl Id
+{
+    /// No generator or index can reach this.
+    pub const MAX: u32 = u32::MAX;
+
+    /// Which [IdGenerator] this [Id] came from. Only [Id]s from the same generator can be equal.
+    pub fn generator(&self) -> usize
+    {
+        self.generator_id as usize
+    }
+
+    /// Where this [Id] was in the order its generator handed them out, starting at 0.
+    pub fn index(&self) -> usize
+    {
+        (self.id.get() - 1) as usize
+    }
+
+    /// Displays the name for this [Id] in `names`, falling back to the usual `g{generator}:{index}`
+    /// if it doesn't have one.
+    pub fn display_with<'a>(&self, names: &'a HashMap<Id, String>) -> IdDisplay<'a>
+    {
+        IdDisplay { id: *self, names }
+    }
+}
+
+impl Display for Id
+{
+    /// Writes the id as `g{generator}:{index}`, like `g3:0`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "g{}:{}", self.generator_id, self.index())
+    }
+}
+
+impl Debug for Id
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "Id({})", self)
+    }
+}
+
+/// Says that `old`, a symbol from some other [IdGenerator], goes by `new` now. Made by
+/// [GrammarBuilder::import](crate::grammar::GrammarBuilder::import) for every symbol it brings in.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct IdRemap
+{
+    pub old: Id,
+    pub new: Id,
+}
+
+/// Returned by [Id::display_with].
+pub struct IdDisplay<'a>
+{
+    id: Id,
+    names: &'a HashMap<Id, String>,
+}
+
+impl Display for IdDisplay<'_>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self.names.get(&self.id)
+        {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.id),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -77,4 +163,45 @@ mod tests
         let id_2 = gen_2.id();
         assert_ne!(id_1, id_2);
     }
+
+    #[test]
+    pub fn test_issued()
+    {
+        let mut gen_1 = IdGenerator::new();
+        let mut gen_2 = IdGenerator::new();
+        let id_1 = gen_1.id();
+        let id_2 = gen_2.id();
+        assert!(gen_1.issued(id_1));
+        assert!(!gen_1.issued(id_2));
+        assert!(!gen_2.issued(id_1));
+        assert_ne!(id_1.generator(), id_2.generator());
+        assert_eq!(id_1.generator(), gen_1.id().generator());
+    }
+
+    #[test]
+    pub fn test_id_order_and_display()
+    {
+        let mut gen_1 = IdGenerator::new();
+        let mut gen_2 = IdGenerator::new();
+        let a = gen_1.id();
+        let b = gen_1.id();
+        let c = gen_2.id();
+
+        assert!(a < b);
+        assert!(b < c);
+        assert_eq!(b.index(), 1);
+        assert_eq!(b.to_string(), format!("g{}:1", b.generator()));
+        assert_eq!(format!("{:?}", b), format!("Id(g{}:1)", b.generator()));
+
+        let names = HashMap::from([(a, String::from("expression"))]);
+        assert_eq!(a.display_with(&names).to_string(), "expression");
+        assert_eq!(b.display_with(&names).to_string(), b.to_string());
+    }
+
+    #[test]
+    pub fn test_id_is_small()
+    {
+        assert_eq!(std::mem::size_of::<Id>(), 8);
+        assert_eq!(std::mem::size_of::<Option<Id>>(), std::mem::size_of::<Id>());
+    }
 }
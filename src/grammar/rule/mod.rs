@@ -4,16 +4,85 @@
 //! One or more terminal and non-terminating symbols.
 //!
 
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::iter;
+
+pub use builder::RuleBuilder;
 pub use id::Id;
+pub use id::IdDisplay;
 pub use id::IdGenerator;
+pub use id::IdRemap;
 
 use crate::grammar::GrammarTree;
+use crate::grammar::StackEntry;
 
+mod builder;
 mod id;
 
 /// The generic parameter `L` is the type of the langauge we are parser.
 /// This is probably going to be something like `L::is_keyword()` for
-type TokenRecognizer<'a, L> = &'a dyn Fn(&L) -> bool;
+///
+/// Recognizers have to be [Sync] so a [Grammar](crate::grammar::Grammar) can be shared between
+/// threads. Plain functions and closures that don't capture anything always are.
+pub type TokenRecognizer<'a, L> = &'a (dyn Fn(&L) -> bool + Sync);
+
+/// A terminating symbol's recognizer, compared by identity.
+///
+/// Two [Terminal]s are equal if they wrap the same recognizer, so a recognizer that's reused
+/// across several rules is treated as the same terminal everywhere. Used for grammar analysis,
+/// like [Grammar::compute_first_sets](crate::grammar::Grammar::compute_first_sets).
+pub struct Terminal<'a, L>(TokenRecognizer<'a, L>);
+
+impl<'a, L> Terminal<'a, L>
+{
+    pub fn new(recognizer: TokenRecognizer<'a, L>) -> Self
+    {
+        Self(recognizer)
+    }
+
+    /// Checks if `token` is recognized by this terminal.
+    pub fn matches(&self, token: &L) -> bool
+    {
+        (self.0)(token)
+    }
+}
+
+impl<L> Clone for Terminal<'_, L>
+{
+    fn clone(&self) -> Self
+    {
+        *self
+    }
+}
+
+impl<L> Copy for Terminal<'_, L> {}
+
+impl<L> PartialEq for Terminal<'_, L>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl<L> Eq for Terminal<'_, L> {}
+
+impl<L> Hash for Terminal<'_, L>
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        std::ptr::hash(self.0, state)
+    }
+}
+
+impl<L> Debug for Terminal<'_, L>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "Terminal({:p})", self.0)
+    }
+}
 
 /// Symbols can be either terminating or non-terminating symbols.
 ///
@@ -21,7 +90,8 @@ type TokenRecognizer<'a, L> = &'a dyn Fn(&L) -> bool;
 ///
 /// Schema means that this type is used in the definition of rules and symbols.
 ///
-/// When we actually want to see if a sequence of tokens match, we use [SymbolInstance] instead.
+/// When we actually want to see if a sequence of tokens match, we check them against the parse
+/// stack directly with [Rule::matches].
 pub enum SymbolSchema<'a, L>
 {
     Terminating(TokenRecognizer<'a, L>),
@@ -37,7 +107,90 @@ pub struct Rule<'a, L>
     // Left-hand input symbol
     input_symbol: Id,
     // Right-hand symbols to replace it with.
-    replacement_symbols: Vec<SymbolSchema<'a, L>>
+    replacement_symbols: Vec<SymbolSchema<'a, L>>,
+    // Human-readable descriptions of the terminating symbols, lined up with replacement_symbols.
+    descriptions: Vec<Option<String>>,
+}
+
+/// What a rule expected to find at some position, for error messages.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SymbolDescription
+{
+    /// A terminating symbol, with its description if it was given one with
+    /// [Rule::add_terminating_symbol_described].
+    Terminal(Option<String>),
+    Nonterminal(Id),
+}
+
+impl Display for SymbolDescription
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Terminal(Some(description)) => write!(f, "{}", description),
+            Self::Terminal(None) => write!(f, "a token"),
+            Self::Nonterminal(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// What was actually on the stack where a rule expected something else.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FoundDescription
+{
+    /// A token the rule's recognizer didn't accept, or a token where a non-terminating symbol
+    /// should have been.
+    Token,
+    Nonterminal(Id),
+}
+
+impl Display for FoundDescription
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Token => write!(f, "a token"),
+            Self::Nonterminal(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// Why a rule didn't match a run of symbols. See [Rule::explain_mismatch].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Mismatch
+{
+    /// Every symbol that was there matched, but there were the wrong number of them.
+    LengthMismatch { expected: usize, got: usize },
+    /// The symbol at `position` wasn't what the rule expected.
+    SymbolMismatch { position: usize, expected: SymbolDescription, found: FoundDescription },
+}
+
+impl Mismatch
+{
+    /// How many symbols matched before the rule went wrong.
+    pub fn matched_len(&self) -> usize
+    {
+        match self
+        {
+            Self::LengthMismatch { expected, got } => *expected.min(got),
+            Self::SymbolMismatch { position, .. } => *position,
+        }
+    }
+}
+
+impl Display for Mismatch
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::LengthMismatch { expected, got } => write!(f, "expected {} symbols, got {}", expected, got),
+            Self::SymbolMismatch { position, expected, found } =>
+                write!(f, "expected {} at position {}, found {}", expected, position, found),
+        }
+    }
 }
 
 impl<'a, L> Rule<'a, L>
@@ -47,54 +200,157 @@ impl<'a, L> Rule<'a, L>
         Self
         {
             input_symbol,
-            replacement_symbols: Vec::new()
+            replacement_symbols: Vec::new(),
+            descriptions: Vec::new(),
         }
     }
 
     pub fn add_nonterminating_symbol(mut self, symbol: Id) -> Self
     {
         self.replacement_symbols.push(SymbolSchema::Nonterminating(symbol));
+        self.descriptions.push(None);
         self
     }
 
     pub fn add_terminating_symbol(mut self, terminating_symbol_recognizer: TokenRecognizer<'a, L>) -> Self
     {
         self.replacement_symbols.push(SymbolSchema::Terminating(terminating_symbol_recognizer));
+        self.descriptions.push(None);
+        self
+    }
+
+    /// Same as [Rule::add_terminating_symbol], but `description` (like `"a line number"`) is used
+    /// to describe the symbol in error messages.
+    pub fn add_terminating_symbol_described(mut self, description: impl Into<String>, terminating_symbol_recognizer: TokenRecognizer<'a, L>) -> Self
+    {
+        self.replacement_symbols.push(SymbolSchema::Terminating(terminating_symbol_recognizer));
+        self.descriptions.push(Some(description.into()));
         self
     }
 
+    /// Checks if `rhs` (usually the top of the parse stack) is exactly this rule's replacement
+    /// symbols. Works on the stack in place, so there's nothing to allocate.
     pub fn matches(&self, rhs: &[GrammarTree<L>]) -> bool
+    {
+        self.matches_entries(rhs)
+    }
+
+    /// Same as [Rule::matches], for any kind of parse stack.
+    pub(crate) fn matches_entries<S: StackEntry<L>>(&self, rhs: &[S]) -> bool
     {
         if self.replacement_symbols.len() != rhs.len()
         {
             return false;
         }
 
-        for (symbol_schema, symbol_instance) in self.replacement_symbols.iter().zip(rhs)
+        self.first_mismatch(rhs).is_none()
+    }
+
+    /// Checks if `rhs` matches the first `rhs.len()` symbols of this rule.
+    ///
+    /// If it does, shifting more tokens onto the end of `rhs` could still complete the rule. An
+    /// empty `rhs` is a prefix of every rule.
+    pub fn matches_prefix(&self, rhs: &[GrammarTree<L>]) -> bool
+    {
+        if rhs.len() > self.replacement_symbols.len()
+        {
+            return false;
+        }
+
+        self.first_mismatch(rhs).is_none()
+    }
+
+    /// Explains why `rhs` doesn't match this rule, or returns [None] if it does.
+    ///
+    /// A wrong symbol is reported before a wrong length, so an `rhs` that's too short but wrong
+    /// at position 1 is a [Mismatch::SymbolMismatch] at position 1.
+    pub fn explain_mismatch(&self, rhs: &[GrammarTree<L>]) -> Option<Mismatch>
+    {
+        self.explain_mismatch_entries(rhs)
+    }
+
+    /// Same as [Rule::explain_mismatch], for any kind of parse stack.
+    pub(crate) fn explain_mismatch_entries<S: StackEntry<L>>(&self, rhs: &[S]) -> Option<Mismatch>
+    {
+        if let Some(position) = self.first_mismatch(rhs)
         {
-            // Check to see if the symbols match.
-            let symbol_match = match (symbol_schema, symbol_instance)
+            let expected = match &self.replacement_symbols[position]
             {
-                (SymbolSchema::Terminating(func), GrammarTree::Leaf(token)) => func(token),
-                (SymbolSchema::Terminating(_), GrammarTree::Node(_)) => false,
-                (SymbolSchema::Nonterminating(_), GrammarTree::Leaf(_)) => false,
-                (SymbolSchema::Nonterminating(id), GrammarTree::Node(data)) => *id == data.symbol,
+                SymbolSchema::Terminating(_) => SymbolDescription::Terminal(self.descriptions[position].clone()),
+                SymbolSchema::Nonterminating(id) => SymbolDescription::Nonterminal(*id),
             };
-
-            // If they don't, abort. Otherwise continue.
-            if !symbol_match 
+            let found = match rhs[position].symbol()
             {
-                return false;
-            }
+                None => FoundDescription::Token,
+                Some(symbol) => FoundDescription::Nonterminal(symbol),
+            };
+            return Some(Mismatch::SymbolMismatch { position, expected, found });
+        }
+
+        if rhs.len() != self.replacement_symbols.len()
+        {
+            return Some(Mismatch::LengthMismatch { expected: self.replacement_symbols.len(), got: rhs.len() });
         }
 
-        return true;
+        None
+    }
+
+    /// The position of the first symbol in `rhs` that doesn't match this rule. Anything past the
+    /// end of the rule isn't checked.
+    fn first_mismatch<S: StackEntry<L>>(&self, rhs: &[S]) -> Option<usize>
+    {
+        self.replacement_symbols.iter().zip(rhs).position(|(symbol_schema, symbol_instance)|
+            match symbol_schema
+            {
+                SymbolSchema::Terminating(func) => symbol_instance.token().is_none_or(|token| !func(token)),
+                SymbolSchema::Nonterminating(id) => symbol_instance.symbol() != Some(*id),
+            })
     }
 
     pub fn input_symbol(&self) -> Id
     {
         self.input_symbol
     }
+
+    /// Every non-terminating symbol the rule mentions, starting with the input symbol.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = Id> + '_
+    {
+        iter::once(self.input_symbol).chain(self.replacement_symbols.iter().filter_map(|x| match x
+        {
+            SymbolSchema::Nonterminating(id) => Some(*id),
+            SymbolSchema::Terminating(_) => None,
+        }))
+    }
+
+    /// Swaps the input symbol and every [SymbolSchema::Nonterminating] reference for its `new`
+    /// [Id] in `remaps`. Symbols that aren't in `remaps` are left alone.
+    pub(crate) fn remap(self, remaps: &[IdRemap]) -> Self
+    {
+        let remap = |id: Id| remaps.iter().find(|x| x.old == id).map_or(id, |x| x.new);
+        Self
+        {
+            input_symbol: remap(self.input_symbol),
+            replacement_symbols: self.replacement_symbols.into_iter().map(|x| match x
+            {
+                SymbolSchema::Nonterminating(id) => SymbolSchema::Nonterminating(remap(id)),
+                terminating => terminating,
+            }).collect(),
+            descriptions: self.descriptions,
+        }
+    }
+
+    /// The symbols the input symbol gets replaced with, in order.
+    pub fn replacement_symbols(&self) -> &[SymbolSchema<'a, L>]
+    {
+        &self.replacement_symbols
+    }
+
+    /// The description given to the replacement symbol at `position` with
+    /// [Rule::add_terminating_symbol_described], if it has one.
+    pub fn description(&self, position: usize) -> Option<&str>
+    {
+        self.descriptions.get(position)?.as_deref()
+    }
 }
 
 #[cfg(test)]
@@ -179,4 +435,73 @@ mod tests
 
         assert!(rule.matches(&input_symbols));
     }
+
+    #[test]
+    fn test_rule_matches_prefix()
+    {
+        let mut grammar = GrammarBuilder::<MockLang>::new();
+        let s = grammar.id();
+        let t = grammar.id();
+
+        let rule = Rule::new(s)
+            .add_terminating_symbol(&MockLang::test_func)
+            .add_nonterminating_symbol(t);
+
+        let leaf = || GrammarTree::<MockLang>::Leaf(MockLang());
+        let node = || GrammarTree::<MockLang>::Node(GrammarNodeData
+            { symbol: t, children: Vec::new() }
+        );
+
+        assert!(rule.matches_prefix(&[]));
+        assert!(rule.matches_prefix(&[leaf()]));
+        assert!(rule.matches_prefix(&[leaf(), node()]));
+        // Wrong symbol in the first position.
+        assert!(!rule.matches_prefix(&[node()]));
+        // Longer than the rule itself.
+        assert!(!rule.matches_prefix(&[leaf(), node(), leaf()]));
+
+        // A strict prefix isn't a full match.
+        assert!(!rule.matches(&[leaf()]));
+    }
+
+    #[test]
+    fn test_rule_explain_mismatch()
+    {
+        let mut grammar = GrammarBuilder::<MockLang>::new();
+        let s = grammar.id();
+        let t = grammar.id();
+
+        let rule = Rule::new(s)
+            .add_terminating_symbol_described("anything", &MockLang::test_func)
+            .add_nonterminating_symbol(t);
+
+        let leaf = || GrammarTree::<MockLang>::Leaf(MockLang());
+        let node = || GrammarTree::<MockLang>::Node(GrammarNodeData
+            { symbol: t, children: Vec::new() }
+        );
+
+        assert_eq!(rule.explain_mismatch(&[leaf(), node()]), None);
+        assert_eq!(
+            rule.explain_mismatch(&[leaf()]),
+            Some(Mismatch::LengthMismatch { expected: 2, got: 1 })
+        );
+        assert_eq!(
+            rule.explain_mismatch(&[leaf(), leaf()]),
+            Some(Mismatch::SymbolMismatch
+            {
+                position: 1,
+                expected: SymbolDescription::Nonterminal(t),
+                found: FoundDescription::Token,
+            })
+        );
+        assert_eq!(
+            rule.explain_mismatch(&[node()]),
+            Some(Mismatch::SymbolMismatch
+            {
+                position: 0,
+                expected: SymbolDescription::Terminal(Some(String::from("anything"))),
+                found: FoundDescription::Nonterminal(t),
+            })
+        );
+    }
 }
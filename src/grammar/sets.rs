@@ -0,0 +1,359 @@
+//! Computes the FIRST and FOLLOW sets of a [Grammar], which say which terminals can start a
+//! non-terminating symbol and which can come right after it.
+//!
+//! Both are computed with the usual fixed-point iteration: we keep applying every rule until none
+//! of the sets change anymore.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+use crate::grammar::Grammar;
+use crate::grammar::Id;
+use crate::grammar::SymbolSchema;
+use crate::grammar::Terminal;
+
+/// Something that can appear in a FIRST set.
+pub enum FirstSymbol<'a, L>
+{
+    /// The symbol can start with a token matching this terminal.
+    Terminal(Terminal<'a, L>),
+    /// The symbol can match no tokens at all.
+    Empty,
+}
+
+/// Something that can appear in a FOLLOW set.
+pub enum FollowSymbol<'a, L>
+{
+    /// The symbol can be followed by a token matching this terminal.
+    Terminal(Terminal<'a, L>),
+    /// The symbol can be the last thing in the input.
+    EndOfInput,
+}
+
+// These are implemented by hand since deriving them would require `L` to implement them too, even
+// though we only ever compare the recognizers.
+impl<L> Debug for FirstSymbol<'_, L>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Terminal(terminal) => write!(f, "{:?}", terminal),
+            Self::Empty => write!(f, "Empty"),
+        }
+    }
+}
+
+impl<L> Clone for FirstSymbol<'_, L>
+{
+    fn clone(&self) -> Self
+    {
+        *self
+    }
+}
+
+impl<L> Copy for FirstSymbol<'_, L> {}
+
+impl<L> PartialEq for FirstSymbol<'_, L>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        match (self, other)
+        {
+            (Self::Terminal(lhs), Self::Terminal(rhs)) => lhs == rhs,
+            (Self::Empty, Self::Empty) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<L> Eq for FirstSymbol<'_, L> {}
+
+impl<L> Hash for FirstSymbol<'_, L>
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        std::mem::discriminant(self).hash(state);
+        if let Self::Terminal(terminal) = self
+        {
+            terminal.hash(state);
+        }
+    }
+}
+
+impl<L> Debug for FollowSymbol<'_, L>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Terminal(terminal) => write!(f, "{:?}", terminal),
+            Self::EndOfInput => write!(f, "EndOfInput"),
+        }
+    }
+}
+
+impl<L> Clone for FollowSymbol<'_, L>
+{
+    fn clone(&self) -> Self
+    {
+        *self
+    }
+}
+
+impl<L> Copy for FollowSymbol<'_, L> {}
+
+impl<L> PartialEq for FollowSymbol<'_, L>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        match (self, other)
+        {
+            (Self::Terminal(lhs), Self::Terminal(rhs)) => lhs == rhs,
+            (Self::EndOfInput, Self::EndOfInput) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<L> Eq for FollowSymbol<'_, L> {}
+
+impl<L> Hash for FollowSymbol<'_, L>
+{
+    fn hash<H: Hasher>(&self, state: &mut H)
+    {
+        std::mem::discriminant(self).hash(state);
+        if let Self::Terminal(terminal) = self
+        {
+            terminal.hash(state);
+        }
+    }
+}
+
+impl<L> Grammar<'_, L>
+{
+    /// Computes the FIRST set of every non-terminating symbol that has a rule.
+    pub fn compute_first_sets(&self) -> HashMap<Id, HashSet<FirstSymbol<'_, L>>>
+    {
+        let mut first_sets: HashMap<Id, HashSet<FirstSymbol<'_, L>>> = self.rules()
+            .map(|rule| (rule.input_symbol(), HashSet::new()))
+            .collect();
+
+        let mut changed = true;
+        while changed
+        {
+            changed = false;
+            for rule in self.rules()
+            {
+                let first = first_of_sequence(rule.replacement_symbols(), &first_sets);
+                let set = first_sets.get_mut(&rule.input_symbol()).unwrap();
+                for symbol in first
+                {
+                    changed |= set.insert(symbol);
+                }
+            }
+        }
+
+        first_sets
+    }
+
+    /// Computes the FOLLOW set of every non-terminating symbol that has a rule.
+    ///
+    /// The [Grammar::start_symbol] can always be followed by the end of the input.
+    pub fn compute_follow_sets(&self) -> HashMap<Id, HashSet<FollowSymbol<'_, L>>>
+    {
+        let first_sets = self.compute_first_sets();
+        let mut follow_sets: HashMap<Id, HashSet<FollowSymbol<'_, L>>> = self.rules()
+            .map(|rule| (rule.input_symbol(), HashSet::new()))
+            .collect();
+        follow_sets.entry(self.start_symbol()).or_default().insert(FollowSymbol::EndOfInput);
+
+        let mut changed = true;
+        while changed
+        {
+            changed = false;
+            for rule in self.rules()
+            {
+                let symbols = rule.replacement_symbols();
+                for (i, symbol) in symbols.iter().enumerate()
+                {
+                    let SymbolSchema::Nonterminating(id) = symbol else
+                    {
+                        continue;
+                    };
+
+                    // Whatever can start the rest of the rule can follow this symbol.
+                    let mut follow: HashSet<FollowSymbol<'_, L>> = HashSet::new();
+                    let mut rest_can_be_empty = false;
+                    for first in first_of_sequence(&symbols[i + 1..], &first_sets)
+                    {
+                        match first
+                        {
+                            FirstSymbol::Terminal(terminal) => {
+                                follow.insert(FollowSymbol::Terminal(terminal));
+                            },
+                            FirstSymbol::Empty => rest_can_be_empty = true,
+                        }
+                    }
+
+                    // If the rest of the rule can be empty, whatever follows the rule follows
+                    // this symbol too.
+                    if rest_can_be_empty
+                        && let Some(rule_follow) = follow_sets.get(&rule.input_symbol())
+                    {
+                        follow.extend(rule_follow.iter().copied());
+                    }
+
+                    let set = follow_sets.entry(*id).or_default();
+                    for symbol in follow
+                    {
+                        changed |= set.insert(symbol);
+                    }
+                }
+            }
+        }
+
+        follow_sets
+    }
+}
+
+/// Computes the FIRST set of a sequence of symbols, using the FIRST sets we know so far.
+///
+/// Contains [FirstSymbol::Empty] if every symbol in the sequence can be empty (including when the
+/// sequence itself is empty).
+fn first_of_sequence<'a, L>(
+    symbols: &[SymbolSchema<'a, L>],
+    first_sets: &HashMap<Id, HashSet<FirstSymbol<'a, L>>>) -> HashSet<FirstSymbol<'a, L>>
+{
+    let mut first = HashSet::new();
+    for symbol in symbols
+    {
+        match symbol
+        {
+            SymbolSchema::Terminating(recognizer) => {
+                first.insert(FirstSymbol::Terminal(Terminal::new(*recognizer)));
+                return first;
+            },
+            SymbolSchema::Nonterminating(id) => {
+                // Symbols without any rules can't match anything, so they're empty sets.
+                let Some(symbol_first) = first_sets.get(id) else
+                {
+                    return first;
+                };
+
+                let mut can_be_empty = false;
+                for symbol in symbol_first
+                {
+                    match symbol
+                    {
+                        FirstSymbol::Empty => can_be_empty = true,
+                        terminal => { first.insert(*terminal); },
+                    }
+                }
+
+                if !can_be_empty
+                {
+                    return first;
+                }
+            },
+        }
+    }
+
+    first.insert(FirstSymbol::Empty);
+    first
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::grammar::{GrammarBuilder, Rule};
+
+    use super::*;
+
+    enum MockLangToken
+    {
+        A,
+        B,
+    }
+
+    impl MockLangToken
+    {
+        fn is_a(&self) -> bool
+        {
+            matches!(self, Self::A)
+        }
+
+        fn is_b(&self) -> bool
+        {
+            matches!(self, Self::B)
+        }
+    }
+
+    type Recognizer<'a> = &'a (dyn Fn(&MockLangToken) -> bool + Sync);
+
+    #[test]
+    fn test_first_and_follow_sets()
+    {
+        let is_a: Recognizer<'_> = &MockLangToken::is_a;
+        let is_b: Recognizer<'_> = &MockLangToken::is_b;
+
+        // S -> X Y
+        // X -> a
+        // X -> ε
+        // Y -> b
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let s = grammar_builder.id();
+        let x = grammar_builder.id();
+        let y = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(s).add_nonterminating_symbol(x).add_nonterminating_symbol(y))
+            .add_rule(Rule::new(x).add_terminating_symbol(is_a))
+            .add_rule(Rule::new(x))
+            .add_rule(Rule::new(y).add_terminating_symbol(is_b))
+            .build()
+            .unwrap();
+
+        let a = Terminal::new(is_a);
+        let b = Terminal::new(is_b);
+        assert!(a.matches(&MockLangToken::A));
+        assert!(!a.matches(&MockLangToken::B));
+
+        let first_sets = grammar.compute_first_sets();
+        assert_eq!(first_sets[&s], HashSet::from([FirstSymbol::Terminal(a), FirstSymbol::Terminal(b)]));
+        assert_eq!(first_sets[&x], HashSet::from([FirstSymbol::Terminal(a), FirstSymbol::Empty]));
+        assert_eq!(first_sets[&y], HashSet::from([FirstSymbol::Terminal(b)]));
+
+        let follow_sets = grammar.compute_follow_sets();
+        assert_eq!(follow_sets[&s], HashSet::from([FollowSymbol::EndOfInput]));
+        assert_eq!(follow_sets[&x], HashSet::from([FollowSymbol::Terminal(b)]));
+        assert_eq!(follow_sets[&y], HashSet::from([FollowSymbol::EndOfInput]));
+    }
+
+    #[test]
+    fn test_first_sets_with_recursion()
+    {
+        let is_a: Recognizer<'_> = &MockLangToken::is_a;
+        let is_b: Recognizer<'_> = &MockLangToken::is_b;
+
+        // L -> a L
+        // L -> b
+        let mut grammar_builder = GrammarBuilder::<MockLangToken>::new();
+        let list = grammar_builder.id();
+        let grammar = grammar_builder
+            .add_rule(Rule::new(list).add_terminating_symbol(is_a).add_nonterminating_symbol(list))
+            .add_rule(Rule::new(list).add_terminating_symbol(is_b))
+            .build()
+            .unwrap();
+
+        let first_sets = grammar.compute_first_sets();
+        assert_eq!(first_sets[&list], HashSet::from([
+            FirstSymbol::Terminal(Terminal::new(is_a)),
+            FirstSymbol::Terminal(Terminal::new(is_b)),
+        ]));
+
+        let follow_sets = grammar.compute_follow_sets();
+        assert_eq!(follow_sets[&list], HashSet::from([FollowSymbol::EndOfInput]));
+    }
+}
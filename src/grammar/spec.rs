@@ -0,0 +1,324 @@
+//! Defines [GrammarSpec], which the [grammar!](crate::grammar!) macro fills in by name and then
+//! turns into a [GrammarBuilder].
+
+use std::collections::HashMap;
+
+use crate::grammar::GrammarBuildError;
+use crate::grammar::GrammarBuilder;
+use crate::grammar::Id;
+use crate::grammar::Rule;
+use crate::grammar::TokenRecognizer;
+
+/// One piece of an alternative in a [GrammarSpec], referring to symbols by name.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SpecPart
+{
+    Symbol(&'static str),
+    /// Symbols that can be left out all together, written `[a b]?` in [grammar!](crate::grammar!).
+    Optional(Vec<&'static str>),
+}
+
+/// A grammar written with names instead of [Id]s. Usually made by the [grammar!](crate::grammar!)
+/// macro rather than by hand.
+pub struct GrammarSpec<'a, L>
+{
+    start: Option<&'static str>,
+    terminals: Vec<(&'static str, TokenRecognizer<'a, L>)>,
+    alternatives: Vec<(&'static str, Vec<SpecPart>)>,
+}
+
+impl<'a, L> GrammarSpec<'a, L>
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            start: None,
+            terminals: Vec::new(),
+            alternatives: Vec::new(),
+        }
+    }
+
+    /// Sets the start symbol. See [GrammarBuilder::start].
+    pub fn start(&mut self, name: &'static str)
+    {
+        self.start = Some(name);
+    }
+
+    /// Names a terminating symbol. It's registered as a
+    /// [terminal class](GrammarBuilder::terminal_class) with the same name, and the name is used
+    /// to describe it in error messages.
+    pub fn terminal(&mut self, name: &'static str, recognizer: TokenRecognizer<'a, L>)
+    {
+        self.terminals.push((name, recognizer));
+    }
+
+    /// Adds one way of writing `lhs`.
+    pub fn alternative(&mut self, lhs: &'static str, parts: Vec<SpecPart>)
+    {
+        self.alternatives.push((lhs, parts));
+    }
+
+    /// Turns the spec into a [GrammarBuilder], returning it along with the [Id] given to each
+    /// non-terminating symbol.
+    ///
+    /// Each symbol on the left of an alternative gets an [Id], in the order they first show up.
+    /// Alternatives with optional parts turn into a rule for every combination of the parts being
+    /// there or not, longest first. Any name that isn't a terminal or on the left of some
+    /// alternative is reported by [GrammarBuilder::build].
+    pub fn into_builder(self) -> (GrammarBuilder<'a, L>, HashMap<&'static str, Id>)
+    {
+        let mut builder = GrammarBuilder::new();
+        let mut ids: HashMap<&'static str, Id> = HashMap::new();
+        for (lhs, _) in &self.alternatives
+        {
+            if !ids.contains_key(lhs)
+            {
+                let id = builder.id();
+                builder.name(id, *lhs);
+                ids.insert(lhs, id);
+            }
+        }
+
+        for (name, recognizer) in &self.terminals
+        {
+            builder = builder.terminal_class(*name, *recognizer);
+        }
+
+        for (lhs, parts) in &self.alternatives
+        {
+            for symbols in expand_optionals(parts)
+            {
+                let mut rule = Rule::new(ids[lhs]);
+                for symbol in symbols
+                {
+                    if let Some((_, recognizer)) = self.terminals.iter().find(|(name, _)| *name == symbol)
+                    {
+                        rule = rule.add_terminating_symbol_described(symbol, *recognizer);
+                    }
+                    else if let Some(id) = ids.get(symbol)
+                    {
+                        rule = rule.add_nonterminating_symbol(*id);
+                    }
+                    else
+                    {
+                        builder.fail(GrammarBuildError::UnknownSymbol(symbol));
+                    }
+                }
+                builder = builder.add_rule(rule);
+            }
+        }
+
+        if let Some(start) = self.start
+        {
+            match ids.get(start)
+            {
+                Some(id) => { builder.start(*id); },
+                None => builder.fail(GrammarBuildError::UnknownSymbol(start)),
+            }
+        }
+
+        (builder, ids)
+    }
+}
+
+impl<L> Default for GrammarSpec<'_, L>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Every list of symbols `parts` can be written as, with the optional parts left in first.
+fn expand_optionals(parts: &[SpecPart]) -> Vec<Vec<&'static str>>
+{
+    let mut expanded: Vec<Vec<&'static str>> = vec![Vec::new()];
+    for part in parts
+    {
+        match part
+        {
+            SpecPart::Symbol(symbol) => expanded.iter_mut().for_each(|x| x.push(symbol)),
+            SpecPart::Optional(symbols) => expanded = expanded.into_iter()
+                .flat_map(|without|
+                {
+                    let mut with = without.clone();
+                    with.extend(symbols);
+                    [with, without]
+                })
+                .collect(),
+        }
+    }
+    expanded
+}
+
+/// Defines a grammar by name instead of wiring up [Id]s and [Rule]s by hand. Evaluates to a
+/// [GrammarBuilder] and a map from each non-terminating symbol's name to its [Id], see
+/// [GrammarSpec::into_builder].
+///
+/// ```
+/// # use tiny_basic_compiler::grammar;
+/// fn is_digit(c: &char) -> bool { c.is_ascii_digit() }
+/// fn is_plus(c: &char) -> bool { *c == '+' }
+///
+/// let (builder, ids) = grammar! {
+///     start sum;
+///     sum ::= sum plus digit | digit;
+///     list ::= digit [plus]? digit;
+///     terminal digit = is_digit;
+///     terminal plus = is_plus;
+/// };
+/// let grammar = builder.build().unwrap();
+/// assert_eq!(grammar.start_symbol(), ids["sum"]);
+/// ```
+///
+/// * `start name;` sets the start symbol. Without it, the first rule's symbol is used.
+/// * `terminal name = recognizer;` names a terminating symbol. The recognizer is anything that can
+///   be borrowed as a [TokenRecognizer], usually a function path like `Token::is_number`.
+/// * `name ::= a b | c [d e]? f;` adds a rule for each alternative, with the symbols in `[...]?`
+///   being optional. An empty alternative is a rule with no symbols.
+///
+/// Statements can be in any order. Repetition like `(a b)*` isn't supported, since there's no
+/// [Rule] feature for it to turn into. Write a recursive rule instead.
+///
+/// ```compile_fail
+/// # use tiny_basic_compiler::grammar;
+/// fn is_digit(c: &char) -> bool { c.is_ascii_digit() }
+/// let (builder, _) = grammar! {
+///     number ::= digit (digit)*;
+///     terminal digit = is_digit;
+/// };
+/// ```
+#[macro_export]
+macro_rules! grammar
+{
+    (@stmts $spec:ident) => {};
+    (@stmts $spec:ident start $start:ident ; $($rest:tt)*) => {
+        $spec.start(stringify!($start));
+        $crate::grammar!(@stmts $spec $($rest)*);
+    };
+    (@stmts $spec:ident terminal $name:ident = $recognizer:expr ; $($rest:tt)*) => {
+        $spec.terminal(stringify!($name), &$recognizer);
+        $crate::grammar!(@stmts $spec $($rest)*);
+    };
+    (@stmts $spec:ident $lhs:ident ::= $($rest:tt)*) => {
+        $crate::grammar!(@alt $spec $lhs [] $($rest)*);
+    };
+
+    (@alt $spec:ident $lhs:ident [$($part:expr),*] | $($rest:tt)*) => {
+        $spec.alternative(stringify!($lhs), vec![$($part),*]);
+        $crate::grammar!(@alt $spec $lhs [] $($rest)*);
+    };
+    (@alt $spec:ident $lhs:ident [$($part:expr),*] ; $($rest:tt)*) => {
+        $spec.alternative(stringify!($lhs), vec![$($part),*]);
+        $crate::grammar!(@stmts $spec $($rest)*);
+    };
+    (@alt $spec:ident $lhs:ident [$($part:expr),*] [ $($optional:ident)+ ] ? $($rest:tt)*) => {
+        $crate::grammar!(@alt $spec $lhs
+            [$($part,)* $crate::grammar::SpecPart::Optional(vec![$(stringify!($optional)),+])]
+            $($rest)*);
+    };
+    (@alt $spec:ident $lhs:ident [$($part:expr),*] ( $($repeated:tt)* ) * $($rest:tt)*) => {
+        compile_error!("grammar! doesn't support repetition, use a recursive rule instead");
+    };
+    (@alt $spec:ident $lhs:ident [$($part:expr),*] $symbol:ident $($rest:tt)*) => {
+        $crate::grammar!(@alt $spec $lhs
+            [$($part,)* $crate::grammar::SpecPart::Symbol(stringify!($symbol))]
+            $($rest)*);
+    };
+
+    ($($body:tt)*) => {{
+        let mut spec = $crate::grammar::GrammarSpec::new();
+        $crate::grammar!(@stmts spec $($body)*);
+        spec.into_builder()
+    }};
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::grammar::export::to_sexpr;
+    use crate::lang::create_lexer;
+    use crate::lang::token::{Symbol, Token};
+
+    use super::*;
+
+    fn is_plus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Plus)
+    }
+
+    fn is_minus(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Minus)
+    }
+
+    fn is_times(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Times)
+    }
+
+    fn is_divide(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Divide)
+    }
+
+    #[test]
+    fn test_tiny_basic_expressions()
+    {
+        let (builder, ids) = crate::grammar! {
+            start expression;
+            expression ::= expression plus term | expression minus term | [minus]? term;
+            term ::= term times factor | term divide factor | factor;
+            factor ::= number | variable;
+
+            terminal number = Token::is_number;
+            terminal variable = Token::is_variable;
+            terminal plus = is_plus;
+            terminal minus = is_minus;
+            terminal times = is_times;
+            terminal divide = is_divide;
+        };
+        let grammar = builder.build().unwrap().compile().unwrap();
+
+        let names: HashMap<Id, &str> = ids.iter().map(|(name, id)| (*id, *name)).collect();
+        let parse = |source: &str|
+        {
+            let tokens: Vec<Token> = create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap();
+            grammar.parse(tokens).map(|tree| to_sexpr(&tree, |id| String::from(names[&id])))
+        };
+
+        assert_eq!(parse("1+2*3").unwrap(),
+            "(expression (expression (term (factor 1))) + (term (term (factor 2)) * (factor 3)))");
+        assert_eq!(parse("-A/2").unwrap(),
+            "(expression - (term (term (factor A)) / (factor 2)))");
+        assert!(parse("1+*3").is_err());
+    }
+
+    #[test]
+    fn test_optional_parts_expand_longest_first()
+    {
+        let parts = [
+            SpecPart::Optional(vec!["a"]),
+            SpecPart::Symbol("b"),
+            SpecPart::Optional(vec!["c", "d"]),
+        ];
+        assert_eq!(expand_optionals(&parts), vec![
+            vec!["a", "b", "c", "d"],
+            vec!["a", "b"],
+            vec!["b", "c", "d"],
+            vec!["b"],
+        ]);
+    }
+
+    #[test]
+    fn test_unknown_symbol()
+    {
+        let (builder, _) = crate::grammar! {
+            line ::= number statment;
+            statement ::= number;
+            terminal number = Token::is_number;
+        };
+        assert_eq!(builder.build().err(), Some(GrammarBuildError::UnknownSymbol("statment")));
+    }
+}
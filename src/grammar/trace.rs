@@ -0,0 +1,122 @@
+//! Defines the events reported by [Grammar::parse_traced](crate::grammar::Grammar::parse_traced)
+//! and some helpers for recording and printing them.
+
+use std::fmt::Write;
+
+use crate::grammar::Id;
+
+/// Something the parser did while parsing an input stream.
+///
+/// L is the type of the language we are parsing.
+#[derive(Debug)]
+pub enum ParseEvent<'a, L>
+{
+    /// The token at `token_index` in the input was pushed onto the stack.
+    Shift
+    {
+        token_index: usize,
+        token: &'a L,
+    },
+    /// The last `matched_len` symbols on the stack were replaced by `rule_symbol`.
+    Reduce
+    {
+        rule_symbol: Id,
+        matched_len: usize,
+        stack_depth_after: usize,
+    },
+    /// No rule matched the end of the stack, so we move on to the next token.
+    NoReduce
+    {
+        stack_depth: usize,
+    },
+}
+
+/// A [ParseEvent] without the reference to the token, so it can be kept around after parsing
+/// is done.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TraceEvent
+{
+    Shift
+    {
+        token_index: usize,
+    },
+    Reduce
+    {
+        rule_symbol: Id,
+        matched_len: usize,
+        stack_depth_after: usize,
+    },
+    NoReduce
+    {
+        stack_depth: usize,
+    },
+}
+
+impl<L> From<&ParseEvent<'_, L>> for TraceEvent
+{
+    fn from(value: &ParseEvent<'_, L>) -> Self {
+        match *value
+        {
+            ParseEvent::Shift { token_index, .. } => Self::Shift { token_index },
+            ParseEvent::Reduce { rule_symbol, matched_len, stack_depth_after } =>
+                Self::Reduce { rule_symbol, matched_len, stack_depth_after },
+            ParseEvent::NoReduce { stack_depth } => Self::NoReduce { stack_depth },
+        }
+    }
+}
+
+/// Records every [ParseEvent] it's given as a [TraceEvent].
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut collector = TraceCollector::new();
+/// grammar.parse_traced(tokens, &mut collector.callback());
+/// println!("{}", render_trace(collector.events(), |id| id.to_string()));
+/// ```
+#[derive(Debug, Default)]
+pub struct TraceCollector
+{
+    events: Vec<TraceEvent>,
+}
+
+impl TraceCollector
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Returns a callback that can be passed to
+    /// [Grammar::parse_traced](crate::grammar::Grammar::parse_traced).
+    pub fn callback<L>(&mut self) -> impl FnMut(ParseEvent<'_, L>) + '_
+    {
+        |event| self.events.push(TraceEvent::from(&event))
+    }
+
+    /// All of the events recorded so far, in order.
+    pub fn events(&self) -> &[TraceEvent]
+    {
+        &self.events
+    }
+}
+
+/// Formats a list of [TraceEvent]s, one per line. `name_fn` gives the name of each rule symbol.
+pub fn render_trace(events: &[TraceEvent], name_fn: impl Fn(Id) -> String) -> String
+{
+    let mut output = String::new();
+    for event in events
+    {
+        match *event
+        {
+            TraceEvent::Shift { token_index } =>
+                writeln!(output, "shift token {}", token_index),
+            TraceEvent::Reduce { rule_symbol, matched_len, stack_depth_after } =>
+                writeln!(output, "reduce {} from {} symbols (stack depth {})",
+                    name_fn(rule_symbol), matched_len, stack_depth_after),
+            TraceEvent::NoReduce { stack_depth } =>
+                writeln!(output, "no reduce (stack depth {})", stack_depth),
+        }.unwrap();
+    }
+    output
+}
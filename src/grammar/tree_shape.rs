@@ -0,0 +1,360 @@
+//! Turning a [GrammarTree] into something more useful, like an AST, with errors that say where in
+//! the tree things went wrong.
+
+use std::fmt::Debug;
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::grammar::GrammarTree;
+use crate::grammar::Id;
+
+/// Something that can be built out of a [GrammarTree].
+///
+/// Implementations should walk the tree with the [TreeCursor] they're given, so errors from deep
+/// inside the tree still say where they happened.
+pub trait ParseGrammarTree
+{
+    type Lang;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+        where Self: Sized;
+
+    /// Parses a whole tree, starting from its root.
+    fn parse_tree(tree: &GrammarTree<Self::Lang>) -> Result<Self, TreeShapeError>
+        where Self: Sized
+    {
+        Self::parse(&TreeCursor::new(tree))
+    }
+}
+
+/// Part of a [GrammarTree], as described in a [TreeShapeError].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TreeShape
+{
+    /// A node for this symbol.
+    Node(Id),
+    /// A node for any symbol.
+    AnyNode,
+    /// A leaf, written as the debug form of its token.
+    Leaf(String),
+    /// Anything else, like "a leaf that passes the check".
+    Described(&'static str),
+    /// Nothing at all, because a node didn't have enough children.
+    Nothing,
+}
+
+impl Display for TreeShape
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Node(id) => write!(f, "a {} node", id),
+            Self::AnyNode => write!(f, "a node"),
+            Self::Leaf(token) => write!(f, "the leaf {}", token),
+            Self::Described(description) => write!(f, "{}", description),
+            Self::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+/// A [GrammarTree] wasn't the shape a [ParseGrammarTree] implementation wanted.
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[error("Expected {expected}, found {found} at {}", path_string(.path))]
+pub struct TreeShapeError
+{
+    pub expected: TreeShape,
+    pub found: TreeShape,
+    /// The child indices to follow from the root to get to where it went wrong. Empty means the
+    /// root itself.
+    pub path: Vec<usize>,
+}
+
+fn path_string(path: &[usize]) -> String
+{
+    if path.is_empty()
+    {
+        return String::from("the root");
+    }
+    let indices: Vec<String> = path.iter().map(usize::to_string).collect();
+    format!("child {}", indices.join("."))
+}
+
+/// Points at a spot in a [GrammarTree] and remembers how it got there, so anything that goes wrong
+/// gets a [TreeShapeError::path] for free.
+#[derive(Debug, Clone)]
+pub struct TreeCursor<'t, L>
+{
+    tree: &'t GrammarTree<L>,
+    path: Vec<usize>,
+}
+
+impl<'t, L> TreeCursor<'t, L>
+{
+    /// A cursor at the root of `tree`.
+    pub fn new(tree: &'t GrammarTree<L>) -> Self
+    {
+        Self { tree, path: Vec::new() }
+    }
+
+    /// The subtree the cursor points at.
+    pub fn tree(&self) -> &'t GrammarTree<L>
+    {
+        self.tree
+    }
+
+    /// The child indices from the root to here.
+    pub fn path(&self) -> &[usize]
+    {
+        &self.path
+    }
+
+    /// An error at this spot in the tree. For the checks the cursor can't do itself.
+    pub fn error(&self, expected: TreeShape, found: TreeShape) -> TreeShapeError
+    {
+        TreeShapeError { expected, found, path: self.path.clone() }
+    }
+}
+
+impl<'t, L: Debug> TreeCursor<'t, L>
+{
+    /// What the cursor points at, for error messages.
+    pub fn shape(&self) -> TreeShape
+    {
+        match self.tree
+        {
+            GrammarTree::Leaf(token) => TreeShape::Leaf(format!("{:?}", token)),
+            GrammarTree::Node(node) => TreeShape::Node(node.symbol),
+        }
+    }
+
+    /// Checks that the cursor is on a node for `symbol`.
+    pub fn expect_node(&self, symbol: Id) -> Result<&Self, TreeShapeError>
+    {
+        match self.tree
+        {
+            GrammarTree::Node(node) if node.symbol == symbol => Ok(self),
+            _ => Err(self.error(TreeShape::Node(symbol), self.shape())),
+        }
+    }
+
+    /// A cursor on child `index` of this node.
+    pub fn child(&self, index: usize) -> Result<Self, TreeShapeError>
+    {
+        let GrammarTree::Node(node) = self.tree
+        else
+        {
+            return Err(self.error(TreeShape::AnyNode, self.shape()));
+        };
+
+        let mut path = self.path.clone();
+        path.push(index);
+        match node.children.get(index)
+        {
+            Some(child) => Ok(Self { tree: child, path }),
+            None => Err(TreeShapeError { expected: TreeShape::Described("another child"), found: TreeShape::Nothing, path }),
+        }
+    }
+
+    /// Cursors on every child of this node, in order.
+    pub fn children(&self) -> Result<Vec<Self>, TreeShapeError>
+    {
+        match self.tree
+        {
+            GrammarTree::Node(node) => (0..node.children.len()).map(|i| self.child(i)).collect(),
+            GrammarTree::Leaf(_) => Err(self.error(TreeShape::AnyNode, self.shape())),
+        }
+    }
+
+    /// Like [TreeCursor::children], but any child node for the same symbol as this one is replaced
+    /// by its own children. That way a list written with a recursive rule, like
+    /// `list -> list , item | item`, comes out flat and in order.
+    pub fn flat_children(&self) -> Result<Vec<Self>, TreeShapeError>
+    {
+        let symbol = match self.tree
+        {
+            GrammarTree::Node(node) => node.symbol,
+            GrammarTree::Leaf(_) => return Err(self.error(TreeShape::AnyNode, self.shape())),
+        };
+
+        let mut flat = Vec::new();
+        for child in self.children()?
+        {
+            match child.tree
+            {
+                GrammarTree::Node(node) if node.symbol == symbol => flat.extend(child.flat_children()?),
+                _ => flat.push(child),
+            }
+        }
+        Ok(flat)
+    }
+
+    /// The token, if the cursor is on a leaf that `predicate` accepts.
+    pub fn expect_leaf_with(&self, predicate: impl FnOnce(&L) -> bool) -> Result<&'t L, TreeShapeError>
+    {
+        match self.tree
+        {
+            GrammarTree::Leaf(token) if predicate(token) => Ok(token),
+            _ => Err(self.error(TreeShape::Described("a leaf that passes the check"), self.shape())),
+        }
+    }
+}
+
+/// Parses every child of the node at `cursor` as a `T`. Recursive lists are flattened like
+/// [TreeCursor::flat_children] does.
+///
+/// If an element fails, the error's path ends at the index of that child.
+pub fn parse_children<T>(cursor: &TreeCursor<'_, T::Lang>) -> Result<Vec<T>, TreeShapeError>
+    where T: ParseGrammarTree, T::Lang: Debug
+{
+    cursor.flat_children()?.iter().map(T::parse).collect()
+}
+
+/// Parses a list like `A, B, C`, where the elements are separated by leaves `is_separator`
+/// accepts. The list can't be empty, and it has to start and end with an element.
+pub fn parse_separated<T>(cursor: &TreeCursor<'_, T::Lang>, is_separator: impl Fn(&T::Lang) -> bool) -> Result<Vec<T>, TreeShapeError>
+    where T: ParseGrammarTree, T::Lang: Debug
+{
+    let children = cursor.flat_children()?;
+    let mut elements = Vec::new();
+    for (i, child) in children.iter().enumerate()
+    {
+        if i % 2 == 0
+        {
+            elements.push(T::parse(child)?);
+        }
+        else
+        {
+            child.expect_leaf_with(&is_separator)
+                .map_err(|x| TreeShapeError { expected: TreeShape::Described("a separator"), ..x })?;
+        }
+    }
+
+    match children.last()
+    {
+        None => Err(cursor.error(TreeShape::Described("an element"), TreeShape::Nothing)),
+        Some(last) if children.len() % 2 == 0 => Err(last.error(TreeShape::Described("an element after the separator"), TreeShape::Nothing)),
+        Some(_) => Ok(elements),
+    }
+}
+
+/// A node where every child is a `T`, like the lines of a program.
+impl<T> ParseGrammarTree for Vec<T>
+    where T: ParseGrammarTree, T::Lang: Debug
+{
+    type Lang = T::Lang;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        parse_children(cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::grammar::{GrammarBuilder, GrammarNodeData};
+
+    use super::*;
+
+    fn node(symbol: Id, children: Vec<GrammarTree<char>>) -> GrammarTree<char>
+    {
+        GrammarTree::Node(GrammarNodeData { symbol, children: children.into_iter().map(Box::new).collect() })
+    }
+
+    #[test]
+    fn test_cursor_tracks_the_path()
+    {
+        let mut builder = GrammarBuilder::<char>::new();
+        let outer = builder.id();
+        let inner = builder.id();
+        let tree = node(outer, vec![
+            GrammarTree::Leaf('a'),
+            node(inner, vec![GrammarTree::Leaf('b'), GrammarTree::Leaf('c')]),
+        ]);
+
+        let cursor = TreeCursor::new(&tree);
+        let second = cursor.expect_node(outer).unwrap().child(1).unwrap();
+        assert_eq!(second.expect_node(inner).unwrap().path(), &[1]);
+        assert_eq!(second.child(0).unwrap().expect_leaf_with(|x| *x == 'b'), Ok(&'b'));
+
+        assert_eq!(second.child(1).unwrap().expect_leaf_with(|x| *x == 'b'), Err(TreeShapeError
+        {
+            expected: TreeShape::Described("a leaf that passes the check"),
+            found: TreeShape::Leaf(String::from("'c'")),
+            path: vec![1, 1],
+        }));
+        assert_eq!(
+            second.child(2).err().unwrap().to_string(),
+            "Expected another child, found nothing at child 1.2"
+        );
+        assert_eq!(
+            cursor.expect_node(inner).err().unwrap().to_string(),
+            format!("Expected a {} node, found a {} node at the root", inner, outer)
+        );
+        assert_eq!(cursor.child(0).unwrap().child(0).err().unwrap().found, TreeShape::Leaf(String::from("'a'")));
+        assert_eq!(second.children().unwrap().len(), 2);
+    }
+
+    /// A digit leaf, for testing the list helpers.
+    #[derive(Debug, PartialEq)]
+    struct Digit(u32);
+
+    impl ParseGrammarTree for Digit
+    {
+        type Lang = char;
+
+        fn parse(cursor: &TreeCursor<'_, char>) -> Result<Self, TreeShapeError>
+        {
+            let c = cursor.expect_leaf_with(char::is_ascii_digit)?;
+            Ok(Digit(c.to_digit(10).unwrap()))
+        }
+    }
+
+    #[test]
+    fn test_list_helpers()
+    {
+        let mut builder = GrammarBuilder::<char>::new();
+        let list = builder.id();
+        let other = builder.id();
+        let leaves = |s: &str| s.chars().map(GrammarTree::Leaf).collect::<Vec<_>>();
+
+        let single = node(list, leaves("7"));
+        assert_eq!(Vec::<Digit>::parse_tree(&single), Ok(vec![Digit(7)]));
+        assert_eq!(parse_separated::<Digit>(&TreeCursor::new(&single), |x| *x == ','), Ok(vec![Digit(7)]));
+
+        // 1,2 then a nested list for ,3,4, the way a right recursive rule would build it.
+        let mut children = leaves("1,2,");
+        children.push(node(list, leaves("3,4")));
+        let four = node(list, children);
+        assert_eq!(
+            parse_separated::<Digit>(&TreeCursor::new(&four), |x| *x == ','),
+            Ok(vec![Digit(1), Digit(2), Digit(3), Digit(4)])
+        );
+
+        // The separators aren't digits, so every child has to be an element.
+        let error = Vec::<Digit>::parse_tree(&four).err().unwrap();
+        assert_eq!((error.found, error.path), (TreeShape::Leaf(String::from("','")), vec![1]));
+
+        // Element 3 is the second child of the nested list. A node for a different symbol isn't
+        // flattened, so it has to be an element too.
+        let bad = node(list, vec![GrammarTree::Leaf('1'), GrammarTree::Leaf(','), node(list, leaves("2,x"))]);
+        let error = parse_separated::<Digit>(&TreeCursor::new(&bad), |x| *x == ',').err().unwrap();
+        assert_eq!(error.to_string(), "Expected a leaf that passes the check, found the leaf 'x' at child 2.2");
+        let wrapped = node(list, vec![node(other, leaves("1")), GrammarTree::Leaf(',')]);
+        assert!(parse_separated::<Digit>(&TreeCursor::new(&wrapped), |x| *x == ',').is_err());
+
+        // The second comma is where an element should be.
+        let doubled = node(list, leaves("1,,2"));
+        let error = parse_separated::<Digit>(&TreeCursor::new(&doubled), |x| *x == ',').err().unwrap();
+        assert_eq!((error.found, error.path), (TreeShape::Leaf(String::from("','")), vec![2]));
+        let missing = node(list, leaves("12"));
+        let error = parse_separated::<Digit>(&TreeCursor::new(&missing), |x| *x == ',').err().unwrap();
+        assert_eq!((error.expected, error.path), (TreeShape::Described("a separator"), vec![1]));
+        assert!(parse_separated::<Digit>(&TreeCursor::new(&node(list, Vec::new())), |x| *x == ',').is_err());
+        let trailing = node(list, leaves("1,2,"));
+        let error = parse_separated::<Digit>(&TreeCursor::new(&trailing), |x| *x == ',').err().unwrap();
+        assert_eq!(error.to_string(), "Expected an element after the separator, found nothing at child 3");
+    }
+}
@@ -17,15 +17,17 @@
 //!               RUN
 //!               END
 //!
-//! expr-list ::= (string|expression) (, (string|expression) )*
+//! expr-list ::= (string|expression) ((,|;) (string|expression) )*
 //!
 //! var-list ::= var (, var)*
 //!
 //! expression ::= (+|-|ε) term ((+|-) term)*
 //!
-//! term ::= factor ((*|/) factor)*
+//! term ::= factor ((*|/|MOD) factor)*
 //!
-//! factor ::= var | number | (expression)
+//! factor ::= var | number | (expression) | function (expression)
+//!
+//! function ::= ABS | INT | RND
 //!
 //! var ::= A | B | C ... | Y | Z
 //!
@@ -38,19 +40,21 @@
 //! string ::= " ( |!|#|$ ... -|.|/|digit|: ... @|A|B|C ... |X|Y|Z)* "
 //!```
 
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::HashMap, fmt::Display, rc::Rc, str::FromStr};
 
-use derive_more::{Constructor, Into};
-use getset::CopyGetters;
-use thiserror::Error;
+use derive_more::Constructor;
+use getset::{CopyGetters, Getters};
 use anyhow::{anyhow, Result};
 
-use crate::lang::token::{Symbol, Token};
+use crate::lang::token::{Keyword, Symbol, Token};
+pub use crate::lang::token::{Variable, VariableFromU8Error};
 
 /// Represents a sequence of statements and associated metadata (line numbers)
+#[derive(Getters, Clone)]
 pub struct Program
 {
     /// The list of instructions in order.
+    #[getset(get = "pub")]
     instructions: Vec<Rc<Line>>,
     /// "Saved" or "bookmarked" lines with a reference to their stored location in [instructions]. 
     numbered_lines: HashMap<usize, Rc<Line>>,
@@ -82,18 +86,226 @@ impl Program
         }
         Ok(())
     }
+
+    /// Adds every line of `other` after the lines already here, in the same order.
+    ///
+    /// Like [Program::add_line], a line number that's already taken goes to the line from `other`
+    /// from now on, so [Program::line] and a `GOTO` find that one. The earlier line stays in
+    /// [Program::instructions], where it is.
+    pub fn merge(&mut self, other: Program) -> Result<()>
+    {
+        for line in other.instructions
+        {
+            if let Some(number) = line.line_number
+            {
+                self.numbered_lines.insert(number, line.clone());
+            }
+            self.instructions.push(line);
+        }
+        Ok(())
+    }
+
+    /// The line numbered `number`. If more than one line has that number, it's the one added last.
+    pub fn line(&self, number: usize) -> Option<&Line>
+    {
+        self.numbered_lines.get(&number).map(Rc::as_ref)
+    }
+
+    /// The index into [Program::instructions] of [Program::line].
+    pub fn line_index(&self, number: usize) -> Option<usize>
+    {
+        let line = self.numbered_lines.get(&number)?;
+        self.instructions.iter().position(|x| Rc::ptr_eq(x, line))
+    }
+
+    /// Counts up the lines and statements in the program.
+    pub fn statistics(&self) -> ProgramStatistics
+    {
+        let mut statistics = ProgramStatistics::default();
+        for line in &self.instructions
+        {
+            statistics.total_lines += 1;
+            match line.line_number
+            {
+                Some(num) =>
+                {
+                    statistics.numbered_lines += 1;
+                    statistics.max_line_number = statistics.max_line_number.max(Some(num));
+                },
+                None => statistics.unnumbered_lines += 1,
+            }
+
+            statistics.count_statement(&line.statement);
+        }
+        statistics
+    }
+
+    /// Splits every [Statement::Sequence] into a line for each of its statements, all with the
+    /// sequence's line number. Jumping to that number still goes to the first of them.
+    ///
+    /// A line that's also held onto outside the program (by cloning one of the [Rc]s from
+    /// [Program::instructions]) can't be taken apart, so it's left as it is.
+    pub fn flatten_sequences(&mut self)
+    {
+        let lines = std::mem::take(&mut self.instructions);
+        // Let go of our other copy of every line, so they can be taken apart.
+        self.numbered_lines.clear();
+
+        for line in lines
+        {
+            let pieces = match Rc::try_unwrap(line)
+            {
+                Ok(Line { line_number, statement: Statement::Sequence(statements) }) => {
+                    let mut statements = statements;
+                    flatten_statements(&mut statements);
+                    statements.into_iter().map(|x| Rc::new(Line::new(line_number, x))).collect()
+                },
+                Ok(line) => vec![Rc::new(line)],
+                Err(line) => vec![line],
+            };
+
+            if let Some(number) = pieces.first().and_then(|x| x.line_number)
+            {
+                self.numbered_lines.insert(number, pieces[0].clone());
+            }
+            self.instructions.extend(pieces);
+        }
+    }
+
+    /// The lines sorted by line number, the way `LIST` shows them. A line without a number stays
+    /// right after the numbered line it was added after (or at the top, if there wasn't one).
+    pub fn instructions_in_order(&self) -> Vec<&Line>
+    {
+        let mut last_number = None;
+        let mut lines: Vec<(Option<usize>, &Line)> = self.instructions.iter()
+            .map(|line|
+            {
+                last_number = line.line_number.or(last_number);
+                (last_number, line.as_ref())
+            })
+            .collect();
+        // The sort is stable, so lines with the same key keep the order they were added in.
+        lines.sort_by_key(|(number, _)| *number);
+        lines.into_iter().map(|(_, line)| line).collect()
+    }
+
+    /// Reads the file at `path` and parses it like [Program::from_str] does.
+    pub fn load_from_file(path: impl AsRef<std::path::Path>) -> Result<Program>
+    {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    /// Writes the program to the file at `path`, the way [Display] writes it.
+    pub fn save_to_file(&self, path: impl AsRef<std::path::Path>) -> Result<()>
+    {
+        std::fs::write(path, format!("{}", self))?;
+        Ok(())
+    }
+}
+
+impl FromStr for Program
+{
+    type Err = anyhow::Error;
+
+    /// Lexes and parses `source`, like [compile_str](crate::lang::compile_str).
+    fn from_str(source: &str) -> Result<Self>
+    {
+        crate::lang::compile_str(source)
+    }
+}
+
+impl Display for Program
+{
+    /// Writes the program the way `LIST` shows it, one line per line in order of line number.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        for line in self.instructions_in_order()
+        {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces any [Statement::Sequence]s in `statements` with the statements inside them, so
+/// sequences inside sequences come out flat too.
+fn flatten_statements(statements: &mut Vec<Statement>)
+{
+    let mut flat = Vec::new();
+    for statement in statements.drain(..)
+    {
+        match statement
+        {
+            Statement::Sequence(mut inner) => {
+                flatten_statements(&mut inner);
+                flat.extend(inner);
+            },
+            statement => flat.push(statement),
+        }
+    }
+    *statements = flat;
+}
+
+/// Summary of what's in a [Program], returned by [Program::statistics].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProgramStatistics
+{
+    pub total_lines: usize,
+    pub numbered_lines: usize,
+    pub unnumbered_lines: usize,
+    /// How many times each statement shows up, keyed by its keyword (`"PRINT"`, `"GOTO"`, ...).
+    /// Statements that never show up aren't in the map.
+    pub statement_counts: HashMap<&'static str, usize>,
+    pub max_line_number: Option<usize>,
+    pub uses_gosub: bool,
+    pub uses_if: bool,
+    pub uses_goto: bool,
+}
+
+impl ProgramStatistics
+{
+    /// Counts `statement`, or every statement in it if it's a [Statement::Sequence].
+    fn count_statement(&mut self, statement: &Statement)
+    {
+        if let Statement::Sequence(statements) = statement
+        {
+            for statement in statements
+            {
+                self.count_statement(statement);
+            }
+            return;
+        }
+
+        // The statement after a THEN counts too.
+        if let Statement::If(data) = statement
+        {
+            self.count_statement(&data.then);
+        }
+
+        let keyword = statement.keyword();
+        *self.statement_counts.entry(keyword.as_str()).or_default() += 1;
+        match keyword
+        {
+            Keyword::GoSub => self.uses_gosub = true,
+            Keyword::If => self.uses_if = true,
+            Keyword::Goto => self.uses_goto = true,
+            _ => (),
+        }
+    }
 }
 
 /// This node represents a line in BASIC.
-#[derive(CopyGetters, Constructor)]
+#[derive(Debug, PartialEq, Eq, CopyGetters, Getters, Constructor)]
 pub struct Line
 {
     #[getset(get_copy = "pub")]
     line_number: Option<usize>,
+    #[getset(get = "pub")]
     statement: Statement,
 }
 
 /// A single statement, which is one of the language's keywords plus any arguments it takes.
+#[derive(Debug, PartialEq, Eq)]
 pub enum Statement
 {
     Print(ExprList),
@@ -101,39 +313,248 @@ pub enum Statement
     Goto(Expression),
     Input(VariableList),
     Let(LetData),
+    /// `LET A$ = "HI"`, which sets a string variable. The parser only puts a string or a string
+    /// variable in `value`.
+    LetString { variable: Variable, value: ExprListItem },
     GoSub(Expression),
     Return,
     Clear,
     List,
     Run,
-    End
+    End,
+    /// `FOR I = 1 TO 10 STEP 2`, an extension to Tiny BASIC. Without a `STEP`, the step is 1.
+    For { variable: Variable, from: Expression, to: Expression, step: Option<Expression> },
+    /// `NEXT I`, which ends the `FOR` loop over `I`.
+    Next(Variable),
+    /// Several statements on one line, separated by `:`, like `PRINT A : END`. The parser only
+    /// makes these for two or more statements.
+    Sequence(Vec<Statement>),
+}
+
+impl Statement
+{
+    /// The keyword the statement starts with.
+    pub fn keyword(&self) -> Keyword
+    {
+        match self
+        {
+            Self::Print(_) => Keyword::Print,
+            Self::If(_) => Keyword::If,
+            Self::Goto(_) => Keyword::Goto,
+            Self::Input(_) => Keyword::Input,
+            Self::Let(_) | Self::LetString { .. } => Keyword::Let,
+            Self::GoSub(_) => Keyword::GoSub,
+            Self::Return => Keyword::Return,
+            Self::Clear => Keyword::Clear,
+            Self::List => Keyword::List,
+            Self::Run => Keyword::Run,
+            Self::End => Keyword::End,
+            Self::For { .. } => Keyword::For,
+            Self::Next(_) => Keyword::Next,
+            // Sequences don't have a keyword of their own, so they go by their first statement.
+            // An empty one doesn't do anything, so it might as well be an END.
+            Self::Sequence(statements) => statements.first().map_or(Keyword::End, Statement::keyword),
+        }
+    }
+}
+
+impl Display for Line
+{
+    /// Writes the line the way it would be typed in, like `10 PRINT "HI"`. Lines without a number
+    /// are just the statement.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self.line_number
+        {
+            Some(number) => write!(f, "{} {}", number, self.statement),
+            None => write!(f, "{}", self.statement),
+        }
+    }
+}
+
+impl Display for Statement
+{
+    /// Writes the statement as source code.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let keyword = self.keyword();
+        match self
+        {
+            Self::Print(list) => write!(f, "{} {}", keyword, list),
+            Self::If(data) => write!(f, "{} {} {} {} {} {}",
+                keyword, data.l_expression, data.relop, data.r_expression, Keyword::Then, data.then),
+            Self::Goto(expression) | Self::GoSub(expression) => write!(f, "{} {}", keyword, expression),
+            Self::Input(list) => write!(f, "{} {}", keyword, list),
+            Self::Let(data) => write!(f, "{} {} = {}", keyword, data.variable, data.expression),
+            Self::LetString { variable, value } => write!(f, "{} {}$ = {}", keyword, variable, value),
+            Self::Return | Self::Clear | Self::List | Self::Run | Self::End => write!(f, "{}", keyword),
+            Self::For { variable, from, to, step } => {
+                write!(f, "{} {} = {} {} {}", keyword, variable, from, Keyword::To, to)?;
+                match step
+                {
+                    Some(step) => write!(f, " {} {}", Keyword::Step, step),
+                    None => Ok(()),
+                }
+            },
+            Self::Next(variable) => write!(f, "{} {}", keyword, variable),
+            Self::Sequence(statements) => {
+                let statements: Vec<String> = statements.iter().map(Statement::to_string).collect();
+                write!(f, "{}", statements.join(" : "))
+            },
+        }
+    }
 }
 
+#[derive(Debug, PartialEq, Eq, Constructor)]
 pub struct VariableList
 {
     variable: Variable,
     cons: Vec<Variable>
 }
 
+impl VariableList
+{
+    /// Iterates over every variable in the list, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &Variable>
+    {
+        std::iter::once(&self.variable).chain(self.cons.iter())
+    }
+}
+
+impl Display for VariableList
+{
+    /// Writes the variables separated by commas, like `A, B, C`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let variables: Vec<String> = self.iter().map(Variable::to_string).collect();
+        write!(f, "{}", variables.join(", "))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct ExprList 
 {
     expression: ExprListItem,
-    cons: Vec<ExprListItem>,
+    cons: Vec<ExprListElement>,
+}
+
+impl ExprList
+{
+    /// Iterates over every item in the list, in order.
+    pub fn iter(&self) -> impl Iterator<Item = &ExprListItem>
+    {
+        std::iter::once(&self.expression).chain(self.cons.iter().map(ExprListElement::item))
+    }
 }
 
+impl Display for ExprList
+{
+    /// Writes the items with the separators they were written with, like `"A IS ", A; B`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.expression)?;
+        for element in &self.cons
+        {
+            write!(f, "{} {}", element.separator, element.item)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
+pub struct ExprListElement
+{
+    /// What came between this item and the one before it.
+    separator: PrintSeparator,
+    item: ExprListItem,
+}
+
+/// What goes between two items in a `PRINT`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PrintSeparator
+{
+    /// `,` prints a space between the items.
+    Comma,
+    /// `;` prints the items right next to each other.
+    Semicolon,
+}
+
+impl PrintSeparator
+{
+    /// The separator `token` is, if it's `,` or `;`.
+    pub fn from_token(token: &Token) -> Option<Self>
+    {
+        match token
+        {
+            Token::Symbol(Symbol::Comma) => Some(Self::Comma),
+            Token::Symbol(Symbol::Semicolon) => Some(Self::Semicolon),
+            _ => None,
+        }
+    }
+
+    /// What gets printed between the two items.
+    pub fn spacing(&self) -> &'static str
+    {
+        match self
+        {
+            Self::Comma => " ",
+            Self::Semicolon => "",
+        }
+    }
+}
+
+impl Display for PrintSeparator
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Comma => write!(f, ","),
+            Self::Semicolon => write!(f, ";"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub enum ExprListItem
 {
     String(String),
+    /// A string variable, like `A$`.
+    StringVar(Variable),
     Expression(Expression),
+    /// `TAB(n)`, which moves the output along to column `n`.
+    Tab(Expression),
 }
 
+impl Display for ExprListItem
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::String(string) => write!(f, "\"{}\"", string.replace('"', "\"\"")),
+            Self::StringVar(variable) => write!(f, "{}$", variable),
+            Self::Expression(expression) => write!(f, "{}", expression),
+            Self::Tab(expression) => write!(f, "{}({})", Keyword::Tab, expression),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct IfData
 {
     l_expression: Expression,
     relop: RelOpSymbol,
-    r_expression: Expression
+    r_expression: Expression,
+    /// The statement after the `THEN`, which can be another `IF`.
+    then: Box<Statement>,
 }
 
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct LetData
 {
     variable: Variable,
@@ -141,6 +562,8 @@ pub struct LetData
 }
 
 /// Represents an expression.
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct Expression 
 {
     /// An expression can start with a + or -
@@ -149,6 +572,8 @@ pub struct Expression
     cons: Vec<ExpressionElement>
 }
 
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct ExpressionElement
 {
     /// Elements with multiple terms must be combined with + or -
@@ -156,97 +581,243 @@ pub struct ExpressionElement
     term: Term,
 }
 
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct Term
 {
     factor: Factor,
     cons: Vec<TermElement>
 }
 
+#[derive(Debug, PartialEq, Eq, Getters, Constructor)]
+#[getset(get = "pub")]
 pub struct TermElement
 {
     prefix: TermPrefix,
     factor: Factor,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub enum Factor
 {
     Variable(Variable),
+    /// A string variable used as a number, which is always an error when it's run.
+    StringVariable(Variable),
     Number(usize),
     Expression(Box<Expression>),
+    /// A call to a built-in function, like `ABS(A-5)`.
+    FunctionCall { name: BuiltinFunction, arg: Box<Expression> },
+}
+
+/// The functions that can be called in an expression. They all take one argument.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BuiltinFunction
+{
+    /// The absolute value of the argument.
+    Abs,
+    /// Rounds the argument down to a whole number. Every number is whole already, so this doesn't
+    /// do anything, but older programs use it.
+    Int,
+    /// A random number from 0 up to (but not including) the argument.
+    Rnd,
+}
+
+impl BuiltinFunction
+{
+    /// The function a keyword names, if it names one.
+    pub fn from_keyword(keyword: Keyword) -> Option<Self>
+    {
+        match keyword
+        {
+            Keyword::Abs => Some(Self::Abs),
+            Keyword::Int => Some(Self::Int),
+            Keyword::Rnd => Some(Self::Rnd),
+            _ => None,
+        }
+    }
+
+    /// The keyword the function is called with.
+    pub fn keyword(&self) -> Keyword
+    {
+        match self
+        {
+            Self::Abs => Keyword::Abs,
+            Self::Int => Keyword::Int,
+            Self::Rnd => Keyword::Rnd,
+        }
+    }
+}
+
+impl Display for BuiltinFunction
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.keyword())
+    }
+}
+
+impl Display for Expression
+{
+    /// Writes the expression without any spaces, like `-A*2+(B-1)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        if let Some(prefix) = self.operator_prefix
+        {
+            write!(f, "{}", prefix)?;
+        }
+        write!(f, "{}", self.term)?;
+        for element in &self.cons
+        {
+            write!(f, "{}{}", element.operator_prefix, element.term)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Term
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.factor)?;
+        for element in &self.cons
+        {
+            write!(f, "{}{}", element.prefix, element.factor)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Factor
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Variable(variable) => write!(f, "{}", variable),
+            Self::StringVariable(variable) => write!(f, "{}$", variable),
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Expression(expression) => write!(f, "({})", expression),
+            Self::FunctionCall { name, arg } => write!(f, "{}({})", name, arg),
+        }
+    }
 }
 
 /// A + or - used to connect expression terms.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum ExpressionPrefix
 {
     Positive,
     Negative,
 }
 
-pub enum TermPrefix
+impl ExpressionPrefix
 {
-    Multiply,
-    Divide
+    /// The prefix `token` is, if it's `+` or `-`.
+    pub fn from_token(token: &Token) -> Option<Self>
+    {
+        match token
+        {
+            Token::Symbol(Symbol::Plus) => Some(Self::Positive),
+            Token::Symbol(Symbol::Minus) => Some(Self::Negative),
+            _ => None,
+        }
+    }
 }
 
-/// A variable is any single letter from A-Z.
-/// We'll convert it to 0-25 internally probably?
-#[derive(Debug, PartialEq, Eq, Into, Copy, Clone)]
-pub struct Variable(u8);
+impl Display for ExpressionPrefix
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            Self::Positive => write!(f, "+"),
+            Self::Negative => write!(f, "-"),
+        }
+    }
+}
 
-#[derive(Debug, Error)]
-pub enum VariableFromU8Error
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum TermPrefix
 {
-    #[error("Variable character out of range, must be an ASCII character between A and Z, upper case or lowercase.")]
-    CharacterOutOfRange,
+    Multiply,
+    Divide,
+    /// The remainder of dividing by the next factor, written `MOD`.
+    Mod,
 }
 
-impl TryFrom<u8> for Variable
+impl TermPrefix
 {
-    type Error = VariableFromU8Error;
+    /// The prefix `token` is, if it's `*`, `/` or `MOD`.
+    pub fn from_token(token: &Token) -> Option<Self>
+    {
+        match token
+        {
+            Token::Symbol(Symbol::Times) => Some(Self::Multiply),
+            Token::Symbol(Symbol::Divide) => Some(Self::Divide),
+            Token::Keyword(Keyword::Mod) => Some(Self::Mod),
+            _ => None,
+        }
+    }
+}
 
-    /// Attempts to convert a u8 into a [Variable].
-    ///
-    /// A u8 can only be converted into a [Variable] if it represents an ASCII character between
-    /// 'A' and 'Z' (inclusive) or 'a' and 'z' (inclusive). Otherwise the conversion failes and a
-    /// [VariableFromU8Error] is returned.
-    ///
-    /// # Arguments
-    ///
-    /// * `value` - A u8 representing a single ASCII character or byte. Must be a character
-    /// between 'A'-'Z' or 'a'-'z'
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # use tiny_basic_compiler::lang::ast::Variable;
-    /// # use tiny_basic_compiler::lang::ast::VariableFromU8Error;
-    /// let variable: Result<Variable, VariableFromU8Error> = b'A'.try_into();
-    /// assert!(variable.is_ok());
-    /// let variable = variable.unwrap();
-    /// let variable_u8: u8 = variable.into();
-    /// assert_eq!(variable_u8, 0);
-    /// let variable: Result<Variable, VariableFromU8Error> = 0.try_into();
-    /// assert!(variable.is_err());
-    /// ```
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value
+impl Display for TermPrefix
+{
+    /// `MOD` gets spaces around it, so it doesn't run into the factors on either side.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self
         {
-            x @ b'A'..=b'Z' => Ok(Self(x - b'A')),
-            x @ b'a'..=b'z' => Ok(Self(x - b'a')),
-            _ => Err(VariableFromU8Error::CharacterOutOfRange),
+            Self::Multiply => write!(f, "*"),
+            Self::Divide => write!(f, "/"),
+            Self::Mod => write!(f, " MOD "),
         }
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum RelOpSymbol
 {
     LessThan,
     LessThanOrEqual,
     Equal,
+    NotEqual,
     GreaterThan,
     GreaterThanOrEqual,
 }
 
+impl RelOpSymbol
+{
+    /// Applies the comparison to `lhs` and `rhs`, so `LessThan.evaluate(1, 2)` is `1 < 2`.
+    pub fn evaluate(self, lhs: i64, rhs: i64) -> bool
+    {
+        match self
+        {
+            Self::LessThan => lhs < rhs,
+            Self::LessThanOrEqual => lhs <= rhs,
+            Self::Equal => lhs == rhs,
+            Self::NotEqual => lhs != rhs,
+            Self::GreaterThan => lhs > rhs,
+            Self::GreaterThanOrEqual => lhs >= rhs,
+        }
+    }
+}
+
+impl Display for RelOpSymbol
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self
+        {
+            Self::LessThan => "<",
+            Self::LessThanOrEqual => "<=",
+            Self::Equal => "=",
+            Self::NotEqual => "<>",
+            Self::GreaterThan => ">",
+            Self::GreaterThanOrEqual => ">=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl TryFrom<&[Token]> for RelOpSymbol
 {
     type Error = anyhow::Error;
@@ -279,9 +850,282 @@ impl TryFrom<&[Symbol]> for RelOpSymbol
             [Symbol::LessThanSign] => Ok(Self::LessThan),
             [Symbol::LessThanSign, Symbol::EqualsSign] => Ok(Self::LessThanOrEqual),
             [Symbol::EqualsSign] => Ok(Self::Equal),
+            [Symbol::LessThanSign, Symbol::GreaterThanSign]
+            | [Symbol::GreaterThanSign, Symbol::LessThanSign] => Ok(Self::NotEqual),
             [Symbol::GreaterThanSign] => Ok(Self::GreaterThan),
             [Symbol::GreaterThanSign, Symbol::EqualsSign] => Ok(Self::GreaterThanOrEqual),
-            _ => Err(anyhow!("Expected &[Symbol] to match one of >, >=, =, <, <=")),
+            _ => Err(anyhow!("Expected &[Symbol] to match one of >, >=, =, <>, <, <=")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_relop_evaluate()
+    {
+        let cases = [
+            (RelOpSymbol::LessThan, [false, false, true]),
+            (RelOpSymbol::LessThanOrEqual, [false, true, true]),
+            (RelOpSymbol::Equal, [false, true, false]),
+            (RelOpSymbol::NotEqual, [true, false, true]),
+            (RelOpSymbol::GreaterThan, [true, false, false]),
+            (RelOpSymbol::GreaterThanOrEqual, [true, true, false]),
+        ];
+
+        for (relop, expected) in cases
+        {
+            assert_eq!(relop.evaluate(2, 1), expected[0], "2 {} 1", relop);
+            assert_eq!(relop.evaluate(1, 1), expected[1], "1 {} 1", relop);
+            assert_eq!(relop.evaluate(1, 2), expected[2], "1 {} 2", relop);
+        }
+    }
+
+    #[test]
+    fn test_relop_display_round_trips()
+    {
+        let relops = [
+            RelOpSymbol::LessThan,
+            RelOpSymbol::LessThanOrEqual,
+            RelOpSymbol::Equal,
+            RelOpSymbol::NotEqual,
+            RelOpSymbol::GreaterThan,
+            RelOpSymbol::GreaterThanOrEqual,
+        ];
+
+        for relop in relops
+        {
+            let symbols: Vec<Symbol> = relop.to_string().chars()
+                .map(|c| Symbol::try_from(c).unwrap())
+                .collect();
+            assert_eq!(RelOpSymbol::try_from(symbols.as_slice()).unwrap(), relop);
         }
     }
+
+    #[test]
+    fn test_variable_display()
+    {
+        assert_eq!(Variable::try_from(b'a').unwrap().to_string(), "A");
+        assert_eq!(Variable::try_from(b'Z').unwrap().to_string(), "Z");
+    }
+
+    #[test]
+    fn test_variable_from_str()
+    {
+        assert_eq!(Variable::try_from("A").unwrap(), Variable::try_from(b'A').unwrap());
+        assert_eq!(Variable::try_from("z").unwrap().to_char(), 'Z');
+        assert!(matches!(Variable::try_from(""), Err(VariableFromU8Error::WrongLength)));
+        assert!(matches!(Variable::try_from("AB"), Err(VariableFromU8Error::WrongLength)));
+        assert!(matches!(Variable::try_from("1"), Err(VariableFromU8Error::CharacterOutOfRange)));
+    }
+
+    #[test]
+    fn test_variable_all()
+    {
+        let letters: String = Variable::all().map(Variable::to_char).collect();
+        assert_eq!(letters, "ABCDEFGHIJKLMNOPQRSTUVWXYZ");
+        assert!(Variable::all().enumerate().all(|(i, x)| u8::from(x) as usize == i));
+    }
+
+    #[test]
+    fn test_variable_from_char()
+    {
+        assert_eq!(Variable::from_char('A'), Some(Variable::try_from(b'A').unwrap()));
+        assert_eq!(Variable::from_char('z').map(Variable::to_char), Some('Z'));
+        assert_eq!(Variable::from_char('1'), None);
+        assert_eq!(Variable::from_char('é'), None);
+    }
+
+    #[test]
+    fn test_variable_list_iter()
+    {
+        let a = Variable::from_char('A').unwrap();
+        let b = Variable::from_char('B').unwrap();
+        let c = Variable::from_char('C').unwrap();
+
+        let single = VariableList { variable: a, cons: Vec::new() };
+        assert_eq!(single.iter().copied().collect::<Vec<_>>(), vec![a]);
+
+        let multiple = VariableList { variable: a, cons: vec![b, c] };
+        assert_eq!(multiple.iter().copied().collect::<Vec<_>>(), vec![a, b, c]);
+    }
+
+    #[test]
+    fn test_expr_list_iter()
+    {
+        let string = |x: &str| ExprListItem::String(String::from(x));
+        let strings = |list: &ExprList| list.iter()
+            .map(|x| match x
+            {
+                ExprListItem::String(s) => s.clone(),
+                _ => panic!("Expected a string"),
+            })
+            .collect::<Vec<_>>();
+
+        let single = ExprList { expression: string("hi"), cons: Vec::new() };
+        assert_eq!(strings(&single), vec!["hi"]);
+
+        let multiple = ExprList
+        {
+            expression: string("a"),
+            cons: vec![
+                ExprListElement::new(PrintSeparator::Comma, string("b")),
+                ExprListElement::new(PrintSeparator::Semicolon, string("c")),
+            ],
+        };
+        assert_eq!(strings(&multiple), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_hello_world_statistics()
+    {
+        // 10 CLEAR
+        // 20 PRINT "What is your name?"
+        // 30 INPUT A
+        // 40 PRINT "Hello, ", A
+        // END
+        let a = Variable::from_char('A').unwrap();
+        let a_expression = || Expression::new(None, Term::new(Factor::Variable(a), Vec::new()), Vec::new());
+        let print = |items: Vec<ExprListItem>|
+        {
+            let mut items = items.into_iter();
+            let expression = items.next().unwrap();
+            let cons = items.map(|x| ExprListElement::new(PrintSeparator::Comma, x)).collect();
+            Statement::Print(ExprList { expression, cons })
+        };
+
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Clear)).unwrap();
+        program.add_line(Line::new(Some(20), print(vec![
+            ExprListItem::String(String::from("What is your name?")),
+        ]))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::Input(VariableList { variable: a, cons: Vec::new() }))).unwrap();
+        program.add_line(Line::new(Some(40), print(vec![
+            ExprListItem::String(String::from("Hello, ")),
+            ExprListItem::Expression(a_expression()),
+        ]))).unwrap();
+        program.add_line(Line::new(None, Statement::End)).unwrap();
+
+        assert_eq!(program.statistics(), ProgramStatistics
+        {
+            total_lines: 5,
+            numbered_lines: 4,
+            unnumbered_lines: 1,
+            statement_counts: HashMap::from([("CLEAR", 1), ("PRINT", 2), ("INPUT", 1), ("END", 1)]),
+            max_line_number: Some(40),
+            uses_gosub: false,
+            uses_if: false,
+            uses_goto: false,
+        });
+    }
+
+    #[test]
+    fn test_statement_display()
+    {
+        let a = Variable::from_char('A').unwrap();
+        let b = Variable::from_char('B').unwrap();
+        let variable = |x| Term::new(Factor::Variable(x), Vec::new());
+
+        // -A*2/(B-1)
+        let b_minus_one = Expression::new(None, variable(b), vec![
+            ExpressionElement::new(ExpressionPrefix::Negative, Term::new(Factor::Number(1), Vec::new())),
+        ]);
+        let expression = Expression::new(Some(ExpressionPrefix::Negative), Term::new(Factor::Variable(a), vec![
+            TermElement::new(TermPrefix::Multiply, Factor::Number(2)),
+            TermElement::new(TermPrefix::Divide, Factor::Expression(Box::new(b_minus_one))),
+        ]), Vec::new());
+        assert_eq!(expression.to_string(), "-A*2/(B-1)");
+
+        let if_statement = Statement::If(IfData::new(
+            Expression::new(None, variable(a), Vec::new()),
+            RelOpSymbol::GreaterThanOrEqual,
+            Expression::new(None, variable(b), Vec::new()),
+            Box::new(Statement::Goto(Expression::new(None, Term::new(Factor::Number(40), Vec::new()), Vec::new()))),
+        ));
+        assert_eq!(Line::new(Some(5), if_statement).to_string(), "5 IF A >= B THEN GOTO 40");
+
+        let input = Statement::Input(VariableList { variable: a, cons: vec![b] });
+        assert_eq!(Line::new(None, input).to_string(), "INPUT A, B");
+        assert_eq!(Statement::Return.to_string(), "RETURN");
+    }
+
+    #[test]
+    fn test_instructions_in_order()
+    {
+        let mut program = Program::new();
+        program.add_line(Line::new(None, Statement::Clear)).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Run)).unwrap();
+        program.add_line(Line::new(None, Statement::End)).unwrap();
+        program.add_line(Line::new(Some(10), Statement::Return)).unwrap();
+
+        let lines: Vec<String> = program.instructions_in_order().iter().map(|x| x.to_string()).collect();
+        assert_eq!(lines, vec!["CLEAR", "10 RETURN", "20 RUN", "END"]);
+    }
+
+    #[test]
+    fn test_empty_program_statistics()
+    {
+        assert_eq!(Program::new().statistics(), ProgramStatistics::default());
+    }
+
+    #[test]
+    fn test_flatten_sequences()
+    {
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Sequence(vec![
+            Statement::Clear,
+            Statement::Sequence(vec![Statement::List, Statement::Return]),
+        ]))).unwrap();
+        program.add_line(Line::new(None, Statement::Sequence(vec![Statement::Run, Statement::End]))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::End)).unwrap();
+        assert_eq!(program.statistics().statement_counts.get("RETURN"), Some(&1));
+
+        program.flatten_sequences();
+        let lines: Vec<String> = program.instructions().iter().map(|x| x.to_string()).collect();
+        assert_eq!(lines, vec!["10 CLEAR", "10 LIST", "10 RETURN", "RUN", "END", "20 END"]);
+
+        // Line 10 still starts at the CLEAR.
+        assert!(Rc::ptr_eq(&program.numbered_lines[&10], &program.instructions()[0]));
+        assert!(Rc::ptr_eq(&program.numbered_lines[&20], &program.instructions()[5]));
+        assert_eq!(program.statistics().total_lines, 6);
+    }
+
+    #[test]
+    fn test_save_and_load_file()
+    {
+        let path = std::env::temp_dir().join(format!("tiny-basic-{}-save.bas", std::process::id()));
+        let program: Program = "20 PRINT \"HI\", A\n10 LET A = 5\nCLEAR\n".parse().unwrap();
+        program.save_to_file(&path).unwrap();
+
+        let source = std::fs::read_to_string(&path).unwrap();
+        let loaded = Program::load_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        // Saved in order of line number, and the unnumbered line stays after 10.
+        assert_eq!(source, "10 LET A = 5\nCLEAR\n20 PRINT \"HI\", A\n");
+        assert_eq!(loaded.unwrap().to_string(), source);
+
+        assert!(Program::load_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_merge()
+    {
+        let mut program: Program = "10 PRINT 1\nCLEAR\n20 PRINT 2\n".parse().unwrap();
+        let other: Program = "20 PRINT 3\nLIST\n30 END\n".parse().unwrap();
+        program.merge(other).unwrap();
+
+        let lines: Vec<String> = program.instructions().iter().map(|x| x.to_string()).collect();
+        assert_eq!(lines, vec!["10 PRINT 1", "CLEAR", "20 PRINT 2", "20 PRINT 3", "LIST", "30 END"]);
+
+        // The later 20 wins.
+        assert_eq!(program.line(20).unwrap().to_string(), "20 PRINT 3");
+        assert_eq!(program.line_index(20), Some(3));
+        assert_eq!(program.line(10).unwrap().to_string(), "10 PRINT 1");
+        assert_eq!(program.line_index(30), Some(5));
+        assert!(program.line(40).is_none());
+    }
 }
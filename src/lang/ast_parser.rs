@@ -1,49 +1,448 @@
 //! Parses a [GrammarTree] into an [AST]
+//!
+//! None of these know the [Id](crate::grammar::Id)s of the grammar's symbols, so they go by the
+//! leaves instead. A node that's nothing but a wrapper around one child is looked through, and
+//! recursive lists are flattened with [TreeCursor::flat_children].
 
-use anyhow::anyhow;
-use anyhow::bail;
+use std::iter::Peekable;
+use std::slice::Iter;
 
-use crate::grammar::ParseGrammarTree;
+use crate::grammar::parse_separated;
 use crate::grammar::GrammarTree;
+use crate::grammar::ParseGrammarTree;
+use crate::grammar::TreeCursor;
+use crate::grammar::TreeShape;
+use crate::grammar::TreeShapeError;
+use crate::lang::token::{Keyword, Symbol};
 use crate::lang::Token;
 
 use super::ast::*;
 
-impl ParseGrammarTree for RelOpSymbol
+type Children<'c, 't> = Peekable<Iter<'c, TreeCursor<'t, Token>>>;
+
+/// The token, if `cursor` is on a leaf.
+fn leaf<'t>(cursor: &TreeCursor<'t, Token>) -> Option<&'t Token>
+{
+    match cursor.tree()
+    {
+        GrammarTree::Leaf(token) => Some(token),
+        GrammarTree::Node(_) => None,
+    }
+}
+
+/// The next child, which has to be there.
+fn next_child<'c, 't>(parent: &TreeCursor<'t, Token>, children: &mut Children<'c, 't>) -> Result<&'c TreeCursor<'t, Token>, TreeShapeError>
+{
+    children.next().ok_or_else(|| parent.error(TreeShape::Described("another child"), TreeShape::Nothing))
+}
+
+/// Turns the next child into a `T` with `from_token`, if it's a leaf that works for.
+fn next_leaf_as<T>(children: &mut Children<'_, '_>, from_token: impl Fn(&Token) -> Option<T>) -> Option<T>
+{
+    let value = leaf(children.peek()?).and_then(from_token)?;
+    children.next();
+    Some(value)
+}
+
+/// Turns `cursor` into a `T` with `from_token`, failing with `expected` if it can't.
+fn expect_leaf_as<T>(cursor: &TreeCursor<'_, Token>, expected: &'static str, from_token: impl Fn(&Token) -> Option<T>) -> Result<T, TreeShapeError>
+{
+    leaf(cursor)
+        .and_then(from_token)
+        .ok_or_else(|| cursor.error(TreeShape::Described(expected), cursor.shape()))
+}
+
+/// The leaf or node this is really about, looking through nodes with a single child.
+fn unwrap_single<'t>(cursor: &TreeCursor<'t, Token>) -> TreeCursor<'t, Token>
+{
+    let mut cursor = cursor.clone();
+    while let Ok(mut children) = cursor.children()
+    {
+        if children.len() != 1
+        {
+            break;
+        }
+        cursor = children.pop().unwrap();
+    }
+    cursor
+}
+
+/// Goes down through nodes with a single child until it gets to one with more, or to one whose
+/// only child is a leaf. Returns that node and its [flat children](TreeCursor::flat_children).
+fn descend<'t>(cursor: &TreeCursor<'t, Token>) -> Result<(TreeCursor<'t, Token>, Vec<TreeCursor<'t, Token>>), TreeShapeError>
+{
+    let mut cursor = cursor.clone();
+    loop
+    {
+        let mut children = cursor.flat_children()?;
+        if children.len() == 1 && leaf(&children[0]).is_none()
+        {
+            cursor = children.pop().unwrap();
+            continue;
+        }
+        return Ok((cursor, children));
+    }
+}
+
+/// Whether `cursor` is a leaf `from_token` accepts.
+fn is_leaf_of<T>(cursor: Option<&TreeCursor<'_, Token>>, from_token: impl Fn(&Token) -> Option<T>) -> bool
+{
+    cursor.and_then(leaf).and_then(from_token).is_some()
+}
+
+/// A single variable leaf.
+impl ParseGrammarTree for Variable
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        expect_leaf_as(&unwrap_single(cursor), "a variable", Token::as_variable)
+    }
+}
+
+/// Variables separated by commas, like `A, B, C`.
+impl ParseGrammarTree for VariableList
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let mut variables = parse_separated::<Variable>(cursor, |x| *x == Token::Symbol(Symbol::Comma))?;
+        let variable = variables.remove(0);
+        Ok(VariableList::new(variable, variables))
+    }
+}
+
+/// A string, a string variable, `TAB(expression)` or an expression.
+impl ParseGrammarTree for ExprListItem
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let single = unwrap_single(cursor);
+        match leaf(&single)
+        {
+            Some(Token::String(string)) => return Ok(ExprListItem::String(string.to_string())),
+            Some(Token::StringVariable(variable)) => return Ok(ExprListItem::StringVar(*variable)),
+            _ => (),
+        }
+
+        let children = single.flat_children().unwrap_or_default();
+        if children.len() == 4 && is_leaf_of(children.first(), |x| (*x == Token::Keyword(Keyword::Tab)).then_some(()))
+        {
+            expect_leaf_as(&children[1], "(", |x| (*x == Token::Symbol(Symbol::LeftParen)).then_some(()))?;
+            expect_leaf_as(&children[3], ")", |x| (*x == Token::Symbol(Symbol::RightParen)).then_some(()))?;
+            return Ok(ExprListItem::Tab(Expression::parse(&children[2])?));
+        }
+        Ok(ExprListItem::Expression(Expression::parse(cursor)?))
+    }
+}
+
+/// Items separated by `,` or `;`, like the list after a `PRINT`.
+impl ParseGrammarTree for ExprList
 {
     type Lang = Token;
 
-    fn parse(from: GrammarTree<Self::Lang>) -> anyhow::Result<Self>
-        where Self: Sized {
-            match from
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let children = cursor.flat_children()?;
+        let mut children = children.iter().peekable();
+
+        let expression = ExprListItem::parse(next_child(cursor, &mut children)?)?;
+        let mut cons = Vec::new();
+        while let Some(child) = children.next()
+        {
+            let separator = expect_leaf_as(child, ", or ;", PrintSeparator::from_token)?;
+            let item = ExprListItem::parse(next_child(cursor, &mut children)?)?;
+            cons.push(ExprListElement::new(separator, item));
+        }
+        Ok(ExprList::new(expression, cons))
+    }
+}
+
+/// An optional sign, then terms separated by `+` or `-`.
+impl ParseGrammarTree for Expression
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        if leaf(cursor).is_some()
+        {
+            return Ok(Expression::new(None, Term::parse(cursor)?, Vec::new()));
+        }
+
+        // Without a + or - anywhere, it's all one term.
+        let (cursor, children) = descend(cursor)?;
+        let is_expression = is_leaf_of(children.first(), ExpressionPrefix::from_token)
+            || is_leaf_of(children.get(1), ExpressionPrefix::from_token);
+        if !is_expression
+        {
+            return Ok(Expression::new(None, Term::parse(&cursor)?, Vec::new()));
+        }
+        let cursor = &cursor;
+        let mut children = children.iter().peekable();
+
+        let operator_prefix = next_leaf_as(&mut children, ExpressionPrefix::from_token);
+        let term = Term::parse(next_child(cursor, &mut children)?)?;
+        let mut cons = Vec::new();
+        while let Some(child) = children.next()
+        {
+            let operator_prefix = expect_leaf_as(child, "+ or -", ExpressionPrefix::from_token)?;
+            cons.push(ExpressionElement::new(operator_prefix, Term::parse(next_child(cursor, &mut children)?)?));
+        }
+        Ok(Expression::new(operator_prefix, term, cons))
+    }
+}
+
+/// Factors separated by `*`, `/` or `MOD`.
+impl ParseGrammarTree for Term
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        // A term that's just a number or a variable might not get a node of its own.
+        if leaf(cursor).is_some()
+        {
+            return Ok(Term::new(Factor::parse(cursor)?, Vec::new()));
+        }
+
+        let (cursor, children) = descend(cursor)?;
+        if !is_leaf_of(children.get(1), TermPrefix::from_token)
+        {
+            return Ok(Term::new(Factor::parse(&cursor)?, Vec::new()));
+        }
+        let cursor = &cursor;
+        let mut children = children.iter().peekable();
+
+        let factor = Factor::parse(next_child(cursor, &mut children)?)?;
+        let mut cons = Vec::new();
+        while let Some(child) = children.next()
+        {
+            let prefix = expect_leaf_as(child, "*, / or MOD", TermPrefix::from_token)?;
+            cons.push(TermElement::new(prefix, Factor::parse(next_child(cursor, &mut children)?)?));
+        }
+        Ok(Term::new(factor, cons))
+    }
+}
+
+/// A variable, a number, `(expression)` or a function call like `ABS(expression)`.
+impl ParseGrammarTree for Factor
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let cursor = unwrap_single(cursor);
+        if let Some(token) = leaf(&cursor)
+        {
+            return match token
             {
-                GrammarTree::Leaf(_) => bail!("Expected a tree node, got a leaf node"),
-                GrammarTree::Node(node) => {
-                    let iter = node.children().into_iter();
+                Token::Variable(variable) => Ok(Factor::Variable(*variable)),
+                Token::StringVariable(variable) => Ok(Factor::StringVariable(*variable)),
+                Token::Number(number) => Ok(Factor::Number(*number)),
+                _ => Err(cursor.error(TreeShape::Described("a variable or a number"), cursor.shape())),
+            };
+        }
+
+        let children = cursor.flat_children()?;
+        let name = match children.len()
+        {
+            3 => None,
+            4 => Some(expect_leaf_as(&children[0], "a function name", |x| x.as_keyword().copied().and_then(BuiltinFunction::from_keyword))?),
+            _ => return Err(cursor.error(TreeShape::Described("a parenthesized expression or a function call"), cursor.shape())),
+        };
+
+        // The parentheses are the last three children either way.
+        let rest = &children[children.len() - 3..];
+        expect_leaf_as(&rest[0], "(", |x| (*x == Token::Symbol(Symbol::LeftParen)).then_some(()))?;
+        let expression = Box::new(Expression::parse(&rest[1])?);
+        expect_leaf_as(&rest[2], ")", |x| (*x == Token::Symbol(Symbol::RightParen)).then_some(()))?;
+
+        Ok(match name
+        {
+            Some(name) => Factor::FunctionCall { name, arg: expression },
+            None => Factor::Expression(expression),
+        })
+    }
+}
+
+impl ParseGrammarTree for RelOpSymbol
+{
+    type Lang = Token;
+
+    /// A relational operator is a node with one or two symbol leaves, like `<` or `<=`.
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let symbols: Vec<Token> = cursor.children()?
+            .iter()
+            .map(|x| x.expect_leaf_with(Token::is_symbol).cloned())
+            .collect::<Result<_, _>>()?;
+
+        RelOpSymbol::try_from(symbols.as_slice())
+            .map_err(|_| cursor.error(TreeShape::Described("a relational operator"), TreeShape::Leaf(format!("{:?}", symbols))))
+    }
+}
+
+/// The parts of a statement, with the form it's being matched as so errors can say.
+struct StatementParts<'c, 't>
+{
+    cursor: &'c TreeCursor<'t, Token>,
+    parts: &'c [TreeCursor<'t, Token>],
+    form: &'static str,
+}
+
+impl<'c, 't> StatementParts<'c, 't>
+{
+    /// Part `index`, counting the keyword as 0, which has to be there.
+    fn get(&self, index: usize) -> Result<&'c TreeCursor<'t, Token>, TreeShapeError>
+    {
+        self.parts.get(index).ok_or_else(|| self.cursor.error(TreeShape::Described(self.form), TreeShape::Nothing))
+    }
+
+    /// Checks there's nothing after the first `count` parts.
+    fn end(&self, count: usize) -> Result<(), TreeShapeError>
+    {
+        match self.parts.get(count)
+        {
+            Some(extra) => Err(extra.error(TreeShape::Described(self.form), extra.shape())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A keyword and whatever goes with it. The statement after an `IF`'s `THEN` is parsed the same
+/// way, so `IF`s can be nested as deep as you like.
+///
+/// Errors point at the first part that doesn't fit, and say which form of statement it was
+/// trying to match if a part is missing or there's one too many.
+impl ParseGrammarTree for Statement
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        // A statement that's just a keyword might be nothing but the leaf.
+        let cursor = unwrap_single(cursor);
+        let parts = match leaf(&cursor)
+        {
+            Some(_) => vec![cursor.clone()],
+            None => cursor.children()?,
+        };
+        let first = parts.first().ok_or_else(|| cursor.error(TreeShape::Described("a statement keyword"), TreeShape::Nothing))?;
+        let keyword = expect_leaf_as(first, "a statement keyword", |x| x.as_keyword().copied())?;
+        let form = |form| StatementParts { cursor: &cursor, parts: &parts, form };
+
+        let (statement, parts, count) = match keyword
+        {
+            Keyword::Print => {
+                let parts = form("PRINT expr-list");
+                (Statement::Print(ExprList::parse(parts.get(1)?)?), parts, 2)
+            },
+            Keyword::If => {
+                let parts = form("IF expression relop expression THEN statement");
+                let l_expression = Expression::parse(parts.get(1)?)?;
+                let relop = RelOpSymbol::parse(parts.get(2)?)?;
+                let r_expression = Expression::parse(parts.get(3)?)?;
+                expect_leaf_as(parts.get(4)?, "THEN after the condition", |x| (*x == Token::Keyword(Keyword::Then)).then_some(()))?;
+                let then = Box::new(Statement::parse(parts.get(5)?)?);
+                (Statement::If(IfData::new(l_expression, relop, r_expression, then)), parts, 6)
+            },
+            Keyword::Goto => {
+                let parts = form("GOTO expression");
+                (Statement::Goto(Expression::parse(parts.get(1)?)?), parts, 2)
+            },
+            Keyword::GoSub => {
+                let parts = form("GOSUB expression");
+                (Statement::GoSub(Expression::parse(parts.get(1)?)?), parts, 2)
+            },
+            Keyword::Input => {
+                let parts = form("INPUT var-list");
+                (Statement::Input(VariableList::parse(parts.get(1)?)?), parts, 2)
+            },
+            Keyword::Let => {
+                let parts = form("LET variable = expression");
+                let variable = Variable::parse(parts.get(1)?)?;
+                expect_leaf_as(parts.get(2)?, "= after the variable", |x| (*x == Token::Symbol(Symbol::EqualsSign)).then_some(()))?;
+                (Statement::Let(LetData::new(variable, Expression::parse(parts.get(3)?)?)), parts, 4)
+            },
+            Keyword::Return => (Statement::Return, form("RETURN on its own"), 1),
+            Keyword::Clear => (Statement::Clear, form("CLEAR on its own"), 1),
+            Keyword::List => (Statement::List, form("LIST on its own"), 1),
+            Keyword::Run => (Statement::Run, form("RUN on its own"), 1),
+            Keyword::End => (Statement::End, form("END on its own"), 1),
+            _ => return Err(first.error(TreeShape::Described("a statement keyword"), first.shape())),
+        };
+        parts.end(count)?;
+        Ok(statement)
+    }
+}
 
-                    let symbols: anyhow::Result<Vec<Token>> = iter 
-                        .map(|x| match *x
-                        {
-                            // Retrieve the inner symbol
-                            GrammarTree::Leaf(token) => Ok(token),
-                            _ => Err(anyhow!("Expected a leaf node, got a tree node!")),
-                        }).collect();
+/// An optional line number, a statement and the newline after it. The newline can be left off,
+/// like on the last line of a file.
+impl ParseGrammarTree for Line
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let children = cursor.children()?;
+        let mut children = children.iter().peekable();
 
-                    // Return error early
-                    let symbols = symbols?;
-                    Ok(RelOpSymbol::try_from(symbols.as_slice())?)
-                }
-            }
+        let line_number = next_leaf_as(&mut children, Token::as_number);
+        let statement = Statement::parse(next_child(cursor, &mut children)?)?;
+        next_leaf_as(&mut children, |x| x.is_newline().then_some(()));
+        match children.next()
+        {
+            Some(extra) => Err(extra.error(TreeShape::Described("the end of the line"), extra.shape())),
+            None => Ok(Line::new(line_number, statement)),
+        }
+    }
+}
+
+/// One or more lines.
+impl ParseGrammarTree for Program
+{
+    type Lang = Token;
+
+    fn parse(cursor: &TreeCursor<'_, Self::Lang>) -> Result<Self, TreeShapeError>
+    {
+        let mut program = Program::new();
+        for line in Vec::<Line>::parse(cursor)?
+        {
+            // Adding a line can't fail.
+            let _ = program.add_line(line);
+        }
+        Ok(program)
     }
 }
 
 #[cfg(test)]
 mod tests
 {
-    use crate::grammar::{GrammarBuilder, GrammarNodeData};
+    use crate::grammar::{GrammarBuilder, GrammarTree, ParseEngine, RecursiveDescentEngine, Rule};
+    use crate::lang::token::Symbol;
 
     use super::*;
 
+    fn is_less_than(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::LessThanSign)
+    }
+
+    fn is_greater_than(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::GreaterThanSign)
+    }
+
+    fn is_equals(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::EqualsSign)
+    }
+
     #[test]
     fn test_relopsymbol()
     {
@@ -54,19 +453,286 @@ mod tests
 
         // Matches <=
         let leq_rule = Rule::new(rel_op_symbol)
-            .add_terminating_symbol(|x| x == Token::Symbol(Symbol::LessThanSign))
-            .add_terminating_symbol(|x| x == Token::Symbol(Symbol::Equals));
+            .add_terminating_symbol(&is_less_than)
+            .add_terminating_symbol(&is_equals);
 
         // Matches >=
         let geq_rule = Rule::new(rel_op_symbol)
-            .add_terminating_symbol(|x| x == Token::Symbol(Symbol::GreaterThanSign))
-            .add_terminating_symbol(|x| x == Token::Symbol(Symbol::Equals));
+            .add_terminating_symbol(&is_greater_than)
+            .add_terminating_symbol(&is_equals);
 
         let grammar = 
-            grammar_buider.add_rule(leq_rule)
+            grammar_builder.add_rule(leq_rule)
                       .add_rule(geq_rule)
-                      .build();
+                      .build()
+                      .unwrap();
+
+        let tree = grammar.parse(vec![
+            Token::Symbol(Symbol::GreaterThanSign),
+            Token::Symbol(Symbol::EqualsSign),
+        ]).unwrap();
 
+        let relop = RelOpSymbol::parse_tree(&tree);
+        assert!(matches!(relop, Ok(RelOpSymbol::GreaterThanOrEqual)));
     }
-}
 
+    #[test]
+    fn test_relopsymbol_errors()
+    {
+        let mut grammar_builder = GrammarBuilder::new();
+        let rel_op_symbol = grammar_builder.id();
+        grammar_builder
+            .rule(rel_op_symbol, |r| r.t(&is_equals).t(&is_less_than))
+            .rule(rel_op_symbol, |r| r.t(&is_equals).t(&Token::is_number));
+        let grammar = grammar_builder.build().unwrap();
+
+        // =< isn't an operator, even though both halves are symbols.
+        let tree = grammar.parse(vec![
+            Token::Symbol(Symbol::EqualsSign),
+            Token::Symbol(Symbol::LessThanSign),
+        ]).unwrap();
+        let error = RelOpSymbol::parse_tree(&tree).err().unwrap();
+        assert_eq!(error.expected, TreeShape::Described("a relational operator"));
+        assert!(error.path.is_empty());
+
+        // The number isn't a symbol at all, and the error points right at it.
+        let tree = grammar.parse(vec![Token::Symbol(Symbol::EqualsSign), Token::Number(5)]).unwrap();
+        let error = RelOpSymbol::parse_tree(&tree).err().unwrap();
+        assert_eq!(error.found, TreeShape::Leaf(String::from("Number(5)")));
+        assert_eq!(error.path, vec![1]);
+
+        // A bare leaf isn't a node.
+        let error = RelOpSymbol::parse_tree(&GrammarTree::Leaf(Token::NewLine)).err().unwrap();
+        assert_eq!(error.to_string(), "Expected a node, found the leaf NewLine at the root");
+    }
+
+    fn is_comma(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::Comma)
+    }
+
+    fn is_print_separator(token: &Token) -> bool
+    {
+        PrintSeparator::from_token(token).is_some()
+    }
+
+    fn is_expression_prefix(token: &Token) -> bool
+    {
+        ExpressionPrefix::from_token(token).is_some()
+    }
+
+    fn is_term_prefix(token: &Token) -> bool
+    {
+        TermPrefix::from_token(token).is_some()
+    }
+
+    fn is_function(token: &Token) -> bool
+    {
+        token.as_keyword().is_some_and(|x| BuiltinFunction::from_keyword(*x).is_some())
+    }
+
+    fn is_tab(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::Tab)
+    }
+
+    fn is_left_paren(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::LeftParen)
+    }
+
+    fn is_right_paren(token: &Token) -> bool
+    {
+        *token == Token::Symbol(Symbol::RightParen)
+    }
+
+    fn tokens(source: &str) -> Vec<Token>
+    {
+        crate::lang::create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn test_variable_list()
+    {
+        let (builder, _) = crate::grammar! {
+            list ::= list comma variable | variable;
+            terminal comma = is_comma;
+            terminal variable = Token::is_variable;
+        };
+        let grammar = builder.build().unwrap();
+
+        let list = VariableList::parse_tree(&grammar.parse(tokens("A")).unwrap()).unwrap();
+        assert_eq!(list.to_string(), "A");
+        let list = VariableList::parse_tree(&grammar.parse(tokens("A, B, C, Z")).unwrap()).unwrap();
+        assert_eq!(list.to_string(), "A, B, C, Z");
+    }
+
+    #[test]
+    fn test_variable_list_error_points_at_the_element()
+    {
+        let (builder, _) = crate::grammar! {
+            list ::= item comma list | item;
+            item ::= variable | number;
+            terminal comma = is_comma;
+            terminal variable = Token::is_variable;
+            terminal number = Token::is_number;
+        };
+        let grammar = builder.build().unwrap();
+        let engine = RecursiveDescentEngine::new(&grammar).unwrap();
+
+        // The 7 is the third item, two lists down.
+        let error = VariableList::parse_tree(&engine.parse_input(tokens("A, B, 7, D")).unwrap()).err().unwrap();
+        assert_eq!(error.found, TreeShape::Leaf(String::from("Number(7)")));
+        assert_eq!(error.to_string(), "Expected a variable, found the leaf Number(7) at child 2.2.0.0");
+    }
+
+    #[test]
+    fn test_expr_list()
+    {
+        // Right recursive, since the recursive descent engine can't do left recursion.
+        let (builder, _) = crate::grammar! {
+            list ::= item separator list | item;
+            item ::= string | expression;
+            expression ::= sign term sign expression | sign term | term sign expression | term;
+            term ::= factor times term | factor;
+            item ::= tab lparen expression rparen;
+            factor ::= function lparen expression rparen | lparen expression rparen | variable | number;
+            terminal tab = is_tab;
+            terminal function = is_function;
+            terminal lparen = is_left_paren;
+            terminal rparen = is_right_paren;
+            terminal separator = is_print_separator;
+            terminal string = Token::is_string;
+            terminal sign = is_expression_prefix;
+            terminal times = is_term_prefix;
+            terminal variable = Token::is_variable;
+            terminal number = Token::is_number;
+        };
+        let grammar = builder.build().unwrap();
+        let engine = RecursiveDescentEngine::new(&grammar).unwrap();
+        let parse = |source| ExprList::parse_tree(&engine.parse_input(tokens(source)).unwrap());
+
+        assert_eq!(parse("\"HI\"").unwrap().to_string(), "\"HI\"");
+        assert_eq!(parse("\"A IS \", A; 1 - 2 * B MOD 3, C").unwrap().to_string(), "\"A IS \", A; 1-2*B MOD 3, C");
+        assert_eq!(parse("-ABS(A - 1) * (2 + B)").unwrap().to_string(), "-ABS(A-1)*(2+B)");
+        assert_eq!(parse("1; TAB(A + 2); 3").unwrap().to_string(), "1; TAB(A+2); 3");
+    }
+
+    fn number(n: usize) -> Expression
+    {
+        Expression::new(None, Term::new(Factor::Number(n), Vec::new()), Vec::new())
+    }
+
+    fn variable(c: char) -> Expression
+    {
+        Expression::new(None, Term::new(Factor::Variable(Variable::from_char(c).unwrap()), Vec::new()), Vec::new())
+    }
+
+    /// Parses `source` as a one line program with the whole Tiny BASIC grammar, then turns it into
+    /// a statement.
+    fn parse_statement(source: &str) -> Result<Statement, TreeShapeError>
+    {
+        let engine = crate::lang::create_grammar();
+        Statement::parse_tree(&engine.parse_input(tokens(source)).unwrap())
+    }
+
+    #[test]
+    fn test_statement()
+    {
+        for source in [
+            "PRINT \"A IS \", A; TAB(5)",
+            "GOTO 10*A",
+            "GOSUB 100",
+            "INPUT A, B, C",
+            "LET A = -A+1",
+            "RETURN",
+            "CLEAR",
+            "LIST",
+            "RUN",
+            "END",
+        ]
+        {
+            assert_eq!(parse_statement(source).unwrap().to_string(), source);
+        }
+
+        assert_eq!(parse_statement("LET B = 5"), Ok(Statement::Let(LetData::new(Variable::from_char('B').unwrap(), number(5)))));
+    }
+
+    #[test]
+    fn test_nested_if()
+    {
+        let statement = parse_statement("IF A < 5 THEN IF B >= 2 THEN GOTO 10").unwrap();
+        let expected = Statement::If(IfData::new(variable('A'), RelOpSymbol::LessThan, number(5), Box::new(
+            Statement::If(IfData::new(variable('B'), RelOpSymbol::GreaterThanOrEqual, number(2), Box::new(
+                Statement::Goto(number(10)),
+            ))),
+        )));
+        assert_eq!(statement, expected);
+
+        let statement = parse_statement("IF A <> B THEN IF A = 1 THEN IF B > A THEN PRINT \"NO\"").unwrap();
+        assert_eq!(statement.to_string(), "IF A <> B THEN IF A = 1 THEN IF B > A THEN PRINT \"NO\"");
+    }
+
+    fn is_if(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::If)
+    }
+
+    fn is_then(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::Then)
+    }
+
+    fn is_let(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::Let)
+    }
+
+    fn is_goto(token: &Token) -> bool
+    {
+        *token == Token::Keyword(Keyword::Goto)
+    }
+
+    #[test]
+    fn test_statement_errors()
+    {
+        // Statements that are the wrong shape, which the real grammar wouldn't make.
+        let (builder, _) = crate::grammar! {
+            statement ::= if variable relop variable then statement
+                | if variable relop variable statement
+                | let variable variable
+                | goto variable variable
+                | goto;
+            relop ::= less;
+            terminal if = is_if;
+            terminal then = is_then;
+            terminal let = is_let;
+            terminal goto = is_goto;
+            terminal less = is_less_than;
+            terminal variable = Token::is_variable;
+        };
+        let grammar = builder.build().unwrap();
+        let engine = RecursiveDescentEngine::new(&grammar).unwrap();
+        let parse = |source| Statement::parse_tree(&engine.parse_input(tokens(source)).unwrap()).err().unwrap();
+
+        // Goes wrong at the GOTO, where the THEN should be.
+        let error = parse("IF A < B GOTO C D");
+        assert_eq!(error.expected, TreeShape::Described("THEN after the condition"));
+        assert_eq!(error.path, vec![4]);
+
+        let error = parse("LET A B");
+        assert_eq!(error.to_string(), "Expected = after the variable, found the leaf Variable(Variable(1)) at child 2");
+
+        let error = parse("GOTO");
+        assert_eq!(error.to_string(), "Expected GOTO expression, found nothing at child 0");
+
+        // Too many parts says which form it was matching.
+        let error = parse("GOTO A B");
+        assert_eq!(error.to_string(), "Expected GOTO expression, found the leaf Variable(Variable(1)) at child 2");
+
+        // The same again, one IF down.
+        let error = parse("IF A < B THEN IF C < D GOTO A B");
+        assert_eq!(error.expected, TreeShape::Described("THEN after the condition"));
+        assert_eq!(error.path, vec![5, 4]);
+    }
+}
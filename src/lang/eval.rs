@@ -0,0 +1,481 @@
+//! Evaluates [Expression]s down to numbers.
+//!
+//! All the arithmetic is checked by default, so a program that overflows gets a [RuntimeError]
+//! instead of a wrong answer (or a panic in debug builds). [WrappingMode::Wrapping] opts into
+//! wrapping around instead.
+
+use std::fmt::Display;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::lang::ast::{BuiltinFunction, Expression, ExpressionPrefix, Factor, Term, TermPrefix, Variable};
+use crate::lang::token::Keyword;
+
+/// An arithmetic operation, used to say which one went wrong.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ArithOp
+{
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// The remainder after dividing.
+    Modulo,
+    /// Unary minus.
+    Negate,
+}
+
+impl Display for ArithOp
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let s = match self
+        {
+            Self::Add => "+",
+            Self::Subtract | Self::Negate => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Modulo => "MOD",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// What happens when arithmetic overflows an [i64].
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum WrappingMode
+{
+    /// Overflow is a [RuntimeError::IntegerOverflow].
+    #[default]
+    Checked,
+    /// The result wraps around, like two's complement hardware does, so `i64::MAX + 1` is
+    /// `i64::MIN`. Dividing by zero is still an error.
+    Wrapping,
+}
+
+/// Errors that can happen while running a program.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RuntimeError
+{
+    /// The result didn't fit in an [i64]. For [ArithOp::Negate], `lhs` is always 0.
+    #[error("Integer overflow evaluating {lhs} {operation} {rhs}")]
+    IntegerOverflow { lhs: i64, rhs: i64, operation: ArithOp },
+    #[error("Division by zero")]
+    DivisionByZero,
+    /// A number literal in the program is too big to be an [i64].
+    #[error("The number {0} is too large")]
+    NumberTooLarge(usize),
+    /// A `NEXT` ran without a `FOR` loop over the same variable.
+    #[error("NEXT {0} without a matching FOR")]
+    NextWithoutFor(Variable),
+    /// A string variable was used where a number was needed.
+    #[error("{0}$ is a string, not a number")]
+    StringInExpression(Variable),
+    /// A [BuiltinFunction] was called with an argument it can't take, like `RND(0)`.
+    #[error("{function} can't take {value} as its argument")]
+    InvalidArgument { function: BuiltinFunction, value: i64 },
+    /// A `TAB` went past [MAX_TAB_COLUMN](crate::lang::interpreter::MAX_TAB_COLUMN).
+    #[error("TAB({0}) is past the last column, {max}", max = crate::lang::interpreter::MAX_TAB_COLUMN)]
+    TabOutOfRange(i64),
+    /// An `INPUT` ran out of input before every variable got a value.
+    #[error("Ran out of input")]
+    EndOfInput,
+    /// An `INPUT` got more lines that aren't numbers than it's allowed to retry, counting every
+    /// bad line since the `INPUT` started. Contains the last line it got.
+    #[error("Expected a number, got {0:?}")]
+    InvalidInput(String),
+    /// The program ran more lines than the
+    /// [Interpreter](crate::lang::interpreter::Interpreter::with_max_steps) allows. Contains how
+    /// many it ran.
+    #[error("Stopped after running {0} lines")]
+    StepLimitExceeded(usize),
+    /// A `GOTO` or `GOSUB` went to a line number that isn't in the program.
+    #[error("There's no line {0}")]
+    UndefinedLine(i64),
+    /// A `RETURN` ran without a `GOSUB` to go back to.
+    #[error("RETURN without a matching GOSUB")]
+    ReturnWithoutGosub,
+    /// The [Interpreter](crate::lang::interpreter::Interpreter) can't run this statement yet.
+    #[error("{0} isn't supported by the interpreter yet")]
+    Unsupported(Keyword),
+}
+
+/// A [RuntimeError] from [Interpreter::run](crate::lang::interpreter::Interpreter::run), along
+/// with the number of the line it happened on.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineError
+{
+    /// [None] if the line doesn't have a number.
+    pub line: Option<usize>,
+    pub error: RuntimeError,
+}
+
+impl std::error::Error for LineError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>
+    {
+        Some(&self.error)
+    }
+}
+
+impl Display for LineError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self.line
+        {
+            Some(line) => write!(f, "error on line {}: {}", line, self.error),
+            None => write!(f, "error: {}", self.error),
+        }
+    }
+}
+
+/// Evaluates `expression`, looking variables up in `variables` (A is index 0, Z is index 25).
+///
+/// `RND` gets a fresh [SmallRng] seeded with 0 every call. Use [eval_expression_with_rng] to
+/// keep a generator around between calls.
+pub fn eval_expression(expression: &Expression, variables: &[i64; 26]) -> Result<i64, RuntimeError>
+{
+    eval_expression_with_rng(expression, variables, &mut SmallRng::seed_from_u64(0))
+}
+
+/// Same as [eval_expression], but `RND` takes its numbers from `rng`.
+pub fn eval_expression_with_rng(expression: &Expression, variables: &[i64; 26], rng: &mut SmallRng) -> Result<i64, RuntimeError>
+{
+    eval_expression_with_mode(expression, variables, rng, WrappingMode::Checked)
+}
+
+/// Same as [eval_expression_with_rng], but overflow is handled the way `mode` says.
+pub fn eval_expression_with_mode(expression: &Expression, variables: &[i64; 26], rng: &mut SmallRng, mode: WrappingMode) -> Result<i64, RuntimeError>
+{
+    let mut value = eval_term(expression.term(), variables, rng, mode)?;
+    if let Some(ExpressionPrefix::Negative) = expression.operator_prefix()
+    {
+        value = negate(value, mode)?;
+    }
+
+    for element in expression.cons()
+    {
+        let rhs = eval_term(element.term(), variables, rng, mode)?;
+        let operation = match element.operator_prefix()
+        {
+            ExpressionPrefix::Positive => ArithOp::Add,
+            ExpressionPrefix::Negative => ArithOp::Subtract,
+        };
+        value = apply(value, rhs, operation, mode)?;
+    }
+
+    Ok(value)
+}
+
+fn eval_term(term: &Term, variables: &[i64; 26], rng: &mut SmallRng, mode: WrappingMode) -> Result<i64, RuntimeError>
+{
+    let mut value = eval_factor(term.factor(), variables, rng, mode)?;
+    for element in term.cons()
+    {
+        let rhs = eval_factor(element.factor(), variables, rng, mode)?;
+        let operation = match element.prefix()
+        {
+            TermPrefix::Multiply => ArithOp::Multiply,
+            TermPrefix::Divide => ArithOp::Divide,
+            TermPrefix::Mod => ArithOp::Modulo,
+        };
+        value = apply(value, rhs, operation, mode)?;
+    }
+    Ok(value)
+}
+
+fn eval_factor(factor: &Factor, variables: &[i64; 26], rng: &mut SmallRng, mode: WrappingMode) -> Result<i64, RuntimeError>
+{
+    match factor
+    {
+        Factor::Variable(variable) => Ok(variables[u8::from(*variable) as usize]),
+        Factor::StringVariable(variable) => Err(RuntimeError::StringInExpression(*variable)),
+        // Wrapping doesn't apply here. The number was never an i64 to begin with.
+        Factor::Number(number) => i64::try_from(*number).map_err(|_| RuntimeError::NumberTooLarge(*number)),
+        Factor::Expression(expression) => eval_expression_with_mode(expression, variables, rng, mode),
+        Factor::FunctionCall { name, arg } => {
+            let value = eval_expression_with_mode(arg, variables, rng, mode)?;
+            call(*name, value, rng, mode)
+        },
+    }
+}
+
+/// Calls a built-in function with an argument that's already been evaluated.
+fn call(function: BuiltinFunction, value: i64, rng: &mut SmallRng, mode: WrappingMode) -> Result<i64, RuntimeError>
+{
+    match function
+    {
+        BuiltinFunction::Abs if value < 0 => negate(value, mode),
+        BuiltinFunction::Abs | BuiltinFunction::Int => Ok(value),
+        BuiltinFunction::Rnd if value <= 0 => Err(RuntimeError::InvalidArgument { function, value }),
+        // The result is less than `value`, so it always fits.
+        BuiltinFunction::Rnd => Ok(rng.random_range(0..value)),
+    }
+}
+
+/// `-value`. Only [i64::MIN] can overflow.
+fn negate(value: i64, mode: WrappingMode) -> Result<i64, RuntimeError>
+{
+    match mode
+    {
+        WrappingMode::Checked => value.checked_neg()
+            .ok_or(RuntimeError::IntegerOverflow { lhs: 0, rhs: value, operation: ArithOp::Negate }),
+        WrappingMode::Wrapping => Ok(value.wrapping_neg()),
+    }
+}
+
+/// Applies a binary operation, checking for overflow (unless `mode` says to wrap) and division (or
+/// MOD) by zero.
+pub(crate) fn apply(lhs: i64, rhs: i64, operation: ArithOp, mode: WrappingMode) -> Result<i64, RuntimeError>
+{
+    if matches!(operation, ArithOp::Divide | ArithOp::Modulo) && rhs == 0
+    {
+        return Err(RuntimeError::DivisionByZero);
+    }
+
+    if mode == WrappingMode::Wrapping
+    {
+        return Ok(match operation
+        {
+            ArithOp::Add => lhs.wrapping_add(rhs),
+            ArithOp::Subtract => lhs.wrapping_sub(rhs),
+            ArithOp::Multiply => lhs.wrapping_mul(rhs),
+            ArithOp::Divide => lhs.wrapping_div(rhs),
+            ArithOp::Modulo => lhs.wrapping_rem(rhs),
+            ArithOp::Negate => unreachable!("negation isn't a binary operation"),
+        });
+    }
+
+    let result = match operation
+    {
+        ArithOp::Add => lhs.checked_add(rhs),
+        ArithOp::Subtract => lhs.checked_sub(rhs),
+        ArithOp::Multiply => lhs.checked_mul(rhs),
+        // Only i64::MIN / -1 can overflow.
+        ArithOp::Divide => lhs.checked_div(rhs),
+        // The result has the same sign as `lhs`, so -7 MOD 3 is -1.
+        ArithOp::Modulo => lhs.checked_rem(rhs),
+        ArithOp::Negate => unreachable!("negation isn't a binary operation"),
+    };
+    result.ok_or(RuntimeError::IntegerOverflow { lhs, rhs, operation })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::lang::ast::{ExpressionElement, TermElement, Variable};
+    use crate::lang::create_lexer;
+    use crate::lang::parser::Parser;
+    use crate::lang::token::Token;
+
+    use super::*;
+
+    fn number(n: usize) -> Term
+    {
+        Term::new(Factor::Number(n), Vec::new())
+    }
+
+    fn variable(c: char) -> Term
+    {
+        Term::new(Factor::Variable(Variable::from_char(c).unwrap()), Vec::new())
+    }
+
+    /// `lhs op rhs` where op is + or -.
+    fn sum(lhs: Term, operator_prefix: ExpressionPrefix, rhs: Term) -> Expression
+    {
+        Expression::new(None, lhs, vec![ExpressionElement::new(operator_prefix, rhs)])
+    }
+
+    /// Variables with A = i64::MAX, B = i64::MIN, C = -1 and the rest 0.
+    fn extreme_variables() -> [i64; 26]
+    {
+        let mut variables = [0; 26];
+        variables[0] = i64::MAX;
+        variables[1] = i64::MIN;
+        variables[2] = -1;
+        variables
+    }
+
+    #[test]
+    fn test_eval_arithmetic()
+    {
+        let mut variables = [0; 26];
+        variables[0] = 6;
+
+        // A * 7 - 2
+        let term = Term::new(Factor::Variable(Variable::from_char('A').unwrap()), vec![
+            TermElement::new(TermPrefix::Multiply, Factor::Number(7)),
+        ]);
+        let expression = sum(term, ExpressionPrefix::Negative, number(2));
+        assert_eq!(eval_expression(&expression, &variables), Ok(40));
+
+        // -(A / 4)
+        let term = Term::new(Factor::Variable(Variable::from_char('A').unwrap()), vec![
+            TermElement::new(TermPrefix::Divide, Factor::Number(4)),
+        ]);
+        let expression = Expression::new(Some(ExpressionPrefix::Negative), term, Vec::new());
+        assert_eq!(eval_expression(&expression, &variables), Ok(-1));
+    }
+
+    #[test]
+    fn test_eval_add_overflow()
+    {
+        let expression = sum(variable('A'), ExpressionPrefix::Positive, number(1));
+        assert_eq!(
+            eval_expression(&expression, &extreme_variables()),
+            Err(RuntimeError::IntegerOverflow { lhs: i64::MAX, rhs: 1, operation: ArithOp::Add })
+        );
+    }
+
+    #[test]
+    fn test_eval_subtract_overflow()
+    {
+        let expression = sum(variable('B'), ExpressionPrefix::Negative, number(1));
+        assert_eq!(
+            eval_expression(&expression, &extreme_variables()),
+            Err(RuntimeError::IntegerOverflow { lhs: i64::MIN, rhs: 1, operation: ArithOp::Subtract })
+        );
+    }
+
+    #[test]
+    fn test_eval_multiply_overflow()
+    {
+        let term = Term::new(Factor::Variable(Variable::from_char('A').unwrap()), vec![
+            TermElement::new(TermPrefix::Multiply, Factor::Number(2)),
+        ]);
+        let expression = Expression::new(None, term, Vec::new());
+        assert_eq!(
+            eval_expression(&expression, &extreme_variables()),
+            Err(RuntimeError::IntegerOverflow { lhs: i64::MAX, rhs: 2, operation: ArithOp::Multiply })
+        );
+    }
+
+    #[test]
+    fn test_eval_divide_overflow_and_zero()
+    {
+        let divide = |factor: Factor| Expression::new(None, Term::new(Factor::Variable(Variable::from_char('B').unwrap()), vec![
+            TermElement::new(TermPrefix::Divide, factor),
+        ]), Vec::new());
+
+        let expression = divide(Factor::Variable(Variable::from_char('C').unwrap()));
+        assert_eq!(
+            eval_expression(&expression, &extreme_variables()),
+            Err(RuntimeError::IntegerOverflow { lhs: i64::MIN, rhs: -1, operation: ArithOp::Divide })
+        );
+
+        let expression = divide(Factor::Number(0));
+        assert_eq!(eval_expression(&expression, &extreme_variables()), Err(RuntimeError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_eval_negate_overflow()
+    {
+        let expression = Expression::new(Some(ExpressionPrefix::Negative), variable('B'), Vec::new());
+        assert_eq!(
+            eval_expression(&expression, &extreme_variables()),
+            Err(RuntimeError::IntegerOverflow { lhs: 0, rhs: i64::MIN, operation: ArithOp::Negate })
+        );
+
+        let expression = Expression::new(Some(ExpressionPrefix::Negative), variable('A'), Vec::new());
+        assert_eq!(eval_expression(&expression, &extreme_variables()), Ok(-i64::MAX));
+    }
+
+    #[test]
+    fn test_eval_wrapping()
+    {
+        let wrapping = |expression: &Expression|
+        {
+            eval_expression_with_mode(expression, &extreme_variables(), &mut SmallRng::seed_from_u64(0), WrappingMode::Wrapping)
+        };
+
+        assert_eq!(wrapping(&sum(variable('A'), ExpressionPrefix::Positive, number(1))), Ok(i64::MIN));
+        assert_eq!(wrapping(&sum(variable('B'), ExpressionPrefix::Negative, number(1))), Ok(i64::MAX));
+        assert_eq!(wrapping(&Expression::new(Some(ExpressionPrefix::Negative), variable('B'), Vec::new())), Ok(i64::MIN));
+        assert_eq!(wrapping(&call_expression(BuiltinFunction::Abs, Expression::new(None, variable('B'), Vec::new()))), Ok(i64::MIN));
+
+        // B / C is i64::MIN / -1, and dividing by zero is still an error.
+        let divide = |factor| Expression::new(None, Term::new(Factor::Variable(Variable::from_char('B').unwrap()), vec![
+            TermElement::new(TermPrefix::Divide, factor),
+        ]), Vec::new());
+        assert_eq!(wrapping(&divide(Factor::Variable(Variable::from_char('C').unwrap()))), Ok(i64::MIN));
+        assert_eq!(wrapping(&divide(Factor::Number(0))), Err(RuntimeError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_eval_number_too_large()
+    {
+        let expression = Expression::new(None, number(usize::MAX), Vec::new());
+        assert_eq!(eval_expression(&expression, &[0; 26]), Err(RuntimeError::NumberTooLarge(usize::MAX)));
+    }
+
+    fn call_expression(name: BuiltinFunction, arg: Expression) -> Expression
+    {
+        Expression::new(None, Term::new(Factor::FunctionCall { name, arg: Box::new(arg) }, Vec::new()), Vec::new())
+    }
+
+    #[test]
+    fn test_eval_abs_and_int()
+    {
+        // ABS(2-7)
+        let arg = sum(number(2), ExpressionPrefix::Negative, number(7));
+        assert_eq!(eval_expression(&call_expression(BuiltinFunction::Abs, arg), &[0; 26]), Ok(5));
+
+        let arg = Expression::new(None, variable('A'), Vec::new());
+        assert_eq!(eval_expression(&call_expression(BuiltinFunction::Int, arg), &extreme_variables()), Ok(i64::MAX));
+
+        let arg = Expression::new(None, variable('B'), Vec::new());
+        assert_eq!(
+            eval_expression(&call_expression(BuiltinFunction::Abs, arg), &extreme_variables()),
+            Err(RuntimeError::IntegerOverflow { lhs: 0, rhs: i64::MIN, operation: ArithOp::Negate })
+        );
+    }
+
+    #[test]
+    fn test_eval_rnd()
+    {
+        let rnd = |n: usize| call_expression(BuiltinFunction::Rnd, Expression::new(None, number(n), Vec::new()));
+
+        let mut rng = SmallRng::seed_from_u64(42);
+        let numbers: Vec<i64> = (0..100)
+            .map(|_| eval_expression_with_rng(&rnd(10), &[0; 26], &mut rng).unwrap())
+            .collect();
+        assert!(numbers.iter().all(|x| (0..10).contains(x)));
+        // Something other than the first number comes up.
+        assert!(numbers.iter().any(|x| *x != numbers[0]));
+
+        // The same seed gives the same numbers.
+        let mut rng = SmallRng::seed_from_u64(42);
+        let again: Vec<i64> = (0..100)
+            .map(|_| eval_expression_with_rng(&rnd(10), &[0; 26], &mut rng).unwrap())
+            .collect();
+        assert_eq!(numbers, again);
+
+        assert_eq!(eval_expression(&rnd(1), &[0; 26]), Ok(0));
+        assert_eq!(
+            eval_expression(&rnd(0), &[0; 26]),
+            Err(RuntimeError::InvalidArgument { function: BuiltinFunction::Rnd, value: 0 })
+        );
+    }
+
+    /// Lexes, parses and evaluates `source` with every variable 0.
+    fn eval_source(source: &str) -> Result<i64, RuntimeError>
+    {
+        let tokens: Vec<Token> = create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap();
+        let expression = Parser::new(tokens).parse_expression().unwrap();
+        eval_expression(&expression, &[0; 26])
+    }
+
+    #[test]
+    fn test_eval_mod()
+    {
+        assert_eq!(eval_source("10 MOD 3"), Ok(1));
+        assert_eq!(eval_source("10 MOD 0"), Err(RuntimeError::DivisionByZero));
+        assert_eq!(eval_source("(-7) MOD 3"), Ok(-1));
+        assert_eq!(eval_source("7 MOD (-3)"), Ok(1));
+        // Same precedence as * and /, so this is (2*7) MOD 4.
+        assert_eq!(eval_source("2*7 mod 4"), Ok(2));
+    }
+}
@@ -0,0 +1,86 @@
+//! Converts lexed [Token]s into JSON so they can be handed off to tools that aren't written in
+//! Rust.
+//!
+//! Tokens go through their `Serialize` implementations, so each one is an externally tagged enum:
+//! variants with data become `{"Variant":data}` and variants without any become `"Variant"`.
+//! Variables are written as their letter.
+
+use serde::Serialize;
+
+use crate::lang::create_lexer;
+use crate::lang::token::Token;
+
+/// Writes `tokens` as a JSON array, like `[{"Number":10},{"Keyword":"Print"},"NewLine"]`.
+pub fn tokens_to_json(tokens: &[Token]) -> String
+{
+    // Tokens only hold strings and numbers, so this can't fail.
+    serde_json::to_string(tokens).unwrap()
+}
+
+/// What [lex_to_json] returns when lexing fails.
+#[derive(Serialize)]
+struct LexFailure
+{
+    error: String,
+    offset: usize,
+}
+
+/// Lexes `source` with the default lexer and returns the tokens as JSON, like [tokens_to_json].
+///
+/// If lexing fails, returns `{"error":"message","offset":143}` instead, where `offset` is the byte
+/// in `source` the broken token starts at. Meant for callers on the other side of an FFI boundary
+/// (like a browser) that can only take a string back.
+pub fn lex_to_json(source: &str) -> String
+{
+    match create_lexer().parse_stream(source).collect::<Result<Vec<Token>, _>>()
+    {
+        Ok(tokens) => tokens_to_json(&tokens),
+        Err(error) => {
+            let failure = LexFailure { error: error.to_string(), offset: error.offset() };
+            serde_json::to_string(&failure).unwrap()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_tokens_to_json_hello_world()
+    {
+        let tokens: Vec<Token> = create_lexer()
+            .parse_stream("10 PRINT \"Hello, world\"\n20 LET A = 5\n")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let json = tokens_to_json(&tokens);
+
+        assert!(json.starts_with("[{\"Number\":10},{\"Keyword\":\"Print\"},{\"String\":\"Hello, world\"},\"NewLine\","));
+        assert!(json.contains("{\"Keyword\":\"Let\"},{\"Variable\":\"A\"},{\"Symbol\":\"EqualsSign\"},{\"Number\":5}"));
+        assert!(json.ends_with(",\"NewLine\"]"));
+    }
+
+    #[test]
+    fn test_tokens_to_json_escapes_strings()
+    {
+        let tokens = [Token::String("say \"hi\"\\".into())];
+        assert_eq!(tokens_to_json(&tokens), "[{\"String\":\"say \\\"hi\\\"\\\\\"}]");
+    }
+
+    #[test]
+    fn test_tokens_to_json_empty()
+    {
+        assert_eq!(tokens_to_json(&[]), "[]");
+    }
+
+    #[test]
+    fn test_lex_to_json()
+    {
+        assert_eq!(lex_to_json("10 END\n"), "[{\"Number\":10},{\"Keyword\":\"End\"},\"NewLine\"]");
+        assert_eq!(
+            lex_to_json("10 PRINT \"oops"),
+            "{\"error\":\"Expected closing \\\" character!\",\"offset\":9}"
+        );
+    }
+}
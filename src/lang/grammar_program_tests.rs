@@ -0,0 +1,130 @@
+#![cfg(test)]
+//! Tests the grammar on an entire program.
+
+use crate::grammar::{GrammarTree, ParseEngine, TreeCursor};
+use crate::lang::{compile, create_grammar, create_grammar_with_symbols, create_lexer, CompileError};
+use crate::lang::parser::Parser;
+use crate::lang::token::Token;
+
+fn tokens(source: &str) -> Vec<Token>
+{
+    create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn test_grammar_on_hello_world()
+{
+    let (engine, symbols) = create_grammar_with_symbols();
+    let tree = engine.parse_input(tokens("10 CLEAR\n\
+                                          20 PRINT \"What is your name?\"\n\
+                                          30 INPUT A\n\
+                                          40 PRINT \"Hello, \", A")).unwrap();
+
+    let cursor = TreeCursor::new(&tree);
+    cursor.expect_node(symbols.program).unwrap();
+
+    // The recursive program nodes flatten out into one line each.
+    let lines = cursor.flat_children().unwrap();
+    assert_eq!(lines.len(), 4);
+    assert!(lines.iter().all(|x| x.expect_node(symbols.line).is_ok()));
+}
+
+#[test]
+fn test_grammar_on_every_statement()
+{
+    let (engine, symbols) = create_grammar_with_symbols();
+    let source = "10 PRINT \"A IS \"; A, TAB(5); -ABS(A - 1) * (2 + B) MOD 3\n\
+                  20 IF A <> 5 THEN IF B >= 2 THEN GOTO 10\n\
+                  30 INPUT A, B, C\n\
+                  40 LET A = A + 1\n\
+                  50 GOSUB A * 10 + 5\n\
+                  RETURN\n\
+                  CLEAR\n\
+                  LIST\n\
+                  RUN\n\
+                  END\n";
+
+    let tree = engine.parse_input(tokens(source)).unwrap();
+    assert!(matches!(tree, GrammarTree::Node(node) if node.symbol() == symbols.program));
+}
+
+#[test]
+fn test_grammar_rejects_missing_then()
+{
+    let engine = create_grammar();
+
+    assert!(engine.parse_input(tokens("10 CLEAR\n20 IF A < 5 PRINT A\n")).is_err());
+    assert!(engine.parse_input(tokens("10 INPUT A,\n")).is_err());
+}
+
+#[test]
+fn test_compile_hello_world()
+{
+    let program = compile("10 CLEAR\n\
+                           20 PRINT \"What is your name?\"\n\
+                           30 INPUT A\n\
+                           40 PRINT \"Hello, \", A").unwrap();
+
+    let numbers: Vec<Option<usize>> = program.instructions().iter().map(|x| x.line_number()).collect();
+    assert_eq!(numbers, vec![Some(10), Some(20), Some(30), Some(40)]);
+    assert_eq!(program.to_string(), "10 CLEAR\n\
+                                     20 PRINT \"What is your name?\"\n\
+                                     30 INPUT A\n\
+                                     40 PRINT \"Hello, \", A\n");
+}
+
+#[test]
+fn test_compile_skips_blank_lines()
+{
+    let program = compile("\n10 IF A < 5 THEN IF B > 1 THEN GOTO 40\n\nRETURN\n").unwrap();
+    assert_eq!(program.to_string(), "10 IF A < 5 THEN IF B > 1 THEN GOTO 40\nRETURN\n");
+}
+
+#[test]
+fn test_compile_errors()
+{
+    let error = compile("10 CLEAR\n20 IF A < 5 PRINT A\n30 END\n").err().unwrap();
+    assert!(matches!(error, CompileError::Parse { line: 2, .. }));
+    assert!(error.to_string().starts_with("Couldn't parse line 2: "), "{}", error);
+
+    let error = compile("10 PRINT \"oops\n").err().unwrap();
+    assert!(matches!(error, CompileError::Lex(_)));
+    assert!(error.to_string().starts_with("Couldn't lex the source at offset 9: "), "{}", error);
+}
+
+#[test]
+fn test_compile_parses_lines_as_they_are_lexed()
+{
+    // Line 1 is parsed before the lexer ever gets to the bad string on line 2.
+    let error = compile("10 IF A PRINT A
+20 PRINT \"oops\n").err().unwrap();
+    assert!(matches!(error, CompileError::Parse { line: 1, .. }));
+
+    let error = compile("10 CLEAR
+20 PRINT \"oops\n").err().unwrap();
+    assert!(matches!(error, CompileError::Lex(_)));
+}
+
+#[test]
+fn test_grammar_and_hand_parser_agree()
+{
+    // Only statements both of them know about.
+    let source = "10 PRINT \"A IS \"; A, TAB(5); -ABS(A - 1) * (2 + B) MOD 3\n\
+                  20 IF A <> 5 THEN IF B >= 2 THEN LET C = A / (B - 1)\n\
+                  30 LET A = -A + 2 * B - RND(10)\n\
+                  CLEAR\n\
+                  40 IF (A + 1) * 2 < B THEN PRINT A; B\n\
+                  RETURN\n\
+                  50 INPUT A, B\n\
+                  60 GOSUB A * 10 + 5\n\
+                  GOTO 10\n\
+                  LIST\n\
+                  RUN\n\
+                  END\n";
+
+    let from_grammar = compile(source).unwrap();
+    let from_parser = Parser::new(tokens(source)).parse().unwrap();
+
+    assert_eq!(from_grammar.instructions().len(), 12);
+    assert_eq!(from_grammar.instructions(), from_parser.instructions());
+}
@@ -0,0 +1,1110 @@
+//! Runs [Program]s one statement at a time.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use getset::{CopyGetters, Getters};
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::lang::ast::{ExprListItem, Expression, Program, Statement, Variable, VariableList};
+use crate::lang::eval::{apply, eval_expression_with_mode, ArithOp, LineError, RuntimeError, WrappingMode};
+
+/// Where a running program's output goes, and where `INPUT` reads from.
+pub trait BasicIO
+{
+    /// Writes `text` exactly as it is. Nothing gets added, not even a newline.
+    fn print(&mut self, text: &str);
+
+    /// Reads a line of input, without the newline. [None] means there's nothing left to read,
+    /// which is all there is by default.
+    fn read_line(&mut self) -> Option<String>
+    {
+        None
+    }
+
+    /// Prints spaces until the output is at `column` (counting from 0), then prints `text`. If
+    /// it's already at or past `column`, `text` is printed right where it is.
+    ///
+    /// By default we don't know what column we're at, so `text` is just printed.
+    fn print_at_column(&mut self, _column: usize, text: &str)
+    {
+        self.print(text);
+    }
+}
+
+/// The furthest column a `TAB` can go to. Anything past it is a [RuntimeError::TabOutOfRange],
+/// instead of printing (and allocating) as many spaces as the program asks for.
+pub const MAX_TAB_COLUMN: usize = 255;
+
+/// Prints `text` after enough spaces to get from `current` to `column`.
+fn print_padded(io: &mut impl BasicIO, current: usize, column: usize, text: &str)
+{
+    io.print(&format!("{}{}", " ".repeat(column.saturating_sub(current)), text));
+}
+
+/// The column the output is at after printing `text`, starting from `column`.
+fn column_after(column: usize, text: &str) -> usize
+{
+    match text.rsplit_once('\n')
+    {
+        Some((_, last_line)) => last_line.chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+/// A [BasicIO] that keeps all the output in a [String] and reads input from a list of lines
+/// given up front. Handy for tests.
+#[derive(Debug, Default, Getters)]
+pub struct MemoryBasicIO
+{
+    /// Everything printed so far.
+    #[getset(get = "pub")]
+    output: String,
+    /// The lines that haven't been read yet.
+    input: VecDeque<String>,
+}
+
+impl MemoryBasicIO
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Creates an IO that hands out `lines` to `INPUT`, one at a time.
+    pub fn with_input<S: Into<String>>(lines: impl IntoIterator<Item = S>) -> Self
+    {
+        Self { input: lines.into_iter().map(Into::into).collect(), ..Self::default() }
+    }
+}
+
+impl BasicIO for MemoryBasicIO
+{
+    fn print(&mut self, text: &str)
+    {
+        self.output.push_str(text);
+    }
+
+    fn read_line(&mut self) -> Option<String>
+    {
+        self.input.pop_front()
+    }
+
+    fn print_at_column(&mut self, column: usize, text: &str)
+    {
+        let current = column_after(0, &self.output);
+        print_padded(self, current, column, text);
+    }
+}
+
+/// A [BasicIO] for the terminal, which prints to stdout and reads from stdin.
+#[derive(Debug, Default)]
+pub struct StdioBasicIO
+{
+    /// The column the cursor's at, so `TAB` knows how far to go.
+    column: usize,
+}
+
+impl StdioBasicIO
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+}
+
+impl BasicIO for StdioBasicIO
+{
+    fn print(&mut self, text: &str)
+    {
+        let mut stdout = std::io::stdout().lock();
+        // Flushed straight away, so a prompt shows up before we wait for input. There's nowhere
+        // to report a broken stdout, so errors are ignored.
+        let _ = stdout.write_all(text.as_bytes()).and_then(|_| stdout.flush());
+        self.column = column_after(self.column, text);
+    }
+
+    fn read_line(&mut self) -> Option<String>
+    {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line)
+        {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                // The user pressed enter, so we're at the start of a line again.
+                self.column = 0;
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                Some(line.strip_suffix('\r').unwrap_or(line).to_string())
+            },
+        }
+    }
+
+    fn print_at_column(&mut self, column: usize, text: &str)
+    {
+        print_padded(self, self.column, column, text);
+    }
+}
+
+/// A [BasicIO] on top of any [Write] and [BufRead], like a file and a [Vec<u8>]. See
+/// [Program::run_to_writer].
+#[derive(Debug)]
+pub struct WriterBasicIO<W, R>
+{
+    writer: W,
+    reader: R,
+    /// The column the output is at, so `TAB` knows how far to go.
+    column: usize,
+    /// The first error writing to `writer`. [BasicIO::print] can't report it, so it's kept for
+    /// [WriterBasicIO::finish].
+    error: Option<std::io::Error>,
+}
+
+impl<W: Write, R: BufRead> WriterBasicIO<W, R>
+{
+    pub fn new(writer: W, reader: R) -> Self
+    {
+        Self { writer, reader, column: 0, error: None }
+    }
+
+    /// Flushes the writer and gives it back, or the first error writing to it.
+    pub fn finish(mut self) -> std::io::Result<W>
+    {
+        match self.error
+        {
+            Some(error) => Err(error),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+}
+
+impl<W: Write, R: BufRead> BasicIO for WriterBasicIO<W, R>
+{
+    /// Once a write fails, nothing else gets written.
+    fn print(&mut self, text: &str)
+    {
+        if self.error.is_none()
+        {
+            self.error = self.writer.write_all(text.as_bytes()).err();
+            self.column = column_after(self.column, text);
+        }
+    }
+
+    fn read_line(&mut self) -> Option<String>
+    {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line)
+        {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                Some(line.strip_suffix('\r').unwrap_or(line).to_string())
+            },
+        }
+    }
+
+    fn print_at_column(&mut self, column: usize, text: &str)
+    {
+        print_padded(self, self.column, column, text);
+    }
+}
+
+/// Runs statements against a [Program], sending anything they print to `IO`.
+#[derive(Getters, CopyGetters)]
+pub struct Interpreter<IO>
+{
+    #[getset(get = "pub")]
+    program: Program,
+    #[getset(get = "pub")]
+    io: IO,
+    /// The values of A-Z, in order.
+    #[getset(get = "pub")]
+    variables: [i64; 26],
+    /// The values of A$-Z$, in order.
+    #[getset(get = "pub")]
+    string_variables: [String; 26],
+    /// Where each `GOSUB` that hasn't returned yet should come back to.
+    #[getset(get = "pub")]
+    call_stack: Vec<usize>,
+    /// The `FOR` loops that are running, innermost last. Each one is the loop variable, the limit,
+    /// the step, and the program counter and statement counter to go back to.
+    #[getset(get = "pub")]
+    for_stack: Vec<(Variable, i64, i64, usize, usize)>,
+    /// The index into [Program::instructions] of the next line to run.
+    #[getset(get_copy = "pub")]
+    program_counter: usize,
+    /// Where to pick up partway through a line of statements separated by `:`, like after a `FOR`
+    /// that isn't the last thing on its line. If it isn't 0, the next line to run is the one
+    /// before the program counter, starting from this statement.
+    #[getset(get_copy = "pub")]
+    statement_counter: usize,
+    /// Whether the last statement moved the program counter, so the rest of its line is skipped.
+    jumped: bool,
+    /// Where `RND` gets its numbers from.
+    rng: SmallRng,
+    /// How many times an `INPUT` asks again after a line that isn't a number before giving up.
+    #[getset(get_copy = "pub")]
+    max_input_retries: usize,
+    /// How many lines [Interpreter::run] can run before it gives up. [None] means there's no limit.
+    #[getset(get_copy = "pub")]
+    max_steps: Option<usize>,
+    /// How many lines have been run so far.
+    #[getset(get_copy = "pub")]
+    steps: usize,
+    /// What arithmetic does when it overflows.
+    #[getset(get_copy = "pub")]
+    wrapping_mode: WrappingMode,
+}
+
+impl<IO: BasicIO> Interpreter<IO>
+{
+    /// Creates an interpreter that's ready to run `program` from the top. `RND` is seeded from the
+    /// clock, so it's different every run.
+    pub fn new(program: Program, io: IO) -> Self
+    {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_nanos() as u64);
+
+        Self
+        {
+            program,
+            io,
+            variables: [0; 26],
+            string_variables: Default::default(),
+            call_stack: Vec::new(),
+            for_stack: Vec::new(),
+            program_counter: 0,
+            statement_counter: 0,
+            jumped: false,
+            rng: SmallRng::seed_from_u64(seed),
+            max_input_retries: 3,
+            max_steps: None,
+            steps: 0,
+            wrapping_mode: WrappingMode::Checked,
+        }
+    }
+
+    /// Seeds `RND` with `seed`, so the program gets the same random numbers every run.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self
+    {
+        self.rng = SmallRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Sets how many times an `INPUT` asks again after a line it can't read before it fails with
+    /// [RuntimeError::InvalidInput]. The default is 3.
+    pub fn with_max_input_retries(mut self, retries: usize) -> Self
+    {
+        self.max_input_retries = retries;
+        self
+    }
+
+    /// Stops [Interpreter::run] with [RuntimeError::StepLimitExceeded] once it's run `steps` lines,
+    /// counting every line since the interpreter was made. Handy for running programs you don't
+    /// trust to finish, since a `RUN` or a loop can go on forever. There's no limit by default.
+    pub fn with_max_steps(mut self, steps: usize) -> Self
+    {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Makes arithmetic that overflows wrap around instead of failing with
+    /// [RuntimeError::IntegerOverflow], if `mode` is [WrappingMode::Wrapping]. Arithmetic is checked
+    /// by default.
+    pub fn with_wrapping_mode(mut self, mode: WrappingMode) -> Self
+    {
+        self.wrapping_mode = mode;
+        self
+    }
+
+    /// The value of `variable`. Every variable starts at 0.
+    pub fn variable(&self, variable: Variable) -> i64
+    {
+        self.variables[u8::from(variable) as usize]
+    }
+
+    /// The value of the string variable `variable`, like `A$`. Every string variable starts empty.
+    pub fn string_variable(&self, variable: Variable) -> &str
+    {
+        &self.string_variables[u8::from(variable) as usize]
+    }
+
+    fn set_variable(&mut self, variable: Variable, value: i64)
+    {
+        self.variables[u8::from(variable) as usize] = value;
+    }
+
+    fn eval(&mut self, expression: &Expression) -> Result<i64, RuntimeError>
+    {
+        eval_expression_with_mode(expression, &self.variables, &mut self.rng, self.wrapping_mode)
+    }
+
+    /// Sets every variable back to 0 (or empty, for strings) and forgets every `GOSUB` and `FOR`.
+    fn clear(&mut self)
+    {
+        self.variables = [0; 26];
+        self.string_variables = Default::default();
+        self.call_stack.clear();
+        self.for_stack.clear();
+    }
+
+    /// The text a `PRINT` writes for `item`.
+    fn print_item(&mut self, item: &ExprListItem) -> Result<String, RuntimeError>
+    {
+        match item
+        {
+            ExprListItem::String(string) => Ok(string.clone()),
+            ExprListItem::StringVar(variable) => Ok(self.string_variable(*variable).to_string()),
+            ExprListItem::Expression(expression) => Ok(self.eval(expression)?.to_string()),
+            // A TAB doesn't print anything by itself. PRINT moves the output along for it.
+            ExprListItem::Tab(_) => Ok(String::new()),
+        }
+    }
+
+    /// Reads numbers into `variables`. Each line can have several numbers separated by commas,
+    /// and we keep asking until every variable has one.
+    ///
+    /// A line with something that isn't a number gets a `?REENTER`, and every number on it is
+    /// thrown away. Numbers from the lines before it are kept, so only the rest need entering
+    /// again. Bad lines count towards [Interpreter::max_input_retries] for the whole `INPUT`, even
+    /// with good lines between them.
+    fn input(&mut self, variables: &VariableList) -> Result<(), RuntimeError>
+    {
+        let variables: Vec<Variable> = variables.iter().copied().collect();
+        let mut values: Vec<i64> = Vec::new();
+        let mut retries = 0;
+        while values.len() < variables.len()
+        {
+            self.io.print(if values.is_empty() { "? " } else { "?? " });
+            let line = self.io.read_line().ok_or(RuntimeError::EndOfInput)?;
+
+            let parsed: Result<Vec<i64>, _> = line.split(',').map(|x| x.trim().parse::<i64>()).collect();
+            match parsed
+            {
+                Ok(parsed) => values.extend(parsed),
+                Err(_) if retries < self.max_input_retries => {
+                    retries += 1;
+                    self.io.print("?REENTER\n");
+                },
+                Err(_) => return Err(RuntimeError::InvalidInput(line)),
+            }
+        }
+
+        // Any extra numbers are ignored.
+        for (variable, value) in variables.into_iter().zip(values)
+        {
+            self.set_variable(variable, value);
+        }
+        Ok(())
+    }
+
+    /// Moves the program counter, so the rest of the line being run is skipped.
+    fn jump(&mut self, program_counter: usize, statement_counter: usize)
+    {
+        self.program_counter = program_counter;
+        self.statement_counter = statement_counter;
+        self.jumped = true;
+    }
+
+    /// The index into [Program::instructions] of the line numbered `number`.
+    fn line_index(&self, number: i64) -> Result<usize, RuntimeError>
+    {
+        usize::try_from(number).ok()
+            .and_then(|x| self.program.line_index(x))
+            .ok_or(RuntimeError::UndefinedLine(number))
+    }
+
+    /// Runs the program one line at a time, starting at the program counter, until it runs off
+    /// the end. An error says which line it happened on.
+    pub fn run(&mut self) -> Result<(), LineError>
+    {
+        loop
+        {
+            let index = if self.statement_counter == 0 { self.program_counter } else { self.program_counter.saturating_sub(1) };
+            let Some(line) = self.program.instructions().get(index).cloned() else
+            {
+                return Ok(());
+            };
+
+            let error = |error| LineError { line: line.line_number(), error };
+            if self.max_steps.is_some_and(|x| self.steps >= x)
+            {
+                return Err(error(RuntimeError::StepLimitExceeded(self.steps)));
+            }
+            self.steps += 1;
+            self.program_counter = index + 1;
+            self.execute_statement(line.statement()).map_err(error)?;
+        }
+    }
+
+    /// Runs a single statement.
+    ///
+    /// Only `PRINT`, `INPUT`, `LIST`, `CLEAR`, `RUN`, `LET` (for numbers and strings), `GOTO`,
+    /// `GOSUB`, `RETURN`, `FOR`, `NEXT` and statements separated by `:` are supported so far. Everything else is a
+    /// [RuntimeError::Unsupported].
+    pub fn execute_statement(&mut self, statement: &Statement) -> Result<(), RuntimeError>
+    {
+        match statement
+        {
+            // The whole line is worked out before any of it is printed, so an error partway
+            // through doesn't print half of it. Each `TAB` starts a new piece at its column.
+            Statement::Print(list) => {
+                let items = std::iter::once(("", list.expression()))
+                    .chain(list.cons().iter().map(|x| (x.separator().spacing(), x.item())));
+
+                let mut pieces: Vec<(Option<usize>, String)> = vec![(None, String::new())];
+                for (spacing, item) in items
+                {
+                    pieces.last_mut().unwrap().1.push_str(spacing);
+                    match item
+                    {
+                        // A negative column is the same as column 0.
+                        ExprListItem::Tab(column) => {
+                            let value = self.eval(column)?;
+                            let column = usize::try_from(value).unwrap_or(0);
+                            if column > MAX_TAB_COLUMN
+                            {
+                                return Err(RuntimeError::TabOutOfRange(value));
+                            }
+                            pieces.push((Some(column), String::new()));
+                        },
+                        item => {
+                            let text = self.print_item(item)?;
+                            pieces.last_mut().unwrap().1.push_str(&text);
+                        },
+                    }
+                }
+                pieces.last_mut().unwrap().1.push('\n');
+
+                for (column, text) in pieces
+                {
+                    match column
+                    {
+                        Some(column) => self.io.print_at_column(column, &text),
+                        None => self.io.print(&text),
+                    }
+                }
+                Ok(())
+            },
+            Statement::List => {
+                for line in self.program.instructions_in_order()
+                {
+                    self.io.print(&format!("{}\n", line));
+                }
+                Ok(())
+            },
+            // The program counter is left alone, so a running program carries on from here.
+            Statement::Clear => {
+                self.clear();
+                Ok(())
+            },
+            // Starts over from the first line. Run from outside a program (like typed into a
+            // prompt), this only gets it ready, and the next call to run() starts the program.
+            Statement::Run => {
+                self.clear();
+                self.jump(0, 0);
+                Ok(())
+            },
+            Statement::Let(data) => {
+                let value = self.eval(data.expression())?;
+                self.set_variable(*data.variable(), value);
+                Ok(())
+            },
+            Statement::Input(variables) => self.input(variables),
+            Statement::Goto(expression) => {
+                let number = self.eval(expression)?;
+                let index = self.line_index(number)?;
+                self.jump(index, 0);
+                Ok(())
+            },
+            Statement::GoSub(expression) => {
+                let number = self.eval(expression)?;
+                let index = self.line_index(number)?;
+                self.call_stack.push(self.program_counter);
+                self.jump(index, 0);
+                Ok(())
+            },
+            Statement::Return => {
+                let index = self.call_stack.pop().ok_or(RuntimeError::ReturnWithoutGosub)?;
+                self.jump(index, 0);
+                Ok(())
+            },
+            Statement::LetString { variable, value } => {
+                let value = self.print_item(value)?;
+                self.string_variables[u8::from(*variable) as usize] = value;
+                Ok(())
+            },
+            // We don't look ahead for the NEXT, so the body always runs at least once.
+            Statement::For { variable, from, to, step } => {
+                let from = self.eval(from)?;
+                let limit = self.eval(to)?;
+                let step = step.as_ref().map_or(Ok(1), |x| self.eval(x))?;
+                self.set_variable(*variable, from);
+
+                // Starting a loop over the same variable again replaces it, along with any loops
+                // that were inside it.
+                if let Some(i) = self.for_stack.iter().position(|(x, ..)| x == variable)
+                {
+                    self.for_stack.truncate(i);
+                }
+                self.for_stack.push((*variable, limit, step, self.program_counter, self.statement_counter));
+                Ok(())
+            },
+            Statement::Next(variable) => {
+                // Any loops inside this one are finished, even if they never got to their NEXT.
+                let i = self.for_stack.iter()
+                    .rposition(|(x, ..)| x == variable)
+                    .ok_or(RuntimeError::NextWithoutFor(*variable))?;
+                self.for_stack.truncate(i + 1);
+
+                let (_, limit, step, program_counter, statement_counter) = self.for_stack[i];
+                let value = apply(self.variable(*variable), step, ArithOp::Add, self.wrapping_mode)?;
+                self.set_variable(*variable, value);
+
+                let keep_going = if step < 0 { value >= limit } else { value <= limit };
+                if keep_going
+                {
+                    self.jump(program_counter, statement_counter);
+                }
+                else
+                {
+                    self.for_stack.pop();
+                }
+                Ok(())
+            },
+            // A statement that jumps somewhere else skips the rest of the line. A NEXT going back
+            // to a FOR earlier on the same line carries on from just after the FOR. While a
+            // statement runs, the statement counter points just past it, so a FOR knows where
+            // its loop starts.
+            Statement::Sequence(statements) => {
+                let program_counter = self.program_counter;
+                let mut i = std::mem::take(&mut self.statement_counter);
+                while let Some(statement) = statements.get(i)
+                {
+                    self.jumped = false;
+                    self.statement_counter = i + 1;
+                    self.execute_statement(statement)?;
+                    if !self.jumped
+                    {
+                        i += 1;
+                    }
+                    else if self.program_counter == program_counter && self.statement_counter != 0
+                    {
+                        i = std::mem::take(&mut self.statement_counter);
+                    }
+                    else
+                    {
+                        return Ok(());
+                    }
+                }
+                self.statement_counter = 0;
+                Ok(())
+            },
+            statement => Err(RuntimeError::Unsupported(statement.keyword())),
+        }
+    }
+}
+
+impl Program
+{
+    /// Runs the program from the top, printing to `out` and reading `INPUT` from `input`. A
+    /// shortcut for an [Interpreter] with a [WriterBasicIO].
+    ///
+    /// Fails if the program does, or if writing to `out` does.
+    pub fn run_to_writer<W: Write>(&self, out: W, input: impl BufRead) -> anyhow::Result<()>
+    {
+        let mut interpreter = Interpreter::new(self.clone(), WriterBasicIO::new(out, input));
+        let result = interpreter.run();
+        // A program that fails still gets to keep what it printed before that.
+        let io_result = interpreter.io.finish();
+        result?;
+        io_result?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::lang::ast::{BuiltinFunction, ExpressionElement, ExpressionPrefix, ExprList, ExprListElement, ExprListItem, Factor, LetData, Line, PrintSeparator, Term, TermElement, TermPrefix, VariableList};
+    use crate::lang::create_lexer;
+    use crate::lang::parser::Parser;
+    use crate::lang::token::{Keyword, Token};
+
+    use super::*;
+
+    fn number(n: usize) -> Expression
+    {
+        Expression::new(None, Term::new(Factor::Number(n), Vec::new()), Vec::new())
+    }
+
+    fn variable(c: char) -> Variable
+    {
+        Variable::from_char(c).unwrap()
+    }
+
+    /// `lhs + rhs`, for two variables.
+    fn sum(lhs: char, rhs: char) -> Expression
+    {
+        Expression::new(
+            None,
+            Term::new(Factor::Variable(variable(lhs)), Vec::new()),
+            vec![ExpressionElement::new(ExpressionPrefix::Positive, Term::new(Factor::Variable(variable(rhs)), Vec::new()))],
+        )
+    }
+
+    fn run(lines: Vec<Statement>) -> Result<Interpreter<MemoryBasicIO>, RuntimeError>
+    {
+        let mut program = Program::new();
+        for (i, statement) in lines.into_iter().enumerate()
+        {
+            program.add_line(Line::new(Some((i + 1) * 10), statement)).unwrap();
+        }
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new());
+        interpreter.run().map_err(|x| x.error)?;
+        Ok(interpreter)
+    }
+
+    #[test]
+    fn test_list()
+    {
+        let a = variable('A');
+        let a_plus_one = Expression::new(
+            None,
+            Term::new(Factor::Variable(a), Vec::new()),
+            vec![ExpressionElement::new(ExpressionPrefix::Positive, Term::new(Factor::Number(1), Vec::new()))],
+        );
+
+        // Added out of order, with an unnumbered line after 30.
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(30), Statement::Goto(number(10)))).unwrap();
+        program.add_line(Line::new(None, Statement::End)).unwrap();
+        program.add_line(Line::new(Some(10), Statement::Print(ExprList::new(
+            ExprListItem::String(String::from("A IS ")),
+            vec![ExprListElement::new(
+                PrintSeparator::Comma,
+                ExprListItem::Expression(Expression::new(None, Term::new(Factor::Variable(a), Vec::new()), Vec::new())),
+            )],
+        )))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Let(LetData::new(a, a_plus_one)))).unwrap();
+
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new());
+        interpreter.execute_statement(&Statement::List).unwrap();
+        assert_eq!(interpreter.io().output(), "10 PRINT \"A IS \", A\n20 LET A = A+1\n30 GOTO 10\nEND\n");
+    }
+
+    #[test]
+    fn test_list_empty_program()
+    {
+        let mut interpreter = Interpreter::new(Program::new(), MemoryBasicIO::new());
+        interpreter.execute_statement(&Statement::List).unwrap();
+        assert_eq!(interpreter.io().output(), "");
+    }
+
+    #[test]
+    fn test_clear()
+    {
+        let mut interpreter = Interpreter::new(Program::new(), MemoryBasicIO::new());
+        interpreter.variables[0] = 5;
+        interpreter.variables[7] = -3;
+        interpreter.variables[25] = i64::MAX;
+        interpreter.call_stack = vec![1, 4];
+        interpreter.for_stack = vec![(variable('I'), 10, 1, 3, 0)];
+        interpreter.program_counter = 2;
+
+        interpreter.execute_statement(&Statement::Clear).unwrap();
+        assert_eq!(interpreter.variables(), &[0; 26]);
+        assert!(Variable::all().all(|x| interpreter.variable(x) == 0));
+        assert!(interpreter.call_stack().is_empty());
+        assert!(interpreter.for_stack().is_empty());
+        assert_eq!(interpreter.program_counter(), 2);
+    }
+
+    #[test]
+    fn test_clear_after_let()
+    {
+        let interpreter = run(vec![
+            Statement::Let(LetData::new(variable('A'), number(5))),
+            Statement::Clear,
+        ]).unwrap();
+        assert_eq!(interpreter.variable(variable('A')), 0);
+    }
+
+    #[test]
+    fn test_run_restarts_the_program()
+    {
+        // 10 PRINT A
+        // 20 LET A = A+B
+        // 30 LET B = 1
+        // 40 RUN
+        //
+        // A and B are cleared every time round, so it always prints 0. The step limit is what
+        // stops it, partway through the second time round.
+        let a = Expression::new(None, Term::new(Factor::Variable(variable('A')), Vec::new()), Vec::new());
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Print(ExprList::new(ExprListItem::Expression(a), Vec::new())))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Let(LetData::new(variable('A'), sum('A', 'B'))))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::Let(LetData::new(variable('B'), number(1))))).unwrap();
+        program.add_line(Line::new(Some(40), Statement::Run)).unwrap();
+
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new()).with_max_steps(6);
+        assert_eq!(interpreter.run(), Err(LineError { line: Some(30), error: RuntimeError::StepLimitExceeded(6) }));
+        assert_eq!(interpreter.io().output(), "0\n0\n");
+        assert_eq!(interpreter.variable(variable('A')), 0);
+        assert_eq!(interpreter.variable(variable('B')), 0);
+        assert_eq!(interpreter.program_counter(), 2);
+    }
+
+    #[test]
+    fn test_for_loop_sum()
+    {
+        // 10 LET S = 0
+        // 20 FOR I = 1 TO 10
+        // 30 LET S = S+I
+        // 40 NEXT I
+        let interpreter = run(vec![
+            Statement::Let(LetData::new(variable('S'), number(0))),
+            Statement::For { variable: variable('I'), from: number(1), to: number(10), step: None },
+            Statement::Let(LetData::new(variable('S'), sum('S', 'I'))),
+            Statement::Next(variable('I')),
+        ]).unwrap();
+
+        assert_eq!(interpreter.variable(variable('S')), 55);
+        assert_eq!(interpreter.variable(variable('I')), 11);
+        assert!(interpreter.for_stack().is_empty());
+    }
+
+    #[test]
+    fn test_nested_for_loops_with_negative_step()
+    {
+        // 10 FOR I = 3 TO 1 STEP -1
+        // 20 FOR J = 1 TO 2
+        // 30 LET N = N+I
+        // 40 NEXT J
+        // 50 NEXT I
+        let minus_one = Expression::new(Some(ExpressionPrefix::Negative), Term::new(Factor::Number(1), Vec::new()), Vec::new());
+        let interpreter = run(vec![
+            Statement::For { variable: variable('I'), from: number(3), to: number(1), step: Some(minus_one) },
+            Statement::For { variable: variable('J'), from: number(1), to: number(2), step: None },
+            Statement::Let(LetData::new(variable('N'), sum('N', 'I'))),
+            Statement::Next(variable('J')),
+            Statement::Next(variable('I')),
+        ]).unwrap();
+
+        // (3 + 2 + 1) twice each.
+        assert_eq!(interpreter.variable(variable('N')), 12);
+        assert_eq!(interpreter.variable(variable('I')), 0);
+    }
+
+    #[test]
+    fn test_next_without_for()
+    {
+        let result = run(vec![Statement::Next(variable('I'))]);
+        assert_eq!(result.err(), Some(RuntimeError::NextWithoutFor(variable('I'))));
+    }
+
+    #[test]
+    fn test_for_display()
+    {
+        let statement = Statement::For { variable: variable('I'), from: number(1), to: number(10), step: Some(number(2)) };
+        assert_eq!(statement.to_string(), "FOR I = 1 TO 10 STEP 2");
+        assert_eq!(Statement::Next(variable('I')).to_string(), "NEXT I");
+    }
+
+    #[test]
+    fn test_unsupported_statement()
+    {
+        let mut interpreter = Interpreter::new(Program::new(), MemoryBasicIO::new());
+        assert_eq!(interpreter.execute_statement(&Statement::End), Err(RuntimeError::Unsupported(Keyword::End)));
+    }
+
+    #[test]
+    fn test_rnd_with_seed()
+    {
+        let rnd = || Expression::new(None, Term::new(Factor::FunctionCall
+        {
+            name: BuiltinFunction::Rnd,
+            arg: Box::new(number(1000)),
+        }, Vec::new()), Vec::new());
+
+        let run_seeded = |seed: u64|
+        {
+            let mut program = Program::new();
+            for (i, c) in ['A', 'B', 'C'].into_iter().enumerate()
+            {
+                program.add_line(Line::new(Some(i * 10), Statement::Let(LetData::new(variable(c), rnd())))).unwrap();
+            }
+            let mut interpreter = Interpreter::new(program, MemoryBasicIO::new()).with_rng_seed(seed);
+            interpreter.run().unwrap();
+            interpreter.variables()[..3].to_vec()
+        };
+
+        let values = run_seeded(7);
+        assert!(values.iter().all(|x| (0..1000).contains(x)));
+        assert_eq!(values, run_seeded(7));
+        assert_ne!(values, run_seeded(8));
+    }
+
+    /// Lexes, parses and runs `source`, returning what it printed.
+    fn run_source(source: &str) -> String
+    {
+        let tokens: Vec<Token> = create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new());
+        interpreter.run().unwrap();
+        interpreter.io().output().clone()
+    }
+
+    #[test]
+    fn test_print_separators()
+    {
+        assert_eq!(run_source("PRINT 1,2"), "1 2\n");
+        assert_eq!(run_source("PRINT 1;2"), "12\n");
+        assert_eq!(run_source("PRINT \"A\";-3, \"B\"\nPRINT 4"), "A-3 B\n4\n");
+    }
+
+    /// Runs `INPUT` for `variables`, reading from `input`.
+    fn input(variables: &[char], input: &[&str], retries: usize) -> (Interpreter<MemoryBasicIO>, Result<(), RuntimeError>)
+    {
+        let variables = VariableList::new(variable(variables[0]), variables[1..].iter().map(|x| variable(*x)).collect());
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Input(variables))).unwrap();
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::with_input(input.iter().copied()))
+            .with_max_input_retries(retries);
+        let result = interpreter.run().map_err(|x| x.error);
+        (interpreter, result)
+    }
+
+    #[test]
+    fn test_input_asks_again_after_a_bad_value()
+    {
+        let (interpreter, result) = input(&['A'], &["abc", " -42 "], 3);
+        assert_eq!(result, Ok(()));
+        assert_eq!(interpreter.variable(variable('A')), -42);
+        assert_eq!(interpreter.io().output(), "? ?REENTER\n? ");
+
+        let (interpreter, result) = input(&['A'], &["x", "1.5", "5"], 1);
+        assert_eq!(result, Err(RuntimeError::InvalidInput(String::from("1.5"))));
+        assert_eq!(interpreter.variable(variable('A')), 0);
+
+        // The retries are for the whole INPUT, so a good line in between doesn't reset them.
+        let (_, result) = input(&['A', 'B', 'C'], &["x", "1", "y", "2", "3"], 1);
+        assert_eq!(result, Err(RuntimeError::InvalidInput(String::from("y"))));
+
+        let (_, result) = input(&['A'], &[], 3);
+        assert_eq!(result, Err(RuntimeError::EndOfInput));
+    }
+
+    #[test]
+    fn test_input_two_variables()
+    {
+        let (interpreter, result) = input(&['A', 'B'], &["1, 2"], 3);
+        assert_eq!(result, Ok(()));
+        assert_eq!((interpreter.variable(variable('A')), interpreter.variable(variable('B'))), (1, 2));
+        assert_eq!(interpreter.io().output(), "? ");
+
+        // One number per line works too, and a bad line only throws away its own numbers.
+        let (interpreter, result) = input(&['A', 'B'], &["3", "4, X", "5"], 3);
+        assert_eq!(result, Ok(()));
+        assert_eq!((interpreter.variable(variable('A')), interpreter.variable(variable('B'))), (3, 5));
+        assert_eq!(interpreter.io().output(), "? ?? ?REENTER\n?? ");
+    }
+
+    #[test]
+    fn test_string_variables()
+    {
+        assert_eq!(run_source("LET A$ = \"hi\" : PRINT A$"), "hi\n");
+        assert_eq!(run_source("LET A$ = \"X\"\nLET B$ = A$\nLET A$ = \"Y\"\nPRINT A$; B$, C$; 1"), "YX 1\n");
+
+        // A string can't be used as a number.
+        let tokens: Vec<Token> = create_lexer().parse_stream("LET A$ = \"1\" : LET B = A$ + 1").collect::<Result<_, _>>().unwrap();
+        let mut interpreter = Interpreter::new(Parser::new(tokens).parse().unwrap(), MemoryBasicIO::new());
+        assert_eq!(interpreter.run(), Err(LineError { line: None, error: RuntimeError::StringInExpression(variable('A')) }));
+        assert_eq!(interpreter.string_variable(variable('A')), "1");
+
+        interpreter.execute_statement(&Statement::Clear).unwrap();
+        assert_eq!(interpreter.string_variable(variable('A')), "");
+    }
+
+    #[test]
+    fn test_print_tab()
+    {
+        assert_eq!(run_source("PRINT \"A\"; TAB(10); \"B\""), "A         B\n");
+        // Already past the column, and a column worked out at runtime.
+        assert_eq!(run_source("PRINT \"ABCDEF\"; TAB(2); \"G\"\nLET A = 2\nPRINT TAB(A * 2), 1; TAB(-1); 2"), "ABCDEFG\n     12\n");
+
+        // A huge column is an error rather than that many spaces, and nothing gets printed.
+        let tokens: Vec<Token> = create_lexer().parse_stream("PRINT \"A\"; TAB(9223372036854775807); \"X\"").collect::<Result<_, _>>().unwrap();
+        let mut interpreter = Interpreter::new(Parser::new(tokens).parse().unwrap(), MemoryBasicIO::new());
+        let error = interpreter.run().err().unwrap().error;
+        assert_eq!(error, RuntimeError::TabOutOfRange(i64::MAX));
+        assert_eq!(error.to_string(), "TAB(9223372036854775807) is past the last column, 255");
+        assert_eq!(interpreter.io().output(), "");
+        assert_eq!(run_source("PRINT TAB(255); 1").len(), 257);
+
+        // The column counts from the start of the line, not the start of the PRINT.
+        let mut io = MemoryBasicIO::new();
+        io.print("X\nYZ");
+        io.print_at_column(4, "!");
+        assert_eq!(io.output(), "X\nYZ  !");
+    }
+
+    #[test]
+    fn test_run_to_writer()
+    {
+        // 10 INPUT A
+        // 20 PRINT "A IS", A; TAB(12); 1
+        let a = variable('A');
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Input(VariableList::new(a, Vec::new())))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Print(ExprList::new(
+            ExprListItem::String(String::from("A IS")),
+            vec![
+                ExprListElement::new(PrintSeparator::Comma, ExprListItem::Expression(Expression::new(None, Term::new(Factor::Variable(a), Vec::new()), Vec::new()))),
+                ExprListElement::new(PrintSeparator::Semicolon, ExprListItem::Tab(number(12))),
+                ExprListElement::new(PrintSeparator::Semicolon, ExprListItem::Expression(number(1))),
+            ],
+        )))).unwrap();
+
+        let mut output: Vec<u8> = Vec::new();
+        program.run_to_writer(&mut output, "42\r\n".as_bytes()).unwrap();
+        // The output goes on from the prompt, since the input never gets written to it.
+        assert_eq!(output, b"? A IS 42   1\n");
+
+        let mut output: Vec<u8> = Vec::new();
+        let error = program.run_to_writer(&mut output, "".as_bytes()).err().unwrap();
+        assert_eq!(error.downcast_ref::<LineError>().map(|x| &x.error), Some(&RuntimeError::EndOfInput));
+        assert_eq!(output, b"? ");
+    }
+
+    #[test]
+    fn test_sequence_runs_left_to_right()
+    {
+        assert_eq!(run_source("10 LET A=1 : PRINT A : LET A=A+1 : PRINT A"), "1\n2\n");
+
+        // The NEXT jumps back until the loop is done, so the last LET only runs once, after it.
+        let interpreter = run(vec![
+            Statement::For { variable: variable('I'), from: number(1), to: number(3), step: None },
+            Statement::Sequence(vec![
+                Statement::Let(LetData::new(variable('S'), sum('S', 'I'))),
+                Statement::Next(variable('I')),
+                Statement::Let(LetData::new(variable('N'), sum('N', 'S'))),
+            ]),
+        ]).unwrap();
+        assert_eq!(interpreter.variable(variable('S')), 6);
+        assert_eq!(interpreter.variable(variable('N')), 6);
+    }
+
+    #[test]
+    fn test_for_loop_on_one_line()
+    {
+        // 10 FOR I = 1 TO 3 : LET S = S+I : NEXT I : LET N = N+1
+        let interpreter = run(vec![
+            Statement::Sequence(vec![
+                Statement::For { variable: variable('I'), from: number(1), to: number(3), step: None },
+                Statement::Let(LetData::new(variable('S'), sum('S', 'I'))),
+                Statement::Next(variable('I')),
+                Statement::Let(LetData::new(variable('N'), sum('N', 'S'))),
+            ]),
+        ]).unwrap();
+        assert_eq!(interpreter.variable(variable('S')), 6);
+        assert_eq!(interpreter.variable(variable('N')), 6);
+        assert!(interpreter.for_stack().is_empty());
+        assert_eq!(interpreter.statement_counter(), 0);
+
+        // 10 LET N = N+1 : FOR I = 1 TO 3 : LET S = S+I
+        // 20 NEXT I
+        //
+        // The NEXT goes back to just after the FOR, not to the top of its line.
+        let interpreter = run(vec![
+            Statement::Sequence(vec![
+                Statement::Let(LetData::new(variable('N'), sum('N', 'S'))),
+                Statement::For { variable: variable('I'), from: number(1), to: number(3), step: None },
+                Statement::Let(LetData::new(variable('S'), sum('S', 'I'))),
+            ]),
+            Statement::Next(variable('I')),
+        ]).unwrap();
+        assert_eq!(interpreter.variable(variable('S')), 6);
+        assert_eq!(interpreter.variable(variable('N')), 0);
+
+        // A GOTO skips the rest of its line, even when it goes to the very next line.
+        assert_eq!(run_source("10 GOTO 20 : PRINT 9\n20 PRINT 1"), "1\n");
+    }
+
+    #[test]
+    fn test_error_says_which_line()
+    {
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Let(LetData::new(variable('A'), number(5))))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Let(LetData::new(variable('B'), number(0))))).unwrap();
+        // 30 LET C = A / B
+        let a_over_b = Expression::new(None, Term::new(Factor::Variable(variable('A')), vec![
+            TermElement::new(TermPrefix::Divide, Factor::Variable(variable('B'))),
+        ]), Vec::new());
+        program.add_line(Line::new(Some(30), Statement::Let(LetData::new(variable('C'), a_over_b)))).unwrap();
+
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new());
+        let error = interpreter.run().unwrap_err();
+        assert_eq!(error, LineError { line: Some(30), error: RuntimeError::DivisionByZero });
+        assert_eq!(error.to_string(), "error on line 30: Division by zero");
+    }
+
+    #[test]
+    fn test_goto_and_gosub()
+    {
+        // 10 GOSUB 40
+        // 20 LET B = A+Z
+        // 30 GOTO 60
+        // 40 LET A = 7 : RETURN
+        // 50 LET A = 0
+        // 60 LET C = 1
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::GoSub(number(40)))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Let(LetData::new(variable('B'), sum('A', 'Z'))))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::Goto(number(60)))).unwrap();
+        program.add_line(Line::new(Some(40), Statement::Sequence(vec![
+            Statement::Let(LetData::new(variable('A'), number(7))),
+            Statement::Return,
+        ]))).unwrap();
+        program.add_line(Line::new(Some(50), Statement::Let(LetData::new(variable('A'), number(0))))).unwrap();
+        program.add_line(Line::new(Some(60), Statement::Let(LetData::new(variable('C'), number(1))))).unwrap();
+
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new());
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.variable(variable('A')), 7);
+        assert_eq!(interpreter.variable(variable('B')), 7);
+        assert_eq!(interpreter.variable(variable('C')), 1);
+        assert!(interpreter.call_stack().is_empty());
+    }
+
+    #[test]
+    fn test_bad_jumps()
+    {
+        let result = run(vec![Statement::Goto(number(15))]);
+        assert_eq!(result.err(), Some(RuntimeError::UndefinedLine(15)));
+
+        let result = run(vec![Statement::Return]);
+        assert_eq!(result.err(), Some(RuntimeError::ReturnWithoutGosub));
+    }
+
+    #[test]
+    fn test_overflow_is_checked_unless_wrapping()
+    {
+        let tokens: Vec<Token> = create_lexer().parse_stream("10 LET A = 3037000500 * 3037000500").collect::<Result<_, _>>().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        let mut interpreter = Interpreter::new(program.clone(), MemoryBasicIO::new());
+        assert_eq!(interpreter.run(), Err(LineError
+        {
+            line: Some(10),
+            error: RuntimeError::IntegerOverflow { lhs: 3037000500, rhs: 3037000500, operation: ArithOp::Multiply },
+        }));
+
+        let mut interpreter = Interpreter::new(program, MemoryBasicIO::new()).with_wrapping_mode(WrappingMode::Wrapping);
+        interpreter.run().unwrap();
+        assert_eq!(interpreter.variable(variable('A')), 3037000500i64.wrapping_mul(3037000500));
+        assert!(interpreter.variable(variable('A')) < 0);
+    }
+}
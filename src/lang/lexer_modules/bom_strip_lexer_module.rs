@@ -0,0 +1,76 @@
+//! Lexer module that strips UTF-8 byte order marks.
+//!
+//! Some editors put a byte order mark (`EF BB BF`) at the start of a file. It isn't part of the
+//! program, so we skip it without producing a token.
+
+use crate::lexer::{LexerModule, LexerModuleResult};
+use crate::lang::Token;
+
+/// The byte order mark as a `char`, which is `EF BB BF` when encoded as UTF-8.
+const BYTE_ORDER_MARK: char = '\u{FEFF}';
+
+pub struct BomStripLexerModule();
+
+impl LexerModule for BomStripLexerModule
+{
+    type Language = Token;
+
+    fn parse_stream<'a>(&mut self, stream: &'a str) -> LexerModuleResult<'a, Self::Language>
+    {
+        match stream.strip_prefix(BYTE_ORDER_MARK)
+        {
+            Some(remainder) => LexerModuleResult::TokenSkipped(remainder),
+            None => LexerModuleResult::TokenIgnored,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests
+{
+    use crate::lang::create_lexer;
+    use crate::lang::token::Keyword;
+
+    use super::*;
+
+    #[test]
+    fn test_bom_strip_lexer_module_skips_bom()
+    {
+        let mut lexer_module = BomStripLexerModule();
+        let result = lexer_module.parse_stream("\u{FEFF}10 END");
+        assert!(result.is_skipped());
+        match result
+        {
+            LexerModuleResult::TokenSkipped(remainder) => assert_eq!(remainder, "10 END"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_bom_strip_lexer_module_ignores_stream_without_bom()
+    {
+        let mut lexer_module = BomStripLexerModule();
+        let result = lexer_module.parse_stream("10 END");
+        assert!(result.is_ignored());
+    }
+
+    #[test]
+    fn test_lexer_with_bom()
+    {
+        let mut lexer = create_lexer();
+        let tokens: Vec<Token> = lexer.parse_stream("\u{FEFF}10 END")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tokens, vec![Token::Number(10), Token::Keyword(Keyword::End)]);
+    }
+
+    #[test]
+    fn test_lexer_without_bom()
+    {
+        let mut lexer = create_lexer();
+        let tokens: Vec<Token> = lexer.parse_stream("10 END")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tokens, vec![Token::Number(10), Token::Keyword(Keyword::End)]);
+    }
+}
@@ -1,90 +1,188 @@
+//! Lexer module that parses keywords like `PRINT` and `GOTO`.
+//!
+//! A keyword is a run of ASCII letters, optionally preceded by spaces. We work out where the word
+//! ends while scanning it, so the remainder is just a slice of the stream and lexing a keyword only
+//! looks at the keyword itself, instead of searching the rest of the stream for it.
+
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
 use crate::lang::token::Keyword;
 use crate::lang::token::Token;
 use crate::lexer::LexerModuleSuccessResult;
 
-use std::str::FromStr;
+use core::str::FromStr;
 use crate::lexer::LexerModuleResult;
 use crate::lexer::LexerModule;
 
-pub struct KeywordLexerModule();
+/// A table of keywords that a [KeywordLexerModule] can look words up in.
+pub trait KeywordSet
+{
+    /// The token a keyword turns into.
+    type Token;
+
+    /// Looks up the word. Should ignore case, so `print` and `PRINT` find the same keyword.
+    fn lookup(&self, word: &str) -> Option<Self::Token>;
+}
+
+/// The keywords of Tiny BASIC, lexed as [Token::Keyword].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TinyBasicKeywords;
+
+impl KeywordSet for TinyBasicKeywords
+{
+    type Token = Token;
+
+    fn lookup(&self, word: &str) -> Option<Self::Token>
+    {
+        Keyword::from_str(word).ok().map(Token::Keyword)
+    }
+}
+
+/// Lets you register your own keywords without writing a [KeywordSet]. Keys are matched
+/// case-insensitively.
+#[cfg(feature = "std")]
+impl<L: Clone> KeywordSet for HashMap<&'static str, L>
+{
+    type Token = L;
+
+    fn lookup(&self, word: &str) -> Option<Self::Token>
+    {
+        self.iter()
+            .find(|(name, _)| word.eq_ignore_ascii_case(name))
+            .map(|(_, token)| token.clone())
+    }
+}
+
+/// Lexes the keywords in a [KeywordSet]. Only whole words of ASCII letters are looked up, so
+/// `PRINTX` isn't lexed as `PRINT` followed by `X`.
+pub struct KeywordLexerModule<K = TinyBasicKeywords>
+{
+    keywords: K,
+}
+
+impl KeywordLexerModule
+{
+    /// Makes a module that lexes the Tiny BASIC keywords.
+    pub fn new() -> Self
+    {
+        Self::with_keywords(TinyBasicKeywords)
+    }
+}
+
+impl Default for KeywordLexerModule
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<K: KeywordSet> KeywordLexerModule<K>
+{
+    /// Makes a module that lexes the keywords in `keywords` instead.
+    pub fn with_keywords(keywords: K) -> Self
+    {
+        Self { keywords }
+    }
+}
 
-impl LexerModule for KeywordLexerModule
+impl<K: KeywordSet> LexerModule for KeywordLexerModule<K>
 {
-    type Language = Token;
+    type Language = K::Token;
 
     fn parse_stream<'a>(&mut self, stream: &'a str) -> LexerModuleResult<'a, Self::Language>
     {
-        let token = get_first_word(stream); 
-        if token.is_none()
+        let Some((word, remainder)) = split_first_word(stream) else
         {
             return LexerModuleResult::TokenIgnored;
-        }
-        let token = token.unwrap();
-        let remainder = &stream[stream.find(token).unwrap()+token.len()..];
+        };
 
-        let keyword: Result<Keyword, ()> = Keyword::from_str(&token);
-        if keyword.is_err()
+        let Some(token) = self.keywords.lookup(word) else
         {
             return LexerModuleResult::TokenIgnored;
-        }
-        let keyword = keyword.unwrap();
+        };
         LexerModuleResult::TokenSuccess(LexerModuleSuccessResult
         {
             remainder,
-            token: Token::Keyword(keyword)
+            token
         })
     }
 }
 
-/// Gets the first word (up to the first unicode whitespace).
-/// Returns [None] if the string is empty or all whitespace.
-/// Returns [Some] containing the first word otherwise.
-fn get_first_word(string: &str) -> Option<&str>
+/// Splits the first word (a run of ASCII letters) off the start of the string, skipping any
+/// whitespace before it. Newlines aren't skipped, since they're tokens of their own.
+///
+/// Returns [None] if the string doesn't start with a word.
+/// Returns [Some] containing the word and everything after it otherwise.
+fn split_first_word(string: &str) -> Option<(&str, &str)>
 {
-    string.split_whitespace().next()
+    let trimmed = string.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+    let word_len = trimmed
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(trimmed.len());
+
+    if word_len == 0
+    {
+        return None;
+    }
+
+    Some(trimmed.split_at(word_len))
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests
 {
     use super::*;
     #[test]
-    fn test_get_first_word_returns_first_word()
+    fn test_split_first_word_returns_first_word()
     {
         let s = "This is a string";
-        let result = get_first_word(s);
+        let result = split_first_word(s);
         assert!(result.is_some());
-        assert_eq!(result.unwrap(), "This");
+        assert_eq!(result.unwrap(), ("This", " is a string"));
     }
 
     #[test]
-    fn test_get_first_word_returns_none_empty_string()
+    fn test_split_first_word_returns_none_empty_string()
     {
         let s = "";
-        assert!(get_first_word(s).is_none());
+        assert!(split_first_word(s).is_none());
     }
 
     #[test]
-    fn test_get_first_word_returns_none_all_whitespace()
+    fn test_split_first_word_returns_none_all_whitespace()
     {
         let s = "          ";
-        assert!(get_first_word(s).is_none());
+        assert!(split_first_word(s).is_none());
     }
 
     #[test]
-    fn test_get_first_word_returns_first_word_before_newline()
+    fn test_split_first_word_returns_first_word_before_newline()
     {
         let s = "Hello\nWorld";
-        let result = get_first_word(s);
+        let result = split_first_word(s);
         assert!(result.is_some());
-        assert_eq!(result.unwrap(), "Hello");
+        assert_eq!(result.unwrap(), ("Hello", "\nWorld"));
+    }
+
+    #[test]
+    fn test_split_first_word_does_not_skip_newline()
+    {
+        assert!(split_first_word("\nWorld").is_none());
+    }
+
+    #[test]
+    fn test_split_first_word_stops_at_non_letter()
+    {
+        assert_eq!(split_first_word("PRINT\"Hi\""), Some(("PRINT", "\"Hi\"")));
     }
 
     #[test]
     fn test_valid_keyword_lexer_module()
     {
         let s = String::from("print \"Hello World!\"");
-        let mut lexer_module = KeywordLexerModule();
+        let mut lexer_module = KeywordLexerModule::new();
         let result = lexer_module.parse_stream(&s);
         assert!(result.is_success());
         assert_eq!(result.unwrap().token, Token::Keyword(Keyword::Print))
@@ -94,7 +192,7 @@ mod tests
     fn test_invalid_keyword_lexer_module()
     {
         let s = String::from("This is not a keyword");
-        let mut lexer_module = KeywordLexerModule();
+        let mut lexer_module = KeywordLexerModule::new();
         let result = lexer_module.parse_stream(&s);
         assert!(result.is_ignored());
     }
@@ -103,7 +201,7 @@ mod tests
     fn test_empty_string_keyword_lexer_module()
     {
         let s = String::new();
-        let mut lexer_module = KeywordLexerModule();
+        let mut lexer_module = KeywordLexerModule::new();
         let result = lexer_module.parse_stream(&s);
         assert!(result.is_ignored());
     }
@@ -128,7 +226,7 @@ mod tests
 
         let s = String::from("print if then goto input let gosub return clear list run end");
         let mut remainder: &str = &s;
-        let mut lexer_module = KeywordLexerModule();
+        let mut lexer_module = KeywordLexerModule::new();
         for keyword in keywords
         {
             let result = lexer_module.parse_stream(remainder);
@@ -153,7 +251,7 @@ mod tests
     fn test_valid_keyword_with_newline_separates_correctly()
     {
         let s = "CLEAR\n";
-        let mut lexer_module = KeywordLexerModule();
+        let mut lexer_module = KeywordLexerModule::new();
         let result = lexer_module.parse_stream(&s);
         assert!(result.is_success());
         let result = result.unwrap();
@@ -165,7 +263,7 @@ mod tests
     fn test_valid_keyword_with_preceding_space()
     {
         let s = " CLEAR";
-        let mut lexer_module = KeywordLexerModule();
+        let mut lexer_module = KeywordLexerModule::new();
         let result = lexer_module.parse_stream(&s);
         assert!(result.is_success());
         let result = result.unwrap();
@@ -173,4 +271,75 @@ mod tests
         assert_eq!(result.token, Token::Keyword(Keyword::Clear));
     }
 
+    #[test]
+    fn test_keyword_lexer_module_does_not_skip_newline()
+    {
+        let mut lexer_module = KeywordLexerModule::new();
+        let result = lexer_module.parse_stream("\nEND");
+        assert!(result.is_ignored());
+    }
+
+    /// The old implementation, which searched the stream for the first word to find the remainder.
+    fn old_parse_stream(stream: &str) -> Option<(Keyword, &str)>
+    {
+        let token = stream.split_whitespace().next()?;
+        let remainder = &stream[stream.find(token).unwrap()+token.len()..];
+        Keyword::from_str(token).ok().map(|keyword| (keyword, remainder))
+    }
+
+    #[test]
+    fn test_keyword_lexer_module_matches_old_implementation()
+    {
+        let s = "print if then goto input let gosub return clear list run end";
+        let mut remainder = s;
+        let mut lexer_module = KeywordLexerModule::new();
+        while !remainder.is_empty()
+        {
+            let (old_keyword, old_remainder) = old_parse_stream(remainder).unwrap();
+            let result = lexer_module.parse_stream(remainder).unwrap();
+            assert_eq!(result.token, Token::Keyword(old_keyword));
+            assert_eq!(result.remainder, old_remainder);
+            remainder = result.remainder;
+        }
+    }
+
+    #[derive(Debug, PartialEq, Clone)]
+    enum MyToken
+    {
+        Stop,
+        Go,
+    }
+
+    #[test]
+    fn test_custom_keyword_set()
+    {
+        let keywords = HashMap::from([("STOP", MyToken::Stop), ("GO", MyToken::Go)]);
+        let mut lexer_module = KeywordLexerModule::with_keywords(keywords);
+
+        let result = lexer_module.parse_stream(" stop\n").unwrap();
+        assert_eq!(result.token, MyToken::Stop);
+        assert_eq!(result.remainder, "\n");
+
+        assert_eq!(lexer_module.parse_stream("Go 10").unwrap().token, MyToken::Go);
+        // Whole words only.
+        assert!(lexer_module.parse_stream("STOPPED").is_ignored());
+        assert!(lexer_module.parse_stream("GOTO").is_ignored());
+        // The Tiny BASIC keywords aren't in this set.
+        assert!(lexer_module.parse_stream("PRINT").is_ignored());
+    }
+
+    #[test]
+    fn test_custom_keyword_set_in_lexer()
+    {
+        let keywords = HashMap::from([("STOP", Token::Keyword(Keyword::End))]);
+        let mut lexer = crate::lexer::LexerBuilder::new()
+            .add_module(Box::new(KeywordLexerModule::with_keywords(keywords)))
+            .add_module(Box::new(KeywordLexerModule::new()))
+            .build();
+
+        let tokens: Vec<Token> = lexer.parse_stream("STOP END")
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(tokens, vec![Token::Keyword(Keyword::End), Token::Keyword(Keyword::End)]);
+    }
 }
@@ -1,16 +1,20 @@
 //! Common lexer modules.
 mod keyword_lexer_module;
 mod variable_lexer_module;
+mod string_variable_lexer_module;
 mod number_lexer_module;
 mod symbol_lexer_module;
 mod string_lexer_module;
 mod newline_lexer_module;
+mod bom_strip_lexer_module;
 
-pub use keyword_lexer_module::KeywordLexerModule;
+pub use keyword_lexer_module::{KeywordLexerModule, KeywordSet, TinyBasicKeywords};
 pub use variable_lexer_module::VariableLexerModule;
+pub use string_variable_lexer_module::StringVariableLexerModule;
 pub use number_lexer_module::NumberLexerModule;
 pub use symbol_lexer_module::SymbolLexerModule;
 pub use string_lexer_module::StringLexerModule;
 pub use newline_lexer_module::NewlineLexerModule;
+pub use bom_strip_lexer_module::BomStripLexerModule;
 
 
@@ -28,7 +28,7 @@ impl LexerModule for NewlineLexerModule
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests
 {
     use super::*;
@@ -2,6 +2,9 @@
 //!
 //! It doesn't take into account sign or commas or anything.
 
+use alloc::string::String;
+
+use crate::lexer::LexError;
 use crate::lexer::LexerModuleSuccessResult;
 use crate::lexer::LexerModuleResult;
 use crate::lexer::LexerModule;
@@ -20,12 +23,15 @@ impl LexerModule for NumberLexerModule
             return LexerModuleResult::TokenIgnored;
         }
 
-        // This might actually be a failure, idk.
-        // This shouldn't happen I think???
+        // It's all digits, so the only way this fails is if the number is too big.
         let number: Result<usize, _> = number_str.parse();
         if number.is_err()
         {
-            return LexerModuleResult::TokenIgnored;
+            return LexerModuleResult::TokenFailed(LexError::NumberOverflow
+            {
+                offset: 0,
+                text: String::from(number_str),
+            });
         }
 
         LexerModuleResult::TokenSuccess(
@@ -42,11 +48,11 @@ fn get_all_digits_at_start(stream: &str) -> &str
 {
     // Find the first NON digit character
     let index = stream.find(|c: char| !c.is_ascii_digit());
-    return &stream[0..index.unwrap_or(0)];
+    return &stream[0..index.unwrap_or(stream.len())];
 
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests
 {
     use super::*;
@@ -68,4 +74,16 @@ mod tests
         let result = lexer_module.parse_stream("this is not a number");
         assert!(result.is_ignored());
     }
+
+    #[test]
+    fn test_number_overflow()
+    {
+        let too_big = "99999999999999999999999999";
+        let error = crate::lang::create_lexer()
+            .parse_stream(&format!("10 PRINT {}", too_big))
+            .collect::<Result<Vec<_>, _>>()
+            .err()
+            .unwrap();
+        assert_eq!(error, LexError::NumberOverflow { offset: 9, text: String::from(too_big) });
+    }
 }
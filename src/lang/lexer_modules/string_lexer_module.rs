@@ -1,8 +1,68 @@
 //! Lexer module for parsing strings
 
-use crate::{lang::Token, lexer::{LexerModule, LexerModuleResult, LexerModuleSuccessResult}};
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::sync::Arc;
 
-pub struct StringLexerModule();
+use crate::{lang::Token, lexer::{LexError, LexerModule, LexerModuleResult, LexerModuleSuccessResult, StringInterner}};
+
+/// Lexes string literals. If it was created with [StringLexerModule::interning], identical
+/// literals share the same [Arc<str>].
+#[derive(Default)]
+pub struct StringLexerModule
+{
+    interner: Option<StringInterner>,
+    /// Whether `""` inside a string means a single `"`.
+    doubled_quote_escaping: bool,
+}
+
+impl StringLexerModule
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Creates a module that interns every string literal it lexes.
+    pub fn interning() -> Self
+    {
+        Self { interner: Some(StringInterner::new()), ..Self::default() }
+    }
+
+    /// Lets a string contain a `"` by doubling it, the way classic BASIC does, so
+    /// `"SAY ""HI"""` is `SAY "HI"`. Without this, the string would end at the second `"`.
+    pub fn with_doubled_quote_escaping(mut self) -> Self
+    {
+        self.doubled_quote_escaping = true;
+        self
+    }
+
+    /// Finds the `"` that ends the string starting at the beginning of `stream`, and returns its
+    /// position along with what's inside the string.
+    fn find_end<'a>(&self, stream: &'a str) -> Option<(usize, Cow<'a, str>)>
+    {
+        let end_quote_pos = stream[1..].find('"')? + 1;
+        if !self.doubled_quote_escaping || !stream[end_quote_pos + 1..].starts_with('"')
+        {
+            return Some((end_quote_pos, Cow::Borrowed(&stream[1..end_quote_pos])));
+        }
+
+        // There's at least one doubled quote, so the contents have to be copied without them.
+        let mut contents = String::new();
+        let mut position = 1;
+        loop
+        {
+            let end_quote_pos = position + stream[position..].find('"')?;
+            contents.push_str(&stream[position..end_quote_pos]);
+            if !stream[end_quote_pos + 1..].starts_with('"')
+            {
+                return Some((end_quote_pos, Cow::Owned(contents)));
+            }
+            contents.push('"');
+            position = end_quote_pos + 2;
+        }
+    }
+}
 
 impl LexerModule for StringLexerModule
 {
@@ -15,29 +75,27 @@ impl LexerModule for StringLexerModule
         }
 
         // If it *does*, we parse everything up to the next " character.
-        // This will return us everything inside the string, followed by an empty string for the 
-        let end_quote_pos = &stream[1..].find('"');
-        if end_quote_pos.is_none()
+        let Some((end_quote_pos, string_contents)) = self.find_end(stream)
+        else
         {
-            return LexerModuleResult::TokenFailed(anyhow::anyhow!("Expected closing \" character!"));
-        }
-
-        let end_quote_pos = end_quote_pos.unwrap() + 1;
-        // We expect at LEAST one element. Even if it's the empty string.
-        // Idk what we should do if we don't get that. Just fail?
-        let string_contents = &stream[1..end_quote_pos];
+            return LexerModuleResult::TokenFailed(LexError::UnterminatedString { offset: 0 });
+        };
 
         LexerModuleResult::TokenSuccess(
             LexerModuleSuccessResult
             {
                 // We have to add one to get rid of the end quote. Idk why, I'm dumb.
                 remainder: &stream[end_quote_pos+1..],
-                token: Token::String(string_contents.to_owned()),
+                token: Token::String(match &mut self.interner
+                {
+                    Some(interner) => interner.intern(&string_contents),
+                    None => Arc::from(string_contents.as_ref()),
+                }),
             })
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests
 {
     use crate::lang::Token;
@@ -46,42 +104,114 @@ mod tests
     #[test]
     fn test_parse_string_works()
     {
-        let mut lexer_module = StringLexerModule();
+        let mut lexer_module = StringLexerModule::new();
         let input_stream = "\"This is a string\"";
         let token = lexer_module.parse_stream(&input_stream);
         assert!(token.is_success());
         let token = token.unwrap();
-        assert_eq!(token.token, Token::String(String::from("This is a string")));
+        assert_eq!(token.token, Token::String(Arc::from("This is a string")));
         assert_eq!(token.remainder, "");
     }
 
     #[test]
     fn test_parse_string_with_remainder()
     {
-        let mut lexer_module = StringLexerModule();
+        let mut lexer_module = StringLexerModule::new();
         let input_stream = "\"This is a string\" followed by a non-string";
         let token = lexer_module.parse_stream(&input_stream);
         assert!(token.is_success());
         let token = token.unwrap();
-        assert_eq!(token.token, Token::String(String::from("This is a string")));
+        assert_eq!(token.token, Token::String(Arc::from("This is a string")));
         assert_eq!(token.remainder, " followed by a non-string");
     }
 
     #[test]
     fn test_parse_invalid_string()
     {
-        let mut lexer_module = StringLexerModule();
+        let mut lexer_module = StringLexerModule::new();
         let input_stream = "\"This is a badly formatted string";
         let token = lexer_module.parse_stream(&input_stream);
         assert!(token.is_failure());
+        assert_eq!(token.unwrap_err(), LexError::UnterminatedString { offset: 0 });
+    }
+
+    #[test]
+    fn test_unterminated_string_offset()
+    {
+        let error = crate::lang::create_lexer()
+            .parse_stream("10 PRINT \"oops")
+            .collect::<Result<Vec<_>, _>>()
+            .err()
+            .unwrap();
+        assert_eq!(error, LexError::UnterminatedString { offset: 9 });
     }
 
     #[test]
     fn test_parse_not_string()
     {
-        let mut lexer_module = StringLexerModule();
+        let mut lexer_module = StringLexerModule::new();
         let input_stream = "This is not a string";
         let token = lexer_module.parse_stream(&input_stream);
         assert!(token.is_ignored());
     }
+
+    #[test]
+    fn test_interning_shares_repeated_strings()
+    {
+        let mut lexer = crate::lang::create_interning_lexer();
+        let tokens: Vec<Token> = lexer.parse_stream("PRINT \"hi\"\nPRINT \"hi\"\nPRINT \"bye\"\n")
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let strings: Vec<&Arc<str>> = tokens.iter()
+            .filter_map(|x| match x
+            {
+                Token::String(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(strings.len(), 3);
+        assert!(Arc::ptr_eq(strings[0], strings[1]));
+        assert!(!Arc::ptr_eq(strings[0], strings[2]));
+    }
+
+    #[test]
+    fn test_no_interning_by_default()
+    {
+        let mut lexer_module = StringLexerModule::new();
+        let first = lexer_module.parse_stream("\"hi\"").unwrap().token;
+        let second = lexer_module.parse_stream("\"hi\"").unwrap().token;
+        match (first, second)
+        {
+            (Token::String(a), Token::String(b)) => assert!(!Arc::ptr_eq(&a, &b)),
+            _ => panic!("Expected two strings"),
+        }
+    }
+
+    #[test]
+    fn test_doubled_quote_escaping()
+    {
+        let mut lexer_module = StringLexerModule::new().with_doubled_quote_escaping();
+        let token = lexer_module.parse_stream("\"a\"\"b\" rest").unwrap();
+        assert_eq!(token.token, Token::String(Arc::from("a\"b")));
+        assert_eq!(token.remainder, " rest");
+
+        let token = lexer_module.parse_stream("\"say \"\"hi\"\"\"").unwrap();
+        assert_eq!(token.token, Token::String(Arc::from("say \"hi\"")));
+        assert_eq!(token.remainder, "");
+
+        // Normal strings, and the empty string, still work.
+        let token = lexer_module.parse_stream("\"This is a string\"\"\"").unwrap();
+        assert_eq!(token.token, Token::String(Arc::from("This is a string\"")));
+        let token = lexer_module.parse_stream("\"\", 1").unwrap();
+        assert_eq!(token.token, Token::String(Arc::from("")));
+        assert_eq!(token.remainder, ", 1");
+
+        assert!(lexer_module.parse_stream("\"a\"\"").is_failure());
+
+        // Without the flag, the string stops at the second quote.
+        let token = StringLexerModule::new().parse_stream("\"a\"\"b\"").unwrap();
+        assert_eq!(token.token, Token::String(Arc::from("a")));
+        assert_eq!(token.remainder, "\"b\"");
+    }
 }
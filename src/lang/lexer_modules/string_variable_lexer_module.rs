@@ -0,0 +1,59 @@
+//! The string variable lexer module parses string variables, which are a single letter A-Z
+//! followed by a `$`, like `A$`.
+
+use crate::lang::token::Variable;
+use crate::lexer::LexerModule;
+use crate::lexer::LexerModuleResult;
+use crate::lexer::LexerModuleSuccessResult;
+use crate::lang::Token;
+
+/// Has to go before the [VariableLexerModule](super::VariableLexerModule), or the letter gets
+/// lexed as a number variable and the `$` is left over.
+pub struct StringVariableLexerModule();
+
+impl LexerModule for StringVariableLexerModule
+{
+    type Language = Token;
+
+    fn parse_stream<'a>(&mut self, stream: &'a str) -> LexerModuleResult<'a, Self::Language>
+    {
+        let bytes = stream.as_bytes();
+        if bytes.len() < 2 || bytes[1] != b'$'
+        {
+            return LexerModuleResult::TokenIgnored;
+        }
+
+        match Variable::try_from(bytes[0])
+        {
+            Ok(variable) => LexerModuleResult::TokenSuccess(LexerModuleSuccessResult
+            {
+                remainder: &stream[2..],
+                token: Token::StringVariable(variable),
+            }),
+            Err(_) => LexerModuleResult::TokenIgnored,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_string_variable()
+    {
+        let result = StringVariableLexerModule().parse_stream("b$ = \"HI\"").unwrap();
+        assert_eq!(result.token, Token::StringVariable(Variable::try_from(b'B').unwrap()));
+        assert_eq!(result.remainder, " = \"HI\"");
+    }
+
+    #[test]
+    fn test_ignores_plain_variables()
+    {
+        assert!(StringVariableLexerModule().parse_stream("A = 1").is_ignored());
+        assert!(StringVariableLexerModule().parse_stream("A").is_ignored());
+        assert!(StringVariableLexerModule().parse_stream("1$").is_ignored());
+        assert!(StringVariableLexerModule().parse_stream("").is_ignored());
+    }
+}
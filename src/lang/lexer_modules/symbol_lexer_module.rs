@@ -32,7 +32,7 @@ impl LexerModule for SymbolLexerModule
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests
 {
     use crate::lang::token::Symbol;
@@ -43,7 +43,7 @@ mod tests
     #[test]
     fn test_symbol_list() 
     {
-        let input_symbols = "<>=+-*/,";
+        let input_symbols = "<>=+-*/,();:";
         let expected_token = vec![
             Token::Symbol(Symbol::LessThanSign),
             Token::Symbol(Symbol::GreaterThanSign),
@@ -53,6 +53,10 @@ mod tests
             Token::Symbol(Symbol::Times),
             Token::Symbol(Symbol::Divide),
             Token::Symbol(Symbol::Comma),
+            Token::Symbol(Symbol::LeftParen),
+            Token::Symbol(Symbol::RightParen),
+            Token::Symbol(Symbol::Semicolon),
+            Token::Symbol(Symbol::Colon),
         ];
 
         let lexer_module = SymbolLexerModule();
@@ -1,7 +1,7 @@
 //! The variable lexer module parses variables, which are a single letter A-Z.
 
-use crate::lang::ast::Variable;
-use crate::lang::ast::VariableFromU8Error;
+use crate::lang::token::Variable;
+use crate::lang::token::VariableFromU8Error;
 use crate::lexer::LexerModule;
 use crate::lexer::LexerModuleResult;
 use crate::lang::Token;
@@ -38,7 +38,7 @@ impl LexerModule for VariableLexerModule
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests
 {
     use super::*;
@@ -1,62 +1,114 @@
 #![cfg(test)]
 //! Tests the lexer on an entire program.
 
-use crate::lang::{ast::Variable, token::{Keyword, Symbol}};
+use rand::Rng;
 
-use super::*;
+use crate::lang::create_lexer;
+use crate::lang::test_support::assert_tokens;
+use crate::lang::token::{Keyword, Token};
+use crate::lexer::Position;
+use crate::test_rng::test_rng;
 
-/// Tests if an input stream generates a stream of tokens equivalent to the `expected_output_stream`
-fn test_lexer_on_input(input_stream: &str, expected_output_stream: &[Token]) -> bool
+#[test]
+fn test_lexer_on_lines_without_numbers()
 {
-    let mut lexer = create_lexer();
-    let lexer_iter = lexer.parse_stream(input_stream);
-    let expected_output_iter = expected_output_stream.iter();
-    for (input, output) in lexer_iter.zip(expected_output_iter)
-    {
-        // Return false if we get any errors.
-        if input.is_err()
-        {
-            println!("Got an error!");
-            return false;
-        }
+    assert_tokens("CLEAR\nEND\n", &["kw:CLEAR", "nl", "kw:END", "nl"]);
+}
+
+#[test]
+fn test_lexer_on_hello_world()
+{
+    assert_tokens("10 CLEAR\n\
+                   20 PRINT \"What is your name?\"\n\
+                   30 INPUT A\n\
+                   40 PRINT \"Hello, \", A", &[
+        "num:10", "kw:CLEAR", "nl",
+        "num:20", "kw:PRINT", "str:What is your name?", "nl",
+        "num:30", "kw:INPUT", "var:A", "nl",
+        "num:40", "kw:PRINT", "str:Hello, ", "sym:,", "var:A",
+    ]);
+}
+
+#[test]
+fn test_lexer_on_print_separators()
+{
+    assert_tokens("PRINT 1,2", &["kw:PRINT", "num:1", "sym:,", "num:2"]);
+    assert_tokens("PRINT 1;2", &["kw:PRINT", "num:1", "sym:;", "num:2"]);
+}
+
+#[test]
+fn test_lexer_on_string_variables()
+{
+    assert_tokens("LET A$=\"HI\":PRINT A$;A", &[
+        "kw:LET", "strvar:A", "sym:=", "str:HI", "sym::", "kw:PRINT", "strvar:A", "sym:;", "var:A",
+    ]);
+}
+
+#[test]
+fn test_lexer_on_colon_separated_statements()
+{
+    assert_tokens("10 LET A=1 : PRINT A", &[
+        "num:10", "kw:LET", "var:A", "sym:=", "num:1", "sym::", "kw:PRINT", "var:A",
+    ]);
+}
+
+#[test]
+fn test_lexer_on_multi_byte_characters()
+{
+    // Characters nobody lexes are skipped whole, even when they're more than one byte.
+    assert_tokens("é10 PRINT €\"naïve\"\u{3000}A", &["num:10", "kw:PRINT", "str:naïve", "var:A"]);
+    assert_tokens("\u{10FFFF}", &[]);
+}
 
-        let input = input.unwrap();
-        if &input != output
+#[test]
+fn test_lexer_never_panics()
+{
+    // A cheap stand-in for the fuzz target (fuzz/fuzz_targets/lex.rs), built out of pieces that
+    // have caused trouble before: multi-byte characters, huge numbers and unterminated strings.
+    let pieces = [
+        "\"", "1", "9", "A", "z", " ", "\n", "\r", "\t", "\0", "\u{FEFF}", "é", "€", "\u{3000}",
+        "\u{85}", "\u{10FFFF}", "PRINT", "<", ">", "=", ",", "99999999999999999999999",
+    ];
+    let mut rng = test_rng(1);
+    for _ in 0..20000
+    {
+        let len = rng.random_range(0..12);
+        let source: String = (0..len).map(|_| pieces[rng.random_range(0..pieces.len())]).collect();
+        let result = std::panic::catch_unwind(||
         {
-            println!("Mismatched tokens, expected {:?}, got {:?}", output, input);
-            return false;
-        }
+            let _ = create_lexer().parse_stream(&source).collect::<Result<Vec<_>, _>>();
+        });
+        assert!(result.is_ok(), "lexer panicked on {:?}", source);
     }
-    return true;
 }
 
 #[test]
-fn test_lexer_on_hello_world()
+fn test_unterminated_string_diagnostic()
 {
-    let input = "10 CLEAR\n\
-                 20 PRINT \"What is your name?\"\n\
-                 30 INPUT A\n\
-                 40 PRINT \"Hello, \", A";
-    println!("{:?}", input);
-    let variable = Variable::try_from(b'A').unwrap();
-    let expected_output: Vec<Token> = vec![
-        Token::Number(10),
-        Token::Keyword(Keyword::Clear),
-        Token::NewLine,
-        Token::Number(20),
-        Token::Keyword(Keyword::Print),
-        Token::String(String::from("What is your name?")),
-        Token::NewLine,
-        Token::Number(30),
-        Token::Keyword(Keyword::Input),
-        Token::Variable(variable),
-        Token::NewLine,
-        Token::Number(40),
-        Token::Keyword(Keyword::Print),
-        Token::String(String::from("Hello, ")),
-        Token::Symbol(Symbol::Comma),
-        Token::Variable(variable),
-    ];
+    let source = "10 PRINT \"HI\"\n20 PRINT \"HELLO\n";
+    let error = create_lexer().parse_stream(source).find_map(Result::err).unwrap();
+
+    // The caret should land right under the opening quote.
+    let rendered = crate::lexer::render(source, &error);
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert_eq!(lines[0], "line 2, column 10: Expected closing \" character!");
+    assert_eq!(lines[1].find('"'), lines[2].find('^'));
+}
 
-    assert!(test_lexer_on_input(input, &expected_output));
+#[test]
+fn test_token_position_on_line_3()
+{
+    let source = "10 PRINT \"HI\"\n20 LET A = 1\n30 GOTO 10\n";
+    let mut lexer = create_lexer();
+    let mut tokens = lexer.parse_stream(source);
+
+    while let Some(token) = tokens.next()
+    {
+        if token.unwrap() == Token::Keyword(Keyword::Goto)
+        {
+            assert_eq!(tokens.token_position(), Position { line: 3, column: 4 });
+            return;
+        }
+    }
+    panic!("Never found the GOTO");
 }
@@ -0,0 +1,577 @@
+//! Static checks that find likely mistakes in a [Program] without running it.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::lang::ast::{BuiltinFunction, Expression, ExprListItem, Factor, Line, Program, Statement, Term, Variable};
+use crate::lang::eval::eval_expression;
+
+/// A variable that's read before anything has been assigned to it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UninitWarning
+{
+    pub variable: Variable,
+    /// The line the variable is read on.
+    pub line_number: Option<usize>,
+}
+
+/// A line that can never run.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct UnreachableWarning
+{
+    pub line_number: Option<usize>,
+}
+
+/// Something wrong with how a program uses `GOSUB` and `RETURN`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GosubWarning
+{
+    /// The subroutine at `target` can't reach a `RETURN`.
+    MissingReturn { gosub_line: Option<usize>, target: usize },
+}
+
+/// Walks a program line by line, keeping track of which variables have definitely been assigned
+/// by a `LET` or `INPUT` so far.
+///
+/// This only looks at lines in order and doesn't follow jumps. So it never warns about straight
+/// line code that's fine, but it can miss a variable that's read after a `GOTO` skips the line
+/// that assigns it.
+#[derive(Debug, Default)]
+pub struct UninitializedVariableLint
+{
+    initialized: [bool; 26],
+    warnings: Vec<UninitWarning>,
+}
+
+impl UninitializedVariableLint
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Checks the variables `line` reads, then marks the ones it assigns.
+    pub fn visit_line(&mut self, line: &Line)
+    {
+        self.visit_statement(line.statement(), line.line_number());
+    }
+
+    /// A [Statement::Sequence] is checked one statement at a time, so `LET A=1 : PRINT A` is fine.
+    fn visit_statement(&mut self, statement: &Statement, line_number: Option<usize>)
+    {
+        if let Statement::Sequence(statements) = statement
+        {
+            for statement in statements
+            {
+                self.visit_statement(statement, line_number);
+            }
+            return;
+        }
+
+        let mut reads = Vec::new();
+        match statement
+        {
+            Statement::Print(items) => for item in items.iter()
+            {
+                if let ExprListItem::Expression(expression) | ExprListItem::Tab(expression) = item
+                {
+                    expression_reads(expression, &mut reads);
+                }
+            },
+            Statement::If(data) =>
+            {
+                expression_reads(data.l_expression(), &mut reads);
+                expression_reads(data.r_expression(), &mut reads);
+            },
+            Statement::Goto(expression)
+            | Statement::GoSub(expression) => expression_reads(expression, &mut reads),
+            Statement::Let(data) => expression_reads(data.expression(), &mut reads),
+            Statement::For { from, to, step, .. } =>
+            {
+                expression_reads(from, &mut reads);
+                expression_reads(to, &mut reads);
+                if let Some(step) = step
+                {
+                    expression_reads(step, &mut reads);
+                }
+            },
+            Statement::Next(variable) => reads.push(*variable),
+            Statement::Input(_)
+            | Statement::LetString { .. }
+            | Statement::Return
+            | Statement::Clear
+            | Statement::List
+            | Statement::Run
+            | Statement::End
+            | Statement::Sequence(_) => (),
+        }
+
+        for variable in reads
+        {
+            let warning = UninitWarning { variable, line_number };
+            // Only warn once per variable per line.
+            if !self.initialized[index(variable)] && !self.warnings.contains(&warning)
+            {
+                self.warnings.push(warning);
+            }
+        }
+
+        match statement
+        {
+            Statement::Let(data) => self.initialized[index(*data.variable())] = true,
+            Statement::For { variable, .. } => self.initialized[index(*variable)] = true,
+            Statement::Input(variables) => for variable in variables.iter()
+            {
+                self.initialized[index(*variable)] = true;
+            },
+            Statement::If(data) => self.visit_statement(data.then(), line_number),
+            _ => (),
+        }
+    }
+
+    /// The warnings found so far, in the order the reads appear in the program.
+    pub fn finish(self) -> Vec<UninitWarning>
+    {
+        self.warnings
+    }
+}
+
+impl Program
+{
+    /// Finds variables that are read before they're assigned. See [UninitializedVariableLint].
+    pub fn check_uninitialized_variables(&self) -> Vec<UninitWarning>
+    {
+        let mut lint = UninitializedVariableLint::new();
+        for line in self.instructions()
+        {
+            lint.visit_line(line);
+        }
+        lint.finish()
+    }
+}
+
+impl Program
+{
+    /// Finds lines that can't be reached from the first line, like anything after an `END` or an
+    /// unconditional `GOTO` that nothing jumps back to.
+    ///
+    /// Jumps are only followed when the target is a constant. A `GOTO` or `GOSUB` to a computed
+    /// line could go anywhere, and so could an `IF` (the statement after its `THEN` isn't kept
+    /// around), so if there's one of those that can run, every numbered line counts as reachable.
+    pub fn find_unreachable_lines(&self) -> Vec<UnreachableWarning>
+    {
+        let lines = self.instructions();
+        let line_indices = line_indices(lines);
+
+        let mut reachable = vec![false; lines.len()];
+        let mut to_visit: Vec<usize> = if lines.is_empty() { Vec::new() } else { vec![0] };
+        while let Some(i) = to_visit.pop()
+        {
+            if reachable[i]
+            {
+                continue;
+            }
+            reachable[i] = true;
+
+            let (jump, falls_through) = flow(&lines[i], &line_indices);
+            match jump
+            {
+                Jump::Nowhere => (),
+                Jump::To(target) => to_visit.push(target),
+                Jump::Anywhere => to_visit.extend(line_indices.values()),
+            }
+
+            if falls_through && i + 1 < lines.len()
+            {
+                to_visit.push(i + 1);
+            }
+        }
+
+        lines.iter()
+            .zip(reachable)
+            .filter(|(_, reachable)| !reachable)
+            .map(|(line, _)| UnreachableWarning { line_number: line.line_number() })
+            .collect()
+    }
+
+    /// Finds `GOSUB`s to subroutines that can never `RETURN`, because every path through them
+    /// ends at an `END` or runs off the end of the program.
+    ///
+    /// Only reports clear cases. If the subroutine might jump somewhere we can't work out (an
+    /// `IF`, or a `GOTO` to a computed line) it's assumed to be fine, and so is a `GOSUB` to a
+    /// computed line. A `GOSUB` inside the subroutine is assumed to come back.
+    pub fn check_gosub_return_balance(&self) -> Vec<GosubWarning>
+    {
+        let lines = self.instructions();
+        let line_indices = line_indices(lines);
+
+        let mut warnings = Vec::new();
+        for line in lines.iter()
+        {
+            let Statement::GoSub(target) = line.statement() else
+            {
+                continue;
+            };
+            let Some(target) = constant_line_number(target) else
+            {
+                continue;
+            };
+            let Some(start) = line_indices.get(&target) else
+            {
+                continue;
+            };
+
+            if !may_return(lines, &line_indices, *start)
+            {
+                warnings.push(GosubWarning::MissingReturn { gosub_line: line.line_number(), target });
+            }
+        }
+        warnings
+    }
+}
+
+/// Where a line can send control, besides the next line.
+enum Jump
+{
+    Nowhere,
+    /// The index of a line in the program.
+    To(usize),
+    /// Somewhere we can't work out without running the program.
+    Anywhere,
+}
+
+/// Maps each line number to where that line is in `lines`. When a number is used twice, the
+/// later line wins, same as [Program::add_line].
+fn line_indices(lines: &[Rc<Line>]) -> HashMap<usize, usize>
+{
+    let mut line_indices = HashMap::new();
+    for (i, line) in lines.iter().enumerate()
+    {
+        if let Some(number) = line.line_number()
+        {
+            line_indices.insert(number, i);
+        }
+    }
+    line_indices
+}
+
+/// Where control can go after `line` runs, and whether it can carry on to the next line.
+///
+/// A `GOSUB` counts as both jumping and falling through, since it comes back once the subroutine
+/// returns. An `IF` could jump anywhere, since we don't look at the statement after its `THEN`.
+/// So could a [Statement::Sequence], since we don't work out how its statements combine. Use
+/// [Program::flatten_sequences] first to get a better answer.
+fn flow(line: &Line, line_indices: &HashMap<usize, usize>) -> (Jump, bool)
+{
+    let jump_to = |target: &Expression| match constant_line_number(target)
+    {
+        // Jumping to a line that doesn't exist is a runtime error, so it doesn't go anywhere.
+        Some(number) => line_indices.get(&number).map_or(Jump::Nowhere, |i| Jump::To(*i)),
+        None => Jump::Anywhere,
+    };
+
+    match line.statement()
+    {
+        Statement::Goto(target) => (jump_to(target), false),
+        Statement::GoSub(target) => (jump_to(target), true),
+        Statement::If(_) | Statement::Sequence(_) => (Jump::Anywhere, true),
+        Statement::Run => (Jump::To(0), false),
+        Statement::End | Statement::Return => (Jump::Nowhere, false),
+        // A NEXT can only jump back to just after its FOR, which must have been reached already.
+        Statement::Print(_)
+        | Statement::Input(_)
+        | Statement::Let(_)
+        | Statement::LetString { .. }
+        | Statement::Clear
+        | Statement::List
+        | Statement::For { .. }
+        | Statement::Next(_) => (Jump::Nowhere, true),
+    }
+}
+
+/// Checks if a subroutine starting at `start` could reach a `RETURN`. Gives up and says yes when
+/// it can't tell.
+fn may_return(lines: &[Rc<Line>], line_indices: &HashMap<usize, usize>, start: usize) -> bool
+{
+    let mut visited = vec![false; lines.len()];
+    let mut to_visit = vec![start];
+    while let Some(i) = to_visit.pop()
+    {
+        if visited[i]
+        {
+            continue;
+        }
+        visited[i] = true;
+
+        let (jump, falls_through) = match lines[i].statement()
+        {
+            Statement::Return => return true,
+            // Nested subroutines are checked on their own.
+            Statement::GoSub(_) => (Jump::Nowhere, true),
+            _ => flow(&lines[i], line_indices),
+        };
+        match jump
+        {
+            Jump::Nowhere => (),
+            Jump::To(target) => to_visit.push(target),
+            Jump::Anywhere => return true,
+        }
+
+        if falls_through && i + 1 < lines.len()
+        {
+            to_visit.push(i + 1);
+        }
+    }
+    false
+}
+
+/// The line `target` jumps to, if it doesn't depend on any variables or `RND`.
+fn constant_line_number(target: &Expression) -> Option<usize>
+{
+    let mut reads = Vec::new();
+    expression_reads(target, &mut reads);
+    if !reads.is_empty() || calls_rnd(target)
+    {
+        return None;
+    }
+
+    let value = eval_expression(target, &[0; 26]).ok()?;
+    usize::try_from(value).ok()
+}
+
+fn index(variable: Variable) -> usize
+{
+    u8::from(variable) as usize
+}
+
+/// Adds every variable `expression` reads to `reads`, in order.
+fn expression_reads(expression: &Expression, reads: &mut Vec<Variable>)
+{
+    term_reads(expression.term(), reads);
+    for element in expression.cons()
+    {
+        term_reads(element.term(), reads);
+    }
+}
+
+fn term_reads(term: &Term, reads: &mut Vec<Variable>)
+{
+    factor_reads(term.factor(), reads);
+    for element in term.cons()
+    {
+        factor_reads(element.factor(), reads);
+    }
+}
+
+fn factor_reads(factor: &Factor, reads: &mut Vec<Variable>)
+{
+    match factor
+    {
+        Factor::Variable(variable) => reads.push(*variable),
+        // Only number variables are checked.
+        Factor::StringVariable(_) | Factor::Number(_) => (),
+        Factor::Expression(expression) => expression_reads(expression, reads),
+        Factor::FunctionCall { arg, .. } => expression_reads(arg, reads),
+    }
+}
+
+/// Whether `expression` calls `RND` anywhere, so it can come out different every time.
+fn calls_rnd(expression: &Expression) -> bool
+{
+    let terms = std::iter::once(expression.term()).chain(expression.cons().iter().map(|x| x.term()));
+    terms
+        .flat_map(|term| std::iter::once(term.factor()).chain(term.cons().iter().map(|x| x.factor())))
+        .any(|factor| match factor
+        {
+            Factor::FunctionCall { name: BuiltinFunction::Rnd, .. } => true,
+            Factor::FunctionCall { arg, .. } => calls_rnd(arg),
+            Factor::Expression(expression) => calls_rnd(expression),
+            Factor::Variable(_) | Factor::StringVariable(_) | Factor::Number(_) => false,
+        })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::lang::ast::{ExprList, ExpressionElement, ExpressionPrefix, LetData, TermElement, TermPrefix, VariableList};
+
+    use super::*;
+
+    fn variable(c: char) -> Variable
+    {
+        Variable::from_char(c).unwrap()
+    }
+
+    fn factor_expression(factor: Factor) -> Expression
+    {
+        Expression::new(None, Term::new(factor, Vec::new()), Vec::new())
+    }
+
+    fn print(c: char) -> Statement
+    {
+        let expression = factor_expression(Factor::Variable(variable(c)));
+        Statement::Print(ExprList::new(ExprListItem::Expression(expression), Vec::new()))
+    }
+
+    fn let_number(c: char, number: usize) -> Statement
+    {
+        Statement::Let(LetData::new(variable(c), factor_expression(Factor::Number(number))))
+    }
+
+    #[test]
+    fn test_print_before_let()
+    {
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), print('A'))).unwrap();
+        program.add_line(Line::new(Some(20), let_number('A', 1))).unwrap();
+        program.add_line(Line::new(Some(30), print('A'))).unwrap();
+
+        assert_eq!(program.check_uninitialized_variables(), vec![
+            UninitWarning { variable: variable('A'), line_number: Some(10) },
+        ]);
+    }
+
+    #[test]
+    fn test_input_initializes_and_nested_reads_are_found()
+    {
+        // 10 INPUT A
+        // 20 LET B = A + (C)
+        let nested = Factor::Expression(Box::new(factor_expression(Factor::Variable(variable('C')))));
+        let sum = Expression::new(
+            None,
+            Term::new(Factor::Variable(variable('A')), Vec::new()),
+            vec![ExpressionElement::new(
+                ExpressionPrefix::Positive,
+                Term::new(nested, Vec::new()))]);
+
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Input(VariableList::new(variable('A'), Vec::new())))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Let(LetData::new(variable('B'), sum)))).unwrap();
+
+        assert_eq!(program.check_uninitialized_variables(), vec![
+            UninitWarning { variable: variable('C'), line_number: Some(20) },
+        ]);
+    }
+
+    fn number(number: usize) -> Expression
+    {
+        factor_expression(Factor::Number(number))
+    }
+
+    fn unreachable(program: &Program) -> Vec<Option<usize>>
+    {
+        program.find_unreachable_lines().into_iter().map(|x| x.line_number).collect()
+    }
+
+    #[test]
+    fn test_dead_code_after_end()
+    {
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), let_number('A', 1))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::End)).unwrap();
+        program.add_line(Line::new(Some(30), print('A'))).unwrap();
+        program.add_line(Line::new(None, Statement::End)).unwrap();
+
+        assert_eq!(unreachable(&program), vec![Some(30), None]);
+    }
+
+    #[test]
+    fn test_goto_skips_lines()
+    {
+        // 10 GOTO 40
+        // 20 PRINT A
+        // 30 END
+        // 40 GOSUB 20
+        // 50 END
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Goto(number(40)))).unwrap();
+        program.add_line(Line::new(Some(20), print('A'))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::End)).unwrap();
+        program.add_line(Line::new(Some(40), Statement::GoSub(number(20)))).unwrap();
+        program.add_line(Line::new(Some(50), Statement::End)).unwrap();
+
+        assert!(unreachable(&program).is_empty());
+
+        // Without the GOSUB, 20 and 30 are never run.
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Goto(number(40)))).unwrap();
+        program.add_line(Line::new(Some(20), print('A'))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::End)).unwrap();
+        program.add_line(Line::new(Some(40), Statement::End)).unwrap();
+
+        assert_eq!(unreachable(&program), vec![Some(20), Some(30)]);
+    }
+
+    #[test]
+    fn test_computed_goto_reaches_everything()
+    {
+        let goto_a = factor_expression(Factor::Variable(variable('A')));
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Goto(goto_a))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::End)).unwrap();
+        program.add_line(Line::new(None, Statement::End)).unwrap();
+
+        // Lines without numbers can't be jumped to.
+        assert_eq!(unreachable(&program), vec![None]);
+
+        // RND(3)*10+10 could be any of 10, 20 or 30, even though it reads no variables.
+        let rnd = Term::new(
+            Factor::FunctionCall { name: BuiltinFunction::Rnd, arg: Box::new(number(3)) },
+            vec![TermElement::new(TermPrefix::Multiply, Factor::Number(10))],
+        );
+        let goto_rnd = Expression::new(None, rnd, vec![
+            ExpressionElement::new(ExpressionPrefix::Positive, Term::new(Factor::Number(10), Vec::new())),
+        ]);
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Goto(goto_rnd))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::End)).unwrap();
+        program.add_line(Line::new(Some(30), Statement::End)).unwrap();
+        assert!(unreachable(&program).is_empty());
+    }
+
+    #[test]
+    fn test_gosub_return_balance()
+    {
+        // 10 GOSUB 100
+        // 20 GOSUB 200
+        // 30 END
+        // 100 PRINT A
+        // 110 RETURN
+        // 200 GOTO 210
+        // 210 END
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::GoSub(number(100)))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::GoSub(number(200)))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::End)).unwrap();
+        program.add_line(Line::new(Some(100), print('A'))).unwrap();
+        program.add_line(Line::new(Some(110), Statement::Return)).unwrap();
+        program.add_line(Line::new(Some(200), Statement::Goto(number(210)))).unwrap();
+        program.add_line(Line::new(Some(210), Statement::End)).unwrap();
+
+        assert_eq!(program.check_gosub_return_balance(), vec![
+            GosubWarning::MissingReturn { gosub_line: Some(20), target: 200 },
+        ]);
+    }
+
+    #[test]
+    fn test_gosub_running_off_the_end()
+    {
+        // 10 GOSUB 20
+        // 20 PRINT A
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::GoSub(number(20)))).unwrap();
+        program.add_line(Line::new(Some(20), print('A'))).unwrap();
+
+        assert_eq!(program.check_gosub_return_balance(), vec![
+            GosubWarning::MissingReturn { gosub_line: Some(10), target: 20 },
+        ]);
+
+        // A computed GOTO could go back to a RETURN, so it isn't reported.
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::GoSub(number(20)))).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Goto(factor_expression(Factor::Variable(variable('A')))))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::Return)).unwrap();
+
+        assert!(program.check_gosub_return_balance().is_empty());
+    }
+}
@@ -1,26 +1,83 @@
 //! This module defines the specific shape and grammar of the [Tiny
 //! BASIC](https://en.wikipedia.org/wiki/Tiny_BASIC) language.
+//!
+//! Without the `std` feature, only the tokens and the lexer are here.
 
+#[cfg(feature = "std")]
 pub mod ast;
+#[cfg(feature = "std")]
 pub mod ast_parser;
+#[cfg(feature = "std")]
+pub mod eval;
+#[cfg(all(feature = "std", feature = "serde"))]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod interpreter;
 pub mod lexer_modules;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod syntax;
+#[cfg(feature = "std")]
+pub mod tape;
 pub mod token;
 
+#[cfg(feature = "std")]
+mod grammar_program_tests;
+#[cfg(feature = "std")]
 mod lexer_program_tests;
+#[cfg(feature = "std")]
+mod test_support;
+#[cfg(feature = "std")]
+mod token_gen;
 
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use alloc::{boxed::Box, vec};
+#[cfg(feature = "std")]
+use anyhow::{Context, Result};
 use lexer_modules::*;
+#[cfg(feature = "std")]
+use thiserror::Error;
 
+#[cfg(feature = "std")]
+use crate::grammar::{GrammarTree, ParseEngine, ParseGrammarTree, TreeShapeError};
 use crate::lexer::{Lexer, LexerBuilder};
+#[cfg(feature = "std")]
+use crate::lexer::LexError;
+#[cfg(feature = "std")]
+use crate::lang::ast::{Line, Program};
+#[cfg(feature = "std")]
+use crate::lang::parser::Parser;
 use crate::lang::token::Token;
 
-/// Creates a lexer to parse the tiny basic language.
+#[cfg(feature = "std")]
+pub use syntax::{create_grammar, create_grammar_with_symbols, TinyBasicSymbols};
+
+/// Creates a lexer to parse the tiny basic language. A `"` inside a string is written `""`.
 pub fn create_lexer() -> Lexer<Token>
+{
+    build_lexer(StringLexerModule::new())
+}
+
+/// Same as [create_lexer], but repeated string literals share a single allocation.
+pub fn create_interning_lexer() -> Lexer<Token>
+{
+    build_lexer(StringLexerModule::interning())
+}
+
+fn build_lexer(string_module: StringLexerModule) -> Lexer<Token>
 {
     LexerBuilder::<Token>::new()
         .add_modules(vec![
-            Box::new(StringLexerModule()),
-            Box::new(KeywordLexerModule()),
+            Box::new(BomStripLexerModule()),
+            Box::new(string_module.with_doubled_quote_escaping()),
+            Box::new(KeywordLexerModule::new()),
             Box::new(NumberLexerModule()),
+            Box::new(StringVariableLexerModule()),
             Box::new(VariableLexerModule()),
             Box::new(SymbolLexerModule()),
             Box::new(NewlineLexerModule()),
@@ -28,3 +85,226 @@ pub fn create_lexer() -> Lexer<Token>
         .build()
 }
 
+#[cfg(feature = "std")]
+/// Parses the lexer's output with `engine` one line at a time, so a line that doesn't parse
+/// doesn't stop the lines after it from parsing. The [Token::NewLine] ending each line is parsed as
+/// part of it, and blank lines are skipped.
+///
+/// Each tree comes with its line number, counting lines of the source from 1. Only one line of
+/// tokens is held at a time, and a lex error is yielded as soon as the lexer hits it.
+pub fn parse_lines<'e, P, I>(engine: &'e P, tokens: I) -> impl Iterator<Item = std::result::Result<(usize, GrammarTree<Token>), CompileError>> + 'e
+where
+    P: ParseEngine<Lang = Token>,
+    I: IntoIterator<Item = std::result::Result<Token, LexError>>,
+    I::IntoIter: 'e,
+{
+    let mut tokens = tokens.into_iter().peekable();
+    let mut line_number = 0;
+    core::iter::from_fn(move || loop
+    {
+        tokens.peek()?;
+        line_number += 1;
+        let mut line = Vec::new();
+        for token in tokens.by_ref()
+        {
+            let token = match token
+            {
+                Ok(token) => token,
+                Err(error) => return Some(Err(CompileError::Lex(error))),
+            };
+            let is_newline = token.is_newline();
+            line.push(token);
+            if is_newline
+            {
+                break;
+            }
+        }
+        if line.iter().all(Token::is_newline)
+        {
+            continue;
+        }
+
+        return Some(engine.parse_input(line)
+            .map(|tree| (line_number, tree))
+            .map_err(|error| CompileError::Parse { line: line_number, error }));
+    })
+}
+
+#[cfg(feature = "std")]
+/// Lexes and parses `source` into a [Program].
+///
+/// Lexer errors say how far into `source` (in bytes) the lexer got before failing.
+pub fn compile_str(source: &str) -> Result<Program>
+{
+    let tokens: Vec<Token> = create_lexer()
+        .parse_stream(source)
+        .collect::<std::result::Result<_, LexError>>()
+        .map_err(|error|
+        {
+            let offset = error.offset();
+            anyhow::Error::from(error).context(format!("offset {}", offset))
+        })?;
+
+    Parser::new(tokens).parse()
+}
+
+#[cfg(feature = "std")]
+/// Why [compile] couldn't turn the source into a [Program]. Line numbers count lines of the
+/// source from 1, not the BASIC line numbers.
+#[derive(Debug, Error)]
+pub enum CompileError
+{
+    #[error("Couldn't lex the source at offset {offset}: {0}", offset = .0.offset())]
+    Lex(#[from] LexError),
+    /// The line didn't match the grammar.
+    #[error("Couldn't parse line {line}: {error}")]
+    Parse { line: usize, error: anyhow::Error },
+    /// The line matched the grammar, but the tree wasn't the shape the AST needs. This means the
+    /// grammar and the conversions in [ast_parser] don't agree.
+    #[error("Couldn't build line {line} from its parse tree: {error}")]
+    TreeShape { line: usize, error: TreeShapeError },
+}
+
+#[cfg(feature = "std")]
+/// Lexes `source`, parses it with [create_grammar] and turns the trees into a [Program].
+///
+/// Each line is parsed by itself, so a parse error can say which line it was on. Blank lines are
+/// skipped. Tokens go straight from the lexer to the parser, so only one line of them is held at
+/// a time.
+pub fn compile(source: &str) -> std::result::Result<Program, CompileError>
+{
+    let engine = create_grammar();
+
+    let mut lexer = create_lexer();
+    let mut program = Program::new();
+    for parsed in parse_lines(&engine, lexer.parse_stream(source))
+    {
+        let (line_number, tree) = parsed?;
+        let lines = Vec::<Line>::parse_tree(&tree)
+            .map_err(|error| CompileError::TreeShape { line: line_number, error })?;
+        for line in lines
+        {
+            // Adding a line can't fail.
+            let _ = program.add_line(line);
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(feature = "std")]
+/// Reads the file at `path` and compiles it with [compile_str]. Errors are tagged with the path,
+/// so they read like `foo.bas: offset 143: ...` when printed with `{:#}`.
+pub fn compile_file(path: impl AsRef<Path>) -> Result<Program>
+{
+    let path = path.as_ref();
+    std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|source| compile_str(&source))
+        .with_context(|| path.display().to_string())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests
+{
+    use crate::grammar::{GrammarBuilder, PipelineError, Rule};
+    use crate::lang::ast::{ExprListItem, Statement};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_lines_error_on_one_line()
+    {
+        let mut grammar_builder = GrammarBuilder::<Token>::new();
+        let line = grammar_builder.id();
+        let rule = Rule::new(line)
+            .add_terminating_symbol(&Token::is_number)
+            .add_terminating_symbol(&Token::is_keyword)
+            .add_terminating_symbol(&Token::is_newline);
+        let grammar = grammar_builder.add_rule(rule).build().unwrap();
+
+        let mut lexer = create_lexer();
+        let results: Vec<_> = parse_lines(&grammar, lexer.parse_stream("10 END\n20 20 END\n\n30 RUN\n")).collect();
+
+        assert_eq!(results.len(), 3);
+        assert!(matches!(&results[0], Ok((1, GrammarTree::Node(node))) if node.symbol() == line));
+        assert!(matches!(&results[1], Err(CompileError::Parse { line: 2, .. })));
+        // The blank line is skipped, but still counted.
+        assert!(matches!(&results[2], Ok((4, GrammarTree::Node(node))) if node.symbol() == line));
+    }
+
+    #[test]
+    fn test_parse_results_straight_from_lexer()
+    {
+        let mut grammar_builder = GrammarBuilder::<Token>::new();
+        let line = grammar_builder.id();
+        let rule = Rule::new(line)
+            .add_terminating_symbol(&Token::is_number)
+            .add_terminating_symbol(&Token::is_keyword)
+            .add_terminating_symbol(&Token::is_string);
+        let grammar = grammar_builder.add_rule(rule).build().unwrap();
+
+        let result = grammar.parse_results(create_lexer().parse_stream("10 PRINT \"hi\""));
+        assert!(matches!(result, Ok(GrammarTree::Node(node)) if node.symbol() == line));
+
+        // The unterminated string fails to lex after 10 and PRINT.
+        let result = grammar.parse_results(create_lexer().parse_stream("10 PRINT \"hi"));
+        assert!(matches!(result, Err(PipelineError::Lex { token_index: 2, .. })));
+    }
+
+    /// A path in the temp directory that no other test uses.
+    fn temp_path(name: &str) -> std::path::PathBuf
+    {
+        std::env::temp_dir().join(format!("tiny-basic-{}-{}.bas", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_compile_file()
+    {
+        let path = temp_path("compile");
+        std::fs::write(&path, "10 CLEAR\n20 END\n").unwrap();
+        let program = compile_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(program.unwrap().instructions().len(), 2);
+    }
+
+    #[test]
+    fn test_compile_doubled_quotes()
+    {
+        let program = compile_str("10 PRINT \"SAY \"\"HI\"\"\"").unwrap();
+        assert_eq!(program.to_string(), "10 PRINT \"SAY \"\"HI\"\"\"\n");
+        let Statement::Print(list) = program.instructions()[0].statement() else
+        {
+            panic!("expected a PRINT");
+        };
+        assert_eq!(list.expression(), &ExprListItem::String(String::from("SAY \"HI\"")));
+    }
+
+    #[test]
+    fn test_compile_empty_file()
+    {
+        let path = temp_path("empty");
+        std::fs::write(&path, "").unwrap();
+        let program = compile_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(program.unwrap().instructions().is_empty());
+    }
+
+    #[test]
+    fn test_compile_file_errors_mention_path()
+    {
+        let path = temp_path("missing");
+        let error = compile_file(&path).err().unwrap();
+        assert!(error.downcast_ref::<std::io::Error>().is_some());
+        assert!(format!("{:#}", error).starts_with(&path.display().to_string()));
+
+        let path = temp_path("bad-string");
+        std::fs::write(&path, "10 CLEAR\n20 \"oops\n").unwrap();
+        let error = compile_file(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let message = format!("{:#}", error);
+        assert!(message.starts_with(&format!("{}: offset 12: ", path.display())), "{}", message);
+    }
+}
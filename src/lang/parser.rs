@@ -0,0 +1,821 @@
+//! A hand-written parser that turns a stream of [Token]s straight into a [Program], without going
+//! through a [Grammar](crate::grammar::Grammar).
+//!
+//! Each line is an optional line number, followed by a statement, followed by a
+//! [Token::NewLine] (or the end of the input).
+
+use std::fmt::Display;
+use std::iter::Peekable;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::lang::ast::{BuiltinFunction, ExprList, ExprListElement, ExprListItem, Expression, ExpressionElement, ExpressionPrefix, Factor, IfData, LetData, Line, PrintSeparator, Program, RelOpSymbol, Statement, Term, TermElement, TermPrefix, Variable, VariableList};
+use crate::lang::token::{Keyword, Symbol, Token};
+
+/// A stream of [Token]s with one token of lookahead, and helpers for taking the tokens the
+/// parser expects off the front. Every "Expected X, got Y" error is made here.
+pub struct TokenStream<I>
+    where I: Iterator<Item = Token>
+{
+    tokens: Peekable<I>,
+}
+
+impl<I> TokenStream<I>
+    where I: Iterator<Item = Token>
+{
+    pub fn new(tokens: impl IntoIterator<IntoIter = I>) -> Self
+    {
+        Self
+        {
+            tokens: tokens.into_iter().peekable(),
+        }
+    }
+
+    /// The next token, without taking it.
+    pub fn peek(&mut self) -> Option<&Token>
+    {
+        self.tokens.peek()
+    }
+
+    /// Takes the next token if it's `expected`.
+    pub fn next_if_eq(&mut self, expected: &Token) -> Option<Token>
+    {
+        self.tokens.next_if_eq(expected)
+    }
+
+    /// Turns the next token into a `T` with `from_token`, and takes it if that works.
+    pub fn next_if_as<T>(&mut self, from_token: impl FnOnce(&Token) -> Option<T>) -> Option<T>
+    {
+        let value = from_token(self.tokens.peek()?)?;
+        self.tokens.next();
+        Some(value)
+    }
+
+    /// Takes the next token, failing if it isn't `expected`.
+    pub fn expect(&mut self, expected: Token) -> Result<Token>
+    {
+        match self.tokens.next()
+        {
+            Some(token) if token == expected => Ok(token),
+            found => Err(unexpected(&expected, found)),
+        }
+    }
+
+    /// Takes the next token, failing if it isn't `keyword`.
+    pub fn expect_keyword(&mut self, keyword: Keyword) -> Result<()>
+    {
+        self.expect(Token::Keyword(keyword)).map(|_| ())
+    }
+
+    /// Takes the next token, failing if it isn't `symbol`.
+    pub fn expect_symbol(&mut self, symbol: Symbol) -> Result<()>
+    {
+        self.expect(Token::Symbol(symbol)).map(|_| ())
+    }
+}
+
+impl<I> Iterator for TokenStream<I>
+    where I: Iterator<Item = Token>
+{
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token>
+    {
+        self.tokens.next()
+    }
+}
+
+/// The error for finding `found` (or the end of the input) where `expected` should have been.
+pub fn unexpected(expected: impl Display, found: Option<Token>) -> anyhow::Error
+{
+    match found
+    {
+        Some(token) => anyhow!("Expected {}, got {:?}", expected, token),
+        None => anyhow!("Expected {}, got the end of the input", expected),
+    }
+}
+
+pub struct Parser<I>
+    where I: Iterator<Item = Token>
+{
+    tokens: TokenStream<I>,
+}
+
+impl<I> Parser<I>
+    where I: Iterator<Item = Token>
+{
+    /// Creates a new [Parser] over a stream of tokens.
+    pub fn new(tokens: impl IntoIterator<IntoIter = I>) -> Self
+    {
+        Self
+        {
+            tokens: TokenStream::new(tokens),
+        }
+    }
+
+    /// Parses every line in the token stream into a [Program].
+    ///
+    /// Blank lines (consecutive [Token::NewLine]s) are skipped.
+    pub fn parse(mut self) -> Result<Program>
+    {
+        let mut program = Program::new();
+        while let Some(token) = self.tokens.peek()
+        {
+            // Empty line, nothing to parse.
+            if token.is_newline()
+            {
+                self.tokens.next();
+                continue;
+            }
+
+            let line = self.parse_line()?;
+            program.add_line(line)?;
+        }
+        Ok(program)
+    }
+
+    /// Parses a single line, including the [Token::NewLine] that ends it. Several statements
+    /// separated by `:` are put together in a [Statement::Sequence].
+    fn parse_line(&mut self) -> Result<Line>
+    {
+        let line_number = self.tokens.next_if_as(Token::as_number);
+        let statement = self.parse_statements()?;
+
+        // The last line in a file doesn't need a newline after it.
+        match self.tokens.next()
+        {
+            None | Some(Token::NewLine) => Ok(Line::new(line_number, statement)),
+            found => Err(unexpected("the end of the line", found)),
+        }
+    }
+
+    /// Parses statements separated by `:` up to the end of the line, putting them together in a
+    /// [Statement::Sequence] if there's more than one.
+    fn parse_statements(&mut self) -> Result<Statement>
+    {
+        let mut statements = vec![self.parse_statement()?];
+        while self.tokens.next_if_eq(&Token::Symbol(Symbol::Colon)).is_some()
+        {
+            statements.push(self.parse_statement()?);
+        }
+        Ok(match statements.len()
+        {
+            1 => statements.pop().unwrap(),
+            _ => Statement::Sequence(statements),
+        })
+    }
+
+    /// Parses a statement, which starts with a keyword. The one exception is an implied `LET`,
+    /// like `A = 5`, which starts with the variable.
+    fn parse_statement(&mut self) -> Result<Statement>
+    {
+        if matches!(self.tokens.peek(), Some(Token::Variable(_)) | Some(Token::StringVariable(_)))
+        {
+            return self.parse_let();
+        }
+
+        let keyword = match self.tokens.next()
+        {
+            Some(Token::Keyword(keyword)) => keyword,
+            None => return Err(unexpected("a statement", None)),
+            found => return Err(unexpected("a statement to start with a keyword", found)),
+        };
+
+        match keyword
+        {
+            Keyword::Print => Ok(Statement::Print(self.parse_expr_list()?)),
+            Keyword::If => self.parse_if(),
+            Keyword::Let => self.parse_let(),
+            Keyword::Input => self.parse_input(),
+            Keyword::Goto => Ok(Statement::Goto(self.parse_line_number_expression("GOTO")?)),
+            Keyword::GoSub => Ok(Statement::GoSub(self.parse_line_number_expression("GOSUB")?)),
+            Keyword::Return => Ok(Statement::Return),
+            Keyword::Clear => Ok(Statement::Clear),
+            Keyword::List => Ok(Statement::List),
+            Keyword::Run => Ok(Statement::Run),
+            Keyword::End => Ok(Statement::End),
+            keyword => Err(anyhow!("{:?} statements aren't supported yet", keyword)),
+        }
+    }
+
+    /// Parses the `A < 5 THEN statement` after an `IF`. The statement can be another `IF`.
+    ///
+    /// Everything after the `THEN` up to the end of the line belongs to it, so in
+    /// `IF A = 1 THEN PRINT 1 : PRINT 2`, neither `PRINT` runs unless A is 1.
+    fn parse_if(&mut self) -> Result<Statement>
+    {
+        let l_expression = self.parse_expression()?;
+        let relop = self.parse_relop()?;
+        let r_expression = self.parse_expression()?;
+        self.tokens.expect_keyword(Keyword::Then)?;
+        let then = self.parse_statements()?;
+        Ok(Statement::If(IfData::new(l_expression, relop, r_expression, Box::new(then))))
+    }
+
+    /// Parses a relational operator, which is one or two symbols like `<` or `<=`.
+    fn parse_relop(&mut self) -> Result<RelOpSymbol>
+    {
+        let first = match self.tokens.next()
+        {
+            Some(Token::Symbol(symbol)) if RelOpSymbol::try_from([symbol].as_slice()).is_ok() => symbol,
+            found => return Err(unexpected("a relational operator", found)),
+        };
+
+        let pair = self.tokens.peek()
+            .and_then(Token::as_symbol)
+            .and_then(|second| RelOpSymbol::try_from([first, second].as_slice()).ok());
+        match pair
+        {
+            Some(relop) => {
+                self.tokens.next();
+                Ok(relop)
+            },
+            None => RelOpSymbol::try_from([first].as_slice()),
+        }
+    }
+
+    /// Parses the `A = 1` or `A$ = "HI"` after a `LET`, or on its own for an implied `LET`.
+    /// Errors after the variable say which variable it was.
+    fn parse_let(&mut self) -> Result<Statement>
+    {
+        match self.tokens.next()
+        {
+            Some(Token::Variable(variable)) => {
+                self.expect_equals_after(&variable)?;
+                if self.at_end_of_statement()
+                {
+                    return Err(unexpected(format!("a value to assign to {}", variable), self.tokens.next()));
+                }
+                let value = self.parse_expression()?;
+                Ok(Statement::Let(LetData::new(variable, value)))
+            },
+            Some(Token::StringVariable(variable)) => {
+                let name = format!("{}$", variable);
+                self.expect_equals_after(&name)?;
+                let value = match self.tokens.next()
+                {
+                    Some(Token::String(string)) => ExprListItem::String(string.to_string()),
+                    Some(Token::StringVariable(variable)) => ExprListItem::StringVar(variable),
+                    found => return Err(unexpected(format!("a string or a string variable to assign to {}", name), found)),
+                };
+                Ok(Statement::LetString { variable, value })
+            },
+            found => Err(unexpected("a variable", found)),
+        }
+    }
+
+    /// Parses the `A, B, C` after an `INPUT`. There has to be at least one variable, and no `,`
+    /// at the end.
+    fn parse_input(&mut self) -> Result<Statement>
+    {
+        let variable = self.parse_input_variable(1)?;
+        let mut cons = Vec::new();
+        while self.tokens.next_if_eq(&Token::Symbol(Symbol::Comma)).is_some()
+        {
+            cons.push(self.parse_input_variable(cons.len() + 2)?);
+        }
+        Ok(Statement::Input(VariableList::new(variable, cons)))
+    }
+
+    /// Parses the variable at `position` (counting from 1) in an `INPUT` list.
+    fn parse_input_variable(&mut self, position: usize) -> Result<Variable>
+    {
+        if self.at_end_of_statement()
+        {
+            let expected = match position
+            {
+                1 => "a variable after INPUT",
+                _ => "a variable after the ',' in INPUT",
+            };
+            return Err(unexpected(expected, self.tokens.next()));
+        }
+
+        match self.tokens.next()
+        {
+            Some(Token::Variable(variable)) => Ok(variable),
+            found => Err(unexpected(format!("INPUT item {} to be a variable", position), found)),
+        }
+    }
+
+    /// Parses the line number after a `GOTO` or `GOSUB`. It can be any expression, like
+    /// `GOSUB A*10+5`, not just a number.
+    fn parse_line_number_expression(&mut self, statement: &str) -> Result<Expression>
+    {
+        if self.at_end_of_statement()
+        {
+            return Err(unexpected(format!("a line number after {}", statement), self.tokens.next()));
+        }
+        self.parse_expression()
+            .with_context(|| format!("Expected a line number after {}", statement))
+    }
+
+    /// Takes the `=` after the variable `name` in a `LET`.
+    fn expect_equals_after(&mut self, name: &impl Display) -> Result<()>
+    {
+        match self.tokens.next()
+        {
+            Some(Token::Symbol(Symbol::EqualsSign)) => Ok(()),
+            found => Err(unexpected(format!("= after {}", name), found)),
+        }
+    }
+
+    /// Whether the statement ends here, at a newline, a `:` or the end of the input.
+    fn at_end_of_statement(&mut self) -> bool
+    {
+        matches!(self.tokens.peek(), None | Some(Token::NewLine) | Some(Token::Symbol(Symbol::Colon)))
+    }
+
+    /// Parses the strings and expressions after a `PRINT`, separated by `,` or `;`.
+    ///
+    /// There has to be at least one, so a `PRINT` on its own is an error rather than a blank line.
+    /// A `,` or `;` at the end is an error too.
+    pub fn parse_expr_list(&mut self) -> Result<ExprList>
+    {
+        if self.at_end_of_statement()
+        {
+            return Err(unexpected("something to print after PRINT", self.tokens.next()));
+        }
+        let expression = self.parse_expr_list_item()?;
+
+        let mut cons = Vec::new();
+        while let Some(separator) = self.tokens.next_if_as(PrintSeparator::from_token)
+        {
+            if self.at_end_of_statement()
+            {
+                return Err(unexpected(format!("something to print after the '{}'", separator), self.tokens.next()));
+            }
+            cons.push(ExprListElement::new(separator, self.parse_expr_list_item()?));
+        }
+        Ok(ExprList::new(expression, cons))
+    }
+
+    fn parse_expr_list_item(&mut self) -> Result<ExprListItem>
+    {
+        if let Some(string) = self.tokens.next_if_as(|x| x.as_string().map(str::to_string))
+        {
+            return Ok(ExprListItem::String(string));
+        }
+        if let Some(variable) = self.tokens.next_if_as(Token::as_string_variable)
+        {
+            return Ok(ExprListItem::StringVar(variable));
+        }
+        if self.tokens.next_if_eq(&Token::Keyword(Keyword::Tab)).is_some()
+        {
+            self.tokens.expect_symbol(Symbol::LeftParen)?;
+            let column = self.parse_expression()?;
+            self.tokens.expect_symbol(Symbol::RightParen)?;
+            return Ok(ExprListItem::Tab(column));
+        }
+        Ok(ExprListItem::Expression(self.parse_expression()?))
+    }
+
+    /// Parses an expression. Stops at the first token that can't be part of it (like `THEN`, a
+    /// comma or a newline) and leaves that token in the stream.
+    pub fn parse_expression(&mut self) -> Result<Expression>
+    {
+        let operator_prefix = self.tokens.next_if_as(ExpressionPrefix::from_token);
+        let term = self.parse_term()?;
+
+        let mut cons = Vec::new();
+        while let Some(operator_prefix) = self.tokens.next_if_as(ExpressionPrefix::from_token)
+        {
+            cons.push(ExpressionElement::new(operator_prefix, self.parse_term()?));
+        }
+        Ok(Expression::new(operator_prefix, term, cons))
+    }
+
+    fn parse_term(&mut self) -> Result<Term>
+    {
+        let factor = self.parse_factor()?;
+
+        let mut cons = Vec::new();
+        while let Some(prefix) = self.tokens.next_if_as(TermPrefix::from_token)
+        {
+            cons.push(TermElement::new(prefix, self.parse_factor()?));
+        }
+        Ok(Term::new(factor, cons))
+    }
+
+    /// Parses a variable, a number, an expression in brackets or a function call like `ABS(A)`.
+    fn parse_factor(&mut self) -> Result<Factor>
+    {
+        match self.tokens.next()
+        {
+            Some(Token::Variable(variable)) => Ok(Factor::Variable(variable)),
+            Some(Token::StringVariable(variable)) => Ok(Factor::StringVariable(variable)),
+            Some(Token::Number(number)) => Ok(Factor::Number(number)),
+            Some(Token::Symbol(Symbol::LeftParen)) => {
+                let expression = self.parse_expression()?;
+                self.tokens.expect_symbol(Symbol::RightParen)?;
+                Ok(Factor::Expression(Box::new(expression)))
+            },
+            Some(Token::Keyword(keyword)) => {
+                let Some(name) = BuiltinFunction::from_keyword(keyword) else
+                {
+                    return Err(unexpected("a variable, number, ( or function", Some(Token::Keyword(keyword))));
+                };
+                self.tokens.expect_symbol(Symbol::LeftParen)?;
+                let arg = self.parse_expression()?;
+                self.tokens.expect_symbol(Symbol::RightParen)?;
+                Ok(Factor::FunctionCall { name, arg: Box::new(arg) })
+            },
+            found => Err(unexpected("a variable, number, ( or function", found)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::lang::ast::Variable;
+    use crate::lang::create_lexer;
+
+    use super::*;
+
+    fn parse(input: &str) -> Result<Program>
+    {
+        let mut lexer = create_lexer();
+        let tokens: Vec<Token> = lexer.parse_stream(input).collect::<Result<_, _>>()?;
+        Parser::new(tokens).parse()
+    }
+
+    #[test]
+    fn test_parse_two_lines()
+    {
+        let program = parse("10 CLEAR\n20 END\n").unwrap();
+        assert_eq!(program.instructions().len(), 2);
+        assert_eq!(program.instructions()[0].line_number(), Some(10));
+        assert_eq!(program.instructions()[1].line_number(), Some(20));
+    }
+
+    #[test]
+    fn test_parse_without_trailing_newline()
+    {
+        let program = parse("10 CLEAR\n20 END").unwrap();
+        assert_eq!(program.instructions().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_skips_blank_lines()
+    {
+        let program = parse("\n\n10 CLEAR\n\n\n20 END\n\n").unwrap();
+        assert_eq!(program.instructions().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_line_without_number()
+    {
+        let program = parse("CLEAR\nEND").unwrap();
+        assert_eq!(program.instructions().len(), 2);
+        assert_eq!(program.instructions()[0].line_number(), None);
+    }
+
+    #[test]
+    fn test_parse_takes_the_line_number_before_the_statement()
+    {
+        let program = parse("10 LET A = 1\n20 PRINT A").unwrap();
+        let statements: Vec<String> = program.instructions().iter().map(|x| x.statement().to_string()).collect();
+        assert_eq!(statements, vec!["LET A = 1", "PRINT A"]);
+
+        // Nothing after the number, so there's no statement to parse.
+        let error = parse("10\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a statement to start with a keyword, got NewLine");
+        let error = parse("10").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a statement, got the end of the input");
+    }
+
+    #[test]
+    fn test_parse_fails_on_two_statements_in_one_line()
+    {
+        assert!(parse("10 CLEAR END\n").is_err());
+    }
+
+    fn parse_expression(input: &str) -> Result<Expression>
+    {
+        let mut lexer = create_lexer();
+        let tokens: Vec<Token> = lexer.parse_stream(input).collect::<Result<_, _>>()?;
+        Parser::new(tokens).parse_expression()
+    }
+
+    #[test]
+    fn test_parse_function_calls()
+    {
+        let expression = parse_expression("ABS(A-5)*2").unwrap();
+        let Factor::FunctionCall { name, arg } = expression.term().factor() else
+        {
+            panic!("expected a function call");
+        };
+        assert_eq!(*name, BuiltinFunction::Abs);
+        assert_eq!(arg.to_string(), "A-5");
+        assert_eq!(expression.to_string(), "ABS(A-5)*2");
+
+        assert_eq!(parse_expression("rnd(10) + int((B))").unwrap().to_string(), "RND(10)+INT((B))");
+    }
+
+    #[test]
+    fn test_parse_function_call_needs_brackets()
+    {
+        let error = parse_expression("ABS 5").err().unwrap();
+        assert_eq!(error.to_string(), "Expected (, got Number(5)");
+
+        let error = parse_expression("RND(5").err().unwrap();
+        assert_eq!(error.to_string(), "Expected ), got the end of the input");
+
+        assert!(parse_expression("PRINT(5)").is_err());
+    }
+
+    #[test]
+    fn test_parse_expression_precedence()
+    {
+        let number = |x| Term::new(Factor::Number(x), Vec::new());
+        let expected = Expression::new(None, number(2), vec![ExpressionElement::new(
+            ExpressionPrefix::Positive,
+            Term::new(Factor::Number(3), vec![TermElement::new(TermPrefix::Multiply, Factor::Number(4))]),
+        )]);
+        assert_eq!(parse_expression("2+3*4").unwrap(), expected);
+
+        let a = Variable::from_char('A').unwrap();
+        let bracketed = Expression::new(None, Term::new(Factor::Variable(a), Vec::new()), vec![
+            ExpressionElement::new(ExpressionPrefix::Positive, number(1)),
+        ]);
+        let expected = Expression::new(
+            Some(ExpressionPrefix::Negative),
+            Term::new(Factor::Expression(Box::new(bracketed)), vec![TermElement::new(TermPrefix::Divide, Factor::Number(2))]),
+            Vec::new(),
+        );
+        assert_eq!(parse_expression("-(A+1)/2").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_expression_double_operator()
+    {
+        let tokens: Vec<Token> = create_lexer().parse_stream("2**3").collect::<Result<_, _>>().unwrap();
+        let mut parser = Parser::new(tokens);
+        let error = parser.parse_expression().err().unwrap();
+        assert_eq!(error.to_string(), "Expected a variable, number, ( or function, got Symbol(Times)");
+        // It stopped at the second *, so only the 3 is left.
+        assert_eq!(parser.tokens.collect::<Vec<_>>(), vec![Token::Number(3)]);
+    }
+
+    #[test]
+    fn test_parse_expression_leaves_the_rest()
+    {
+        let mut lexer = create_lexer();
+        let tokens: Vec<Token> = lexer.parse_stream("-A+1 THEN").collect::<Result<_, _>>().unwrap();
+        let mut parser = Parser::new(tokens);
+        assert_eq!(parser.parse_expression().unwrap().to_string(), "-A+1");
+        assert_eq!(parser.tokens.next(), Some(Token::Keyword(Keyword::Then)));
+    }
+
+    #[test]
+    fn test_parse_print_separators()
+    {
+        let program = parse("10 PRINT \"A IS \", A; 5\n").unwrap();
+        let Statement::Print(list) = program.instructions()[0].statement() else
+        {
+            panic!("expected a PRINT");
+        };
+        let separators: Vec<PrintSeparator> = list.cons().iter().map(|x| *x.separator()).collect();
+        assert_eq!(separators, vec![PrintSeparator::Comma, PrintSeparator::Semicolon]);
+        assert_eq!(list.to_string(), "\"A IS \", A; 5");
+    }
+
+    #[test]
+    fn test_parse_print()
+    {
+        let program = parse("10 PRINT \"Hello, \", A\n20 PRINT 2*(A+1)\n").unwrap();
+        assert_eq!(program.instructions()[0].to_string(), "10 PRINT \"Hello, \", A");
+        let Statement::Print(list) = program.instructions()[1].statement() else
+        {
+            panic!("expected a PRINT");
+        };
+        assert!(list.cons().is_empty());
+        assert_eq!(list.to_string(), "2*(A+1)");
+
+        let error = parse("10 PRINT \"A\", 1,\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected something to print after the ',', got NewLine");
+        let error = parse("10 PRINT 1; : END").err().unwrap();
+        assert_eq!(error.to_string(), "Expected something to print after the ';', got Symbol(Colon)");
+
+        let error = parse("10 PRINT").err().unwrap();
+        assert_eq!(error.to_string(), "Expected something to print after PRINT, got the end of the input");
+    }
+
+    #[test]
+    fn test_parse_whole_line()
+    {
+        let program = parse("10 LET A = -B*2 + 1\n").unwrap();
+        let b = Variable::from_char('B').unwrap();
+        let expected = Line::new(Some(10), Statement::Let(LetData::new(
+            Variable::from_char('A').unwrap(),
+            Expression::new(
+                Some(ExpressionPrefix::Negative),
+                Term::new(Factor::Variable(b), vec![TermElement::new(TermPrefix::Multiply, Factor::Number(2))]),
+                vec![ExpressionElement::new(ExpressionPrefix::Positive, Term::new(Factor::Number(1), Vec::new()))],
+            ),
+        )));
+        assert_eq!(program.instructions()[0].as_ref(), &expected);
+    }
+
+    #[test]
+    fn test_parse_if()
+    {
+        let program = parse("IF A>5 THEN PRINT A").unwrap();
+        let a = || Expression::new(None, Term::new(Factor::Variable(Variable::from_char('A').unwrap()), Vec::new()), Vec::new());
+        let five = Expression::new(None, Term::new(Factor::Number(5), Vec::new()), Vec::new());
+        let print_a = Statement::Print(ExprList::new(ExprListItem::Expression(a()), Vec::new()));
+        assert_eq!(program.instructions()[0].statement(), &Statement::If(IfData::new(a(), RelOpSymbol::GreaterThan, five, Box::new(print_a))));
+
+        let program = parse("10 IF A=1 THEN IF B=2 THEN RETURN\n").unwrap();
+        let Statement::If(outer) = program.instructions()[0].statement() else
+        {
+            panic!("expected an IF");
+        };
+        let Statement::If(inner) = outer.then().as_ref() else
+        {
+            panic!("expected an IF inside the THEN");
+        };
+        assert_eq!(inner.then().as_ref(), &Statement::Return);
+        assert_eq!(program.instructions()[0].to_string(), "10 IF A = 1 THEN IF B = 2 THEN RETURN");
+
+        for (source, relop) in [("<", "<"), ("<=", "<="), ("=", "="), (">=", ">="), (">", ">"), ("<>", "<>"), ("><", "<>")]
+        {
+            let program = parse(&format!("IF A {} B THEN END", source)).unwrap();
+            assert_eq!(program.instructions()[0].to_string(), format!("IF A {} B THEN END", relop));
+        }
+    }
+
+    #[test]
+    fn test_parse_if_errors()
+    {
+        let error = parse("IF A > 5 PRINT A").err().unwrap();
+        assert_eq!(error.to_string(), "Expected THEN, got Keyword(Print)");
+        let error = parse("IF A >\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a variable, number, ( or function, got NewLine");
+        let error = parse("IF A THEN END").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a relational operator, got Keyword(Then)");
+        let error = parse("IF A = 1 THEN IF B = 2 THEN\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a statement to start with a keyword, got NewLine");
+    }
+
+    #[test]
+    fn test_parse_mod()
+    {
+        let expression = parse_expression("A MOD 3*B").unwrap();
+        let prefixes: Vec<TermPrefix> = expression.term().cons().iter().map(|x| *x.prefix()).collect();
+        assert_eq!(prefixes, vec![TermPrefix::Mod, TermPrefix::Multiply]);
+        assert_eq!(expression.to_string(), "A MOD 3*B");
+        assert_eq!(parse_expression(&expression.to_string()).unwrap().to_string(), "A MOD 3*B");
+    }
+
+    #[test]
+    fn test_parse_colon_separated_statements()
+    {
+        let program = parse("10 CLEAR : PRINT 1;2 : END\n20 LIST\n").unwrap();
+        assert_eq!(program.instructions().len(), 2);
+        let Statement::Sequence(statements) = program.instructions()[0].statement() else
+        {
+            panic!("expected a sequence");
+        };
+        let keywords: Vec<Keyword> = statements.iter().map(Statement::keyword).collect();
+        assert_eq!(keywords, vec![Keyword::Clear, Keyword::Print, Keyword::End]);
+        assert_eq!(program.instructions()[0].to_string(), "10 CLEAR : PRINT 1; 2 : END");
+
+        // A single statement isn't wrapped in a sequence.
+        assert!(matches!(program.instructions()[1].statement(), Statement::List));
+
+        assert!(parse("10 CLEAR :\n").is_err());
+        assert!(parse("10 CLEAR END : LIST\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_if_takes_the_rest_of_the_line()
+    {
+        let program = parse("10 IF A = 1 THEN PRINT 1 : PRINT 2\n20 LIST").unwrap();
+        let Statement::If(data) = program.instructions()[0].statement() else
+        {
+            panic!("expected an IF");
+        };
+        let Statement::Sequence(statements) = data.then().as_ref() else
+        {
+            panic!("expected the THEN branch to be a sequence");
+        };
+        assert_eq!(statements.len(), 2);
+        assert_eq!(program.instructions()[0].to_string(), "10 IF A = 1 THEN PRINT 1 : PRINT 2");
+        assert!(matches!(program.instructions()[1].statement(), Statement::List));
+    }
+
+    #[test]
+    fn test_parse_let_and_print_on_one_line()
+    {
+        let program = parse("LET A=1:PRINT A").unwrap();
+        let Statement::Sequence(statements) = program.instructions()[0].statement() else
+        {
+            panic!("expected a sequence");
+        };
+        assert!(matches!(&statements[..], [Statement::Let(_), Statement::Print(_)]));
+        assert_eq!(program.instructions()[0].to_string(), "LET A = 1 : PRINT A");
+
+        let program = parse("PRINT \"A\"; TAB(10); \"B\", TAB(A+1)").unwrap();
+        assert_eq!(program.instructions()[0].to_string(), "PRINT \"A\"; TAB(10); \"B\", TAB(A+1)");
+        assert!(parse("PRINT TAB 10\n").is_err());
+        assert!(parse("PRINT TAB(10\n").is_err());
+
+        let program = parse("LET A$ = \"HI\" : LET B$=A$ : PRINT B$, 1").unwrap();
+        assert_eq!(program.instructions()[0].to_string(), "LET A$ = \"HI\" : LET B$ = A$ : PRINT B$, 1");
+        assert!(parse("LET A$ = 1\n").is_err());
+
+        assert!(parse("LET 1=1\n").is_err());
+        assert!(parse("LET A 1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_implied_let()
+    {
+        let explicit = parse("10 LET A = B * 2\n").unwrap();
+        let implied = parse("10 A = B * 2\n").unwrap();
+        assert_eq!(explicit.instructions(), implied.instructions());
+        assert_eq!(implied.instructions()[0].to_string(), "10 LET A = B*2");
+
+        let program = parse("A$ = \"HI\" : B = 1 : IF B = 1 THEN C = 2").unwrap();
+        assert_eq!(program.instructions()[0].to_string(), "LET A$ = \"HI\" : LET B = 1 : IF B = 1 THEN LET C = 2");
+    }
+
+    #[test]
+    fn test_parse_let_errors()
+    {
+        let error = parse("LET 5 = A\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a variable, got Number(5)");
+        let error = parse("LET A 1\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected = after A, got Number(1)");
+        let error = parse("B + 1\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected = after B, got Symbol(Plus)");
+        let error = parse("LET C =\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a value to assign to C, got NewLine");
+        // A value that's there but broken gets the expression's own error.
+        let error = parse("LET C = 1 + )\n").err().unwrap();
+        assert_eq!(format!("{:#}", error), "Expected a variable, number, ( or function, got Symbol(RightParen)");
+        let error = parse("D$ = 1\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a string or a string variable to assign to D$, got Number(1)");
+    }
+
+    #[test]
+    fn test_parse_input()
+    {
+        let program = parse("10 INPUT A\n20 INPUT A, B,C\n").unwrap();
+        let a = Variable::from_char('A').unwrap();
+        let b = Variable::from_char('B').unwrap();
+        let c = Variable::from_char('C').unwrap();
+        assert_eq!(program.instructions()[0].statement(), &Statement::Input(VariableList::new(a, Vec::new())));
+        assert_eq!(program.instructions()[1].statement(), &Statement::Input(VariableList::new(a, vec![b, c])));
+
+        let error = parse("INPUT\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a variable after INPUT, got NewLine");
+        let error = parse("INPUT A,\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a variable after the ',' in INPUT, got NewLine");
+        let error = parse("INPUT A, 5\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected INPUT item 2 to be a variable, got Number(5)");
+        let error = parse("INPUT A$\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected INPUT item 1 to be a variable, got StringVariable(Variable(0))");
+    }
+
+    #[test]
+    fn test_parse_goto_and_gosub()
+    {
+        let program = parse("10 GOTO 100\n20 GOSUB A*10+5 : RETURN\n").unwrap();
+        assert_eq!(program.instructions()[0].to_string(), "10 GOTO 100");
+        assert_eq!(program.instructions()[1].to_string(), "20 GOSUB A*10+5 : RETURN");
+        let Statement::Sequence(statements) = program.instructions()[1].statement() else
+        {
+            panic!("expected a sequence");
+        };
+        assert!(matches!(&statements[..], [Statement::GoSub(_), Statement::Return]));
+
+        let error = parse("GOTO\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a line number after GOTO, got NewLine");
+        let error = parse("GOSUB : END\n").err().unwrap();
+        assert_eq!(error.to_string(), "Expected a line number after GOSUB, got Symbol(Colon)");
+        let error = parse("GOSUB A*\n").err().unwrap();
+        assert_eq!(format!("{:#}", error), "Expected a line number after GOSUB: Expected a variable, number, ( or function, got NewLine");
+    }
+
+    #[test]
+    fn test_token_stream()
+    {
+        let tokens: Vec<Token> = create_lexer().parse_stream("IF A = 5").collect::<Result<_, _>>().unwrap();
+        let mut stream = TokenStream::new(tokens);
+
+        assert_eq!(stream.peek(), Some(&Token::Keyword(Keyword::If)));
+        stream.expect_keyword(Keyword::If).unwrap();
+        assert_eq!(stream.next_if_as(Token::as_number), None);
+        assert_eq!(stream.next_if_as(Token::as_variable), Some(Variable::from_char('A').unwrap()));
+
+        let error = stream.expect_keyword(Keyword::Then).err().unwrap();
+        assert_eq!(error.to_string(), "Expected THEN, got Symbol(EqualsSign)");
+        assert_eq!(stream.expect(Token::Number(5)).unwrap(), Token::Number(5));
+
+        let error = stream.expect_symbol(Symbol::RightParen).err().unwrap();
+        assert_eq!(error.to_string(), "Expected ), got the end of the input");
+        assert_eq!(stream.next(), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Defines the [Grammar] for Tiny BASIC, following the formal grammar in [ast](crate::lang::ast).
+//!
+//! The grammar is written for the [RecursiveDescentEngine], so every recursive rule recurses on
+//! the right, and alternatives that match a prefix of another alternative come after it.
+//! Repetition like `(, var)*` is written as a recursive rule, which
+//! [TreeCursor::flat_children](crate::grammar::TreeCursor::flat_children) flattens back out.
+
+use std::sync::OnceLock;
+
+use crate::grammar::Grammar;
+use crate::grammar::Id;
+use crate::grammar::RecursiveDescentEngine;
+use crate::lang::ast::{BuiltinFunction, ExpressionPrefix, PrintSeparator, TermPrefix};
+use crate::lang::token::{Keyword, Symbol, Token};
+
+/// The [Id]s of the non-terminating symbols in the grammar made by [create_grammar_with_symbols],
+/// so code turning the trees into an AST can tell the nodes apart.
+#[derive(Debug, Clone, Copy)]
+pub struct TinyBasicSymbols
+{
+    /// One or more lines. This is the start symbol.
+    pub program: Id,
+    /// An optional line number, a statement and the newline after it.
+    pub line: Id,
+    pub statement: Id,
+    /// The items after a `PRINT`, separated by `,` or `;`.
+    pub expr_list: Id,
+    /// A string, a string variable, `TAB(expression)` or an expression.
+    pub expr_item: Id,
+    /// Variables separated by commas, like after an `INPUT`.
+    pub var_list: Id,
+    pub expression: Id,
+    pub term: Id,
+    pub factor: Id,
+    /// A relational operator, like `<` or `<=`. Each half is its own leaf.
+    pub relop: Id,
+}
+
+/// Defines a recognizer for each token that has to be matched exactly.
+macro_rules! token_recognizers
+{
+    ($($name:ident => $token:expr;)*) => {
+        $(
+            fn $name(token: &Token) -> bool
+            {
+                *token == $token
+            }
+        )*
+    };
+}
+
+token_recognizers!
+{
+    is_print => Token::Keyword(Keyword::Print);
+    is_if => Token::Keyword(Keyword::If);
+    is_then => Token::Keyword(Keyword::Then);
+    is_goto => Token::Keyword(Keyword::Goto);
+    is_input => Token::Keyword(Keyword::Input);
+    is_let => Token::Keyword(Keyword::Let);
+    is_gosub => Token::Keyword(Keyword::GoSub);
+    is_return => Token::Keyword(Keyword::Return);
+    is_clear => Token::Keyword(Keyword::Clear);
+    is_list => Token::Keyword(Keyword::List);
+    is_run => Token::Keyword(Keyword::Run);
+    is_end => Token::Keyword(Keyword::End);
+    is_tab => Token::Keyword(Keyword::Tab);
+    is_less_than => Token::Symbol(Symbol::LessThanSign);
+    is_greater_than => Token::Symbol(Symbol::GreaterThanSign);
+    is_equals => Token::Symbol(Symbol::EqualsSign);
+    is_comma => Token::Symbol(Symbol::Comma);
+    is_left_paren => Token::Symbol(Symbol::LeftParen);
+    is_right_paren => Token::Symbol(Symbol::RightParen);
+}
+
+fn is_print_separator(token: &Token) -> bool
+{
+    PrintSeparator::from_token(token).is_some()
+}
+
+fn is_expression_prefix(token: &Token) -> bool
+{
+    ExpressionPrefix::from_token(token).is_some()
+}
+
+fn is_term_prefix(token: &Token) -> bool
+{
+    TermPrefix::from_token(token).is_some()
+}
+
+fn is_function(token: &Token) -> bool
+{
+    token.as_keyword().is_some_and(|x| BuiltinFunction::from_keyword(*x).is_some())
+}
+
+/// Creates a parser for the Tiny BASIC language. See [create_grammar_with_symbols] for the [Id]s
+/// of its symbols.
+///
+/// The grammar's own shift-reduce parser reduces as soon as it can, so it would stop at a `line`
+/// and never get to `program`. That's why this hands back a [RecursiveDescentEngine] instead of
+/// the [Grammar] itself.
+pub fn create_grammar() -> RecursiveDescentEngine<'static, Token>
+{
+    create_grammar_with_symbols().0
+}
+
+/// Same as [create_grammar], along with the [Id]s of the grammar's non-terminating symbols.
+///
+/// Only the statements from the formal grammar are in it. The `FOR`/`NEXT` and `:` extensions
+/// aren't. The newline after the last line can be left out.
+pub fn create_grammar_with_symbols() -> (RecursiveDescentEngine<'static, Token>, TinyBasicSymbols)
+{
+    // The engine borrows its rules, so the grammar is built once and kept around.
+    static GRAMMAR: OnceLock<(Grammar<'static, Token>, TinyBasicSymbols)> = OnceLock::new();
+    let (grammar, symbols) = GRAMMAR.get_or_init(build_grammar);
+    // Every recursive rule recurses on the right, so this can't fail.
+    (RecursiveDescentEngine::new(grammar).unwrap(), *symbols)
+}
+
+fn build_grammar() -> (Grammar<'static, Token>, TinyBasicSymbols)
+{
+    let (builder, ids) = crate::grammar! {
+        start program;
+        program ::= line program | line;
+        line ::= [number]? statement newline | [number]? statement;
+
+        statement ::= print expr_list
+            | if expression relop expression then statement
+            | goto expression
+            | input var_list
+            | let variable equals expression
+            | gosub expression
+            | return
+            | clear
+            | list
+            | run
+            | end;
+
+        expr_list ::= expr_item separator expr_list | expr_item;
+        expr_item ::= string | string_variable | tab lparen expression rparen | expression;
+        var_list ::= variable comma var_list | variable;
+
+        expression ::= sign term sign expression | sign term | term sign expression | term;
+        term ::= factor term_op term | factor;
+        factor ::= function lparen expression rparen | lparen expression rparen | variable | number;
+        relop ::= less greater | less equals | greater less | greater equals | less | greater | equals;
+
+        terminal print = is_print;
+        terminal if = is_if;
+        terminal then = is_then;
+        terminal goto = is_goto;
+        terminal input = is_input;
+        terminal let = is_let;
+        terminal gosub = is_gosub;
+        terminal return = is_return;
+        terminal clear = is_clear;
+        terminal list = is_list;
+        terminal run = is_run;
+        terminal end = is_end;
+        terminal tab = is_tab;
+        terminal function = is_function;
+        terminal number = Token::is_number;
+        terminal variable = Token::is_variable;
+        terminal string = Token::is_string;
+        terminal string_variable = Token::is_string_variable;
+        terminal newline = Token::is_newline;
+        terminal separator = is_print_separator;
+        terminal comma = is_comma;
+        terminal sign = is_expression_prefix;
+        terminal term_op = is_term_prefix;
+        terminal lparen = is_left_paren;
+        terminal rparen = is_right_paren;
+        terminal less = is_less_than;
+        terminal greater = is_greater_than;
+        terminal equals = is_equals;
+    };
+
+    let symbols = TinyBasicSymbols
+    {
+        program: ids["program"],
+        line: ids["line"],
+        statement: ids["statement"],
+        expr_list: ids["expr_list"],
+        expr_item: ids["expr_item"],
+        var_list: ids["var_list"],
+        expression: ids["expression"],
+        term: ids["term"],
+        factor: ids["factor"],
+        relop: ids["relop"],
+    };
+
+    // Every symbol above has a rule, so this can't fail.
+    (builder.build().unwrap(), symbols)
+}
@@ -0,0 +1,775 @@
+//! Saves a [Program] to a compact binary "tape" and reads it back, for embedding programs in
+//! small binaries. See [Program::to_tape] for the format.
+
+use thiserror::Error;
+
+use crate::lang::ast::*;
+
+/// The first four bytes of every tape: "Tiny BASIC Tape", version 1.
+const MAGIC: [u8; 4] = *b"TBT1";
+
+/// How deep statements (IF ... THEN, `:`) and expressions (brackets, function calls) can nest in
+/// a tape. Reading is recursive, so a tape made of nothing but `(((` would overflow the stack
+/// without a limit.
+pub const MAX_TAPE_NESTING: usize = 100;
+
+/// Why [Program::from_tape] couldn't read a tape, or [Program::try_to_tape] couldn't write one.
+/// Every `offset` is the byte in the tape where the problem is.
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+pub enum TapeError
+{
+    /// The tape doesn't start with the magic number, so it's not a tape (or not one we can read).
+    #[error("Not a Tiny BASIC tape")]
+    BadMagic,
+    /// The tape ends in the middle of a line.
+    #[error("Tape ends unexpectedly at byte {offset}")]
+    UnexpectedEnd { offset: usize },
+    /// A byte that says what comes next (a statement, a factor, an operator...) isn't one we know.
+    #[error("Unknown {what} tag {tag} at byte {offset}")]
+    InvalidTag { what: &'static str, tag: u8, offset: usize },
+    /// A variable that isn't one of A-Z.
+    #[error("Invalid variable {value} at byte {offset}")]
+    InvalidVariable { value: u8, offset: usize },
+    /// A number that doesn't fit in a [usize].
+    #[error("Number too large at byte {offset}")]
+    NumberTooLarge { offset: usize },
+    /// A string that isn't UTF-8.
+    #[error("Invalid UTF-8 in the string at byte {offset}")]
+    InvalidString { offset: usize },
+    /// Statements or expressions nested more than [MAX_TAPE_NESTING] deep.
+    #[error("Nested more than {MAX_TAPE_NESTING} levels deep at byte {offset}")]
+    TooDeep { offset: usize },
+    /// A line number that's 0 or doesn't fit in the 2 bytes a tape has for it.
+    #[error("Line number {0} can't be written to a tape, it has to be between 1 and 65535")]
+    LineNumberOutOfRange(usize),
+}
+
+/// Writes the pieces of a tape. Numbers and lengths are written as
+/// [LEB128](https://en.wikipedia.org/wiki/LEB128), so small ones only take a byte.
+struct TapeWriter
+{
+    bytes: Vec<u8>,
+}
+
+impl TapeWriter
+{
+    fn byte(&mut self, byte: u8)
+    {
+        self.bytes.push(byte);
+    }
+
+    fn number(&mut self, mut number: usize)
+    {
+        while number >= 0x80
+        {
+            self.bytes.push((number as u8 & 0x7f) | 0x80);
+            number >>= 7;
+        }
+        self.bytes.push(number as u8);
+    }
+
+    fn string(&mut self, string: &str)
+    {
+        self.number(string.len());
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn variable(&mut self, variable: Variable)
+    {
+        self.byte(variable.into());
+    }
+
+    fn statement(&mut self, statement: &Statement)
+    {
+        match statement
+        {
+            Statement::Print(list) => {
+                self.byte(0);
+                self.expr_list(list);
+            },
+            Statement::If(data) => {
+                self.byte(1);
+                self.expression(data.l_expression());
+                self.byte(relop_tag(*data.relop()));
+                self.expression(data.r_expression());
+                self.statement(data.then());
+            },
+            Statement::Goto(expression) => {
+                self.byte(2);
+                self.expression(expression);
+            },
+            Statement::Input(variables) => {
+                self.byte(3);
+                self.number(variables.iter().count());
+                variables.iter().for_each(|x| self.variable(*x));
+            },
+            Statement::Let(data) => {
+                self.byte(4);
+                self.variable(*data.variable());
+                self.expression(data.expression());
+            },
+            Statement::LetString { variable, value } => {
+                self.byte(5);
+                self.variable(*variable);
+                self.expr_list_item(value);
+            },
+            Statement::GoSub(expression) => {
+                self.byte(6);
+                self.expression(expression);
+            },
+            Statement::Return => self.byte(7),
+            Statement::Clear => self.byte(8),
+            Statement::List => self.byte(9),
+            Statement::Run => self.byte(10),
+            Statement::End => self.byte(11),
+            Statement::For { variable, from, to, step } => {
+                self.byte(12);
+                self.variable(*variable);
+                self.expression(from);
+                self.expression(to);
+                match step
+                {
+                    Some(step) => {
+                        self.byte(1);
+                        self.expression(step);
+                    },
+                    None => self.byte(0),
+                }
+            },
+            Statement::Next(variable) => {
+                self.byte(13);
+                self.variable(*variable);
+            },
+            Statement::Sequence(statements) => {
+                self.byte(14);
+                self.number(statements.len());
+                statements.iter().for_each(|x| self.statement(x));
+            },
+        }
+    }
+
+    fn expr_list(&mut self, list: &ExprList)
+    {
+        self.expr_list_item(list.expression());
+        self.number(list.cons().len());
+        for element in list.cons()
+        {
+            self.byte(match element.separator()
+            {
+                PrintSeparator::Comma => 0,
+                PrintSeparator::Semicolon => 1,
+            });
+            self.expr_list_item(element.item());
+        }
+    }
+
+    fn expr_list_item(&mut self, item: &ExprListItem)
+    {
+        match item
+        {
+            ExprListItem::String(string) => {
+                self.byte(0);
+                self.string(string);
+            },
+            ExprListItem::StringVar(variable) => {
+                self.byte(1);
+                self.variable(*variable);
+            },
+            ExprListItem::Expression(expression) => {
+                self.byte(2);
+                self.expression(expression);
+            },
+            ExprListItem::Tab(expression) => {
+                self.byte(3);
+                self.expression(expression);
+            },
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression)
+    {
+        // 0 is no prefix at all.
+        self.byte(expression.operator_prefix().map_or(0, expression_prefix_tag));
+        self.term(expression.term());
+        self.number(expression.cons().len());
+        for element in expression.cons()
+        {
+            self.byte(expression_prefix_tag(*element.operator_prefix()));
+            self.term(element.term());
+        }
+    }
+
+    fn term(&mut self, term: &Term)
+    {
+        self.factor(term.factor());
+        self.number(term.cons().len());
+        for element in term.cons()
+        {
+            self.byte(match element.prefix()
+            {
+                TermPrefix::Multiply => 0,
+                TermPrefix::Divide => 1,
+                TermPrefix::Mod => 2,
+            });
+            self.factor(element.factor());
+        }
+    }
+
+    fn factor(&mut self, factor: &Factor)
+    {
+        match factor
+        {
+            Factor::Variable(variable) => {
+                self.byte(0);
+                self.variable(*variable);
+            },
+            Factor::StringVariable(variable) => {
+                self.byte(1);
+                self.variable(*variable);
+            },
+            Factor::Number(number) => {
+                self.byte(2);
+                self.number(*number);
+            },
+            Factor::Expression(expression) => {
+                self.byte(3);
+                self.expression(expression);
+            },
+            Factor::FunctionCall { name, arg } => {
+                self.byte(4);
+                self.byte(match name
+                {
+                    BuiltinFunction::Abs => 0,
+                    BuiltinFunction::Int => 1,
+                    BuiltinFunction::Rnd => 2,
+                });
+                self.expression(arg);
+            },
+        }
+    }
+}
+
+fn expression_prefix_tag(prefix: ExpressionPrefix) -> u8
+{
+    match prefix
+    {
+        ExpressionPrefix::Positive => 1,
+        ExpressionPrefix::Negative => 2,
+    }
+}
+
+fn relop_tag(relop: RelOpSymbol) -> u8
+{
+    match relop
+    {
+        RelOpSymbol::LessThan => 0,
+        RelOpSymbol::LessThanOrEqual => 1,
+        RelOpSymbol::Equal => 2,
+        RelOpSymbol::NotEqual => 3,
+        RelOpSymbol::GreaterThan => 4,
+        RelOpSymbol::GreaterThanOrEqual => 5,
+    }
+}
+
+/// Reads the pieces of a tape written by a [TapeWriter].
+struct TapeReader<'d>
+{
+    data: &'d [u8],
+    offset: usize,
+    /// How many statements and expressions we're inside of right now.
+    depth: usize,
+}
+
+impl TapeReader<'_>
+{
+    fn is_empty(&self) -> bool
+    {
+        self.offset == self.data.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, TapeError>
+    {
+        let byte = *self.data.get(self.offset).ok_or(TapeError::UnexpectedEnd { offset: self.offset })?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    /// Reads a byte that says what comes next. `what` is used to describe it if it's not one of
+    /// the `count` tags there are.
+    fn tag(&mut self, what: &'static str, count: u8) -> Result<u8, TapeError>
+    {
+        let offset = self.offset;
+        match self.byte()?
+        {
+            tag if tag < count => Ok(tag),
+            tag => Err(TapeError::InvalidTag { what, tag, offset }),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, TapeError>
+    {
+        let offset = self.offset;
+        let mut number: usize = 0;
+        let mut shift = 0;
+        loop
+        {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as usize;
+            if shift >= usize::BITS || (bits << shift) >> shift != bits
+            {
+                return Err(TapeError::NumberTooLarge { offset });
+            }
+            number |= bits << shift;
+            if byte & 0x80 == 0
+            {
+                return Ok(number);
+            }
+            shift += 7;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, TapeError>
+    {
+        let offset = self.offset;
+        let len = self.number()?;
+        let end = self.offset.checked_add(len)
+            .filter(|x| *x <= self.data.len())
+            .ok_or(TapeError::UnexpectedEnd { offset: self.data.len() })?;
+        let string = std::str::from_utf8(&self.data[self.offset..end])
+            .map_err(|_| TapeError::InvalidString { offset })?;
+        self.offset = end;
+        Ok(string.to_string())
+    }
+
+    fn variable(&mut self) -> Result<Variable, TapeError>
+    {
+        let offset = self.offset;
+        let value = self.byte()?;
+        Variable::all().nth(value as usize).ok_or(TapeError::InvalidVariable { value, offset })
+    }
+
+    /// Runs `read` one level deeper, failing if that's past [MAX_TAPE_NESTING].
+    fn nested<T>(&mut self, read: impl FnOnce(&mut Self) -> Result<T, TapeError>) -> Result<T, TapeError>
+    {
+        if self.depth == MAX_TAPE_NESTING
+        {
+            return Err(TapeError::TooDeep { offset: self.offset });
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn line(&mut self) -> Result<Line, TapeError>
+    {
+        let line_number = u16::from_le_bytes([self.byte()?, self.byte()?]);
+        let line_number = (line_number != 0).then_some(line_number as usize);
+        Ok(Line::new(line_number, self.statement()?))
+    }
+
+    fn statement(&mut self) -> Result<Statement, TapeError>
+    {
+        self.nested(Self::statement_body)
+    }
+
+    fn statement_body(&mut self) -> Result<Statement, TapeError>
+    {
+        Ok(match self.tag("statement", 15)?
+        {
+            0 => Statement::Print(self.expr_list()?),
+            1 => {
+                let l_expression = self.expression()?;
+                let relop = match self.tag("relational operator", 6)?
+                {
+                    0 => RelOpSymbol::LessThan,
+                    1 => RelOpSymbol::LessThanOrEqual,
+                    2 => RelOpSymbol::Equal,
+                    3 => RelOpSymbol::NotEqual,
+                    4 => RelOpSymbol::GreaterThan,
+                    _ => RelOpSymbol::GreaterThanOrEqual,
+                };
+                let r_expression = self.expression()?;
+                Statement::If(IfData::new(l_expression, relop, r_expression, Box::new(self.statement()?)))
+            },
+            2 => Statement::Goto(self.expression()?),
+            3 => {
+                let offset = self.offset;
+                let count = self.number()?;
+                if count == 0
+                {
+                    return Err(TapeError::InvalidTag { what: "variable count", tag: 0, offset });
+                }
+                let variable = self.variable()?;
+                let cons = (1..count).map(|_| self.variable()).collect::<Result<_, _>>()?;
+                Statement::Input(VariableList::new(variable, cons))
+            },
+            4 => Statement::Let(LetData::new(self.variable()?, self.expression()?)),
+            5 => Statement::LetString { variable: self.variable()?, value: self.expr_list_item()? },
+            6 => Statement::GoSub(self.expression()?),
+            7 => Statement::Return,
+            8 => Statement::Clear,
+            9 => Statement::List,
+            10 => Statement::Run,
+            11 => Statement::End,
+            12 => {
+                let variable = self.variable()?;
+                let from = self.expression()?;
+                let to = self.expression()?;
+                let step = match self.tag("step", 2)?
+                {
+                    0 => None,
+                    _ => Some(self.expression()?),
+                };
+                Statement::For { variable, from, to, step }
+            },
+            13 => Statement::Next(self.variable()?),
+            _ => {
+                let count = self.number()?;
+                // Don't trust the count with an allocation, every statement is at least a byte.
+                let mut statements = Vec::with_capacity(count.min(self.data.len() - self.offset));
+                for _ in 0..count
+                {
+                    statements.push(self.statement()?);
+                }
+                Statement::Sequence(statements)
+            },
+        })
+    }
+
+    fn expr_list(&mut self) -> Result<ExprList, TapeError>
+    {
+        let expression = self.expr_list_item()?;
+        let count = self.number()?;
+        let mut cons = Vec::new();
+        for _ in 0..count
+        {
+            let separator = match self.tag("separator", 2)?
+            {
+                0 => PrintSeparator::Comma,
+                _ => PrintSeparator::Semicolon,
+            };
+            cons.push(ExprListElement::new(separator, self.expr_list_item()?));
+        }
+        Ok(ExprList::new(expression, cons))
+    }
+
+    fn expr_list_item(&mut self) -> Result<ExprListItem, TapeError>
+    {
+        Ok(match self.tag("print item", 4)?
+        {
+            0 => ExprListItem::String(self.string()?),
+            1 => ExprListItem::StringVar(self.variable()?),
+            2 => ExprListItem::Expression(self.expression()?),
+            _ => ExprListItem::Tab(self.expression()?),
+        })
+    }
+
+    fn expression_prefix(&mut self) -> Result<ExpressionPrefix, TapeError>
+    {
+        let offset = self.offset;
+        match self.byte()?
+        {
+            1 => Ok(ExpressionPrefix::Positive),
+            2 => Ok(ExpressionPrefix::Negative),
+            tag => Err(TapeError::InvalidTag { what: "+ or -", tag, offset }),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expression, TapeError>
+    {
+        self.nested(Self::expression_body)
+    }
+
+    fn expression_body(&mut self) -> Result<Expression, TapeError>
+    {
+        let operator_prefix = match self.tag("expression prefix", 3)?
+        {
+            0 => None,
+            1 => Some(ExpressionPrefix::Positive),
+            _ => Some(ExpressionPrefix::Negative),
+        };
+        let term = self.term()?;
+        let count = self.number()?;
+        let mut cons = Vec::new();
+        for _ in 0..count
+        {
+            let operator_prefix = self.expression_prefix()?;
+            cons.push(ExpressionElement::new(operator_prefix, self.term()?));
+        }
+        Ok(Expression::new(operator_prefix, term, cons))
+    }
+
+    fn term(&mut self) -> Result<Term, TapeError>
+    {
+        let factor = self.factor()?;
+        let count = self.number()?;
+        let mut cons = Vec::new();
+        for _ in 0..count
+        {
+            let prefix = match self.tag("*, / or MOD", 3)?
+            {
+                0 => TermPrefix::Multiply,
+                1 => TermPrefix::Divide,
+                _ => TermPrefix::Mod,
+            };
+            cons.push(TermElement::new(prefix, self.factor()?));
+        }
+        Ok(Term::new(factor, cons))
+    }
+
+    fn factor(&mut self) -> Result<Factor, TapeError>
+    {
+        Ok(match self.tag("factor", 5)?
+        {
+            0 => Factor::Variable(self.variable()?),
+            1 => Factor::StringVariable(self.variable()?),
+            2 => Factor::Number(self.number()?),
+            3 => Factor::Expression(Box::new(self.expression()?)),
+            _ => {
+                let name = match self.tag("function", 3)?
+                {
+                    0 => BuiltinFunction::Abs,
+                    1 => BuiltinFunction::Int,
+                    _ => BuiltinFunction::Rnd,
+                };
+                Factor::FunctionCall { name, arg: Box::new(self.expression()?) }
+            },
+        })
+    }
+}
+
+impl Program
+{
+    /// Writes the program to a compact binary tape that [Program::from_tape] can read back.
+    ///
+    /// The tape is a 4-byte magic number, then each line in the order it was added: a 2-byte
+    /// little endian line number (0 for a line without one), a 1-byte statement tag and whatever
+    /// the statement needs after that. Numbers and lengths inside statements are
+    /// [LEB128](https://en.wikipedia.org/wiki/LEB128), so they're usually a single byte.
+    ///
+    /// # Panics
+    ///
+    /// If a line number is 0 or doesn't fit in 2 bytes. The parser accepts both, so check with
+    /// [Program::try_to_tape] unless you know where the program came from.
+    pub fn to_tape(&self) -> Vec<u8>
+    {
+        self.try_to_tape().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Same as [Program::to_tape], but returns [TapeError::LineNumberOutOfRange] for a line
+    /// number that can't be written instead of panicking.
+    pub fn try_to_tape(&self) -> Result<Vec<u8>, TapeError>
+    {
+        let mut writer = TapeWriter { bytes: MAGIC.to_vec() };
+        for line in self.instructions()
+        {
+            let line_number = match line.line_number()
+            {
+                Some(number) => u16::try_from(number)
+                    .ok()
+                    .filter(|x| *x != 0)
+                    .ok_or(TapeError::LineNumberOutOfRange(number))?,
+                None => 0,
+            };
+            writer.bytes.extend_from_slice(&line_number.to_le_bytes());
+            writer.statement(line.statement());
+        }
+        Ok(writer.bytes)
+    }
+
+    /// Reads a program written by [Program::to_tape].
+    pub fn from_tape(data: &[u8]) -> Result<Program, TapeError>
+    {
+        if !data.starts_with(&MAGIC)
+        {
+            return Err(TapeError::BadMagic);
+        }
+        let mut reader = TapeReader { data, offset: MAGIC.len(), depth: 0 };
+        let mut program = Program::new();
+        while !reader.is_empty()
+        {
+            // Adding a line can't fail.
+            let _ = program.add_line(reader.line()?);
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::lang::create_lexer;
+    use crate::lang::parser::Parser;
+    use crate::lang::token::Token;
+
+    use super::*;
+
+    fn variable(c: char) -> Variable
+    {
+        Variable::from_char(c).unwrap()
+    }
+
+    fn expression(factor: Factor) -> Expression
+    {
+        Expression::new(None, Term::new(factor, Vec::new()), Vec::new())
+    }
+
+    /// The program the way `LIST` would show it, with every line in the order it was added.
+    fn listing(program: &Program) -> Vec<String>
+    {
+        program.instructions().iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_hello_world_round_trip()
+    {
+        let a = variable('A');
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(10), Statement::Clear)).unwrap();
+        program.add_line(Line::new(Some(20), Statement::Print(ExprList::new(
+            ExprListItem::String(String::from("What is your name?")),
+            Vec::new(),
+        )))).unwrap();
+        program.add_line(Line::new(Some(30), Statement::Input(VariableList::new(a, Vec::new())))).unwrap();
+        program.add_line(Line::new(Some(40), Statement::Print(ExprList::new(
+            ExprListItem::String(String::from("Hello, ")),
+            vec![ExprListElement::new(PrintSeparator::Comma, ExprListItem::Expression(expression(Factor::Variable(a))))],
+        )))).unwrap();
+
+        let tape = program.to_tape();
+        assert_eq!(&tape[..6], b"TBT1\x0a\x00");
+        assert_eq!(tape[6], 8);
+
+        let read = Program::from_tape(&tape).unwrap();
+        assert_eq!(listing(&read), listing(&program));
+        assert_eq!(listing(&read), vec![
+            "10 CLEAR",
+            "20 PRINT \"What is your name?\"",
+            "30 INPUT A",
+            "40 PRINT \"Hello, \", A",
+        ]);
+    }
+
+    #[test]
+    fn test_every_statement_round_trip()
+    {
+        let source = "PRINT \"A\"; TAB(2), -ABS(A - 1) * (2 + B) MOD 300 / RND(7), INT(+C), A$\n\
+                      LET A$ = \"HI\" : LET B$ = A$\n\
+                      CLEAR\nLIST\nEND\n";
+        let tokens: Vec<Token> = create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap();
+        let mut program = Parser::new(tokens).parse().unwrap();
+
+        let a = variable('A');
+        let five = || expression(Factor::Number(5));
+        let minus_two = Expression::new(Some(ExpressionPrefix::Negative), Term::new(Factor::Number(2), Vec::new()), Vec::new());
+        program.add_line(Line::new(Some(65535), Statement::Sequence(vec![
+            Statement::For { variable: variable('I'), from: expression(Factor::Number(1)), to: five(), step: Some(minus_two) },
+            Statement::Next(variable('I')),
+            Statement::For { variable: variable('J'), from: five(), to: five(), step: None },
+        ]))).unwrap();
+        for statement in [
+            Statement::If(IfData::new(expression(Factor::Variable(a)), RelOpSymbol::NotEqual, five(), Box::new(
+                Statement::If(IfData::new(five(), RelOpSymbol::LessThan, five(), Box::new(Statement::Goto(five())))),
+            ))),
+            Statement::Goto(five()),
+            Statement::GoSub(expression(Factor::StringVariable(a))),
+            Statement::Input(VariableList::new(a, vec![variable('B'), variable('Z')])),
+            Statement::Return,
+            Statement::Run,
+        ]
+        {
+            program.add_line(Line::new(None, statement)).unwrap();
+        }
+
+        let read = Program::from_tape(&program.to_tape()).unwrap();
+        assert_eq!(listing(&read), listing(&program));
+        assert_eq!(read.instructions()[5].line_number(), Some(65535));
+    }
+
+    #[test]
+    fn test_empty_program()
+    {
+        assert_eq!(Program::new().to_tape(), b"TBT1");
+        assert!(Program::from_tape(b"TBT1").unwrap().instructions().is_empty());
+    }
+
+    #[test]
+    fn test_bad_tapes()
+    {
+        assert_eq!(Program::from_tape(b"").err(), Some(TapeError::BadMagic));
+        assert_eq!(Program::from_tape(b"TBT2\0\0\x07").err(), Some(TapeError::BadMagic));
+        // A line number with no statement.
+        assert_eq!(Program::from_tape(b"TBT1\x0a\0").err(), Some(TapeError::UnexpectedEnd { offset: 6 }));
+        assert_eq!(
+            Program::from_tape(b"TBT1\0\0\x63").err(),
+            Some(TapeError::InvalidTag { what: "statement", tag: 99, offset: 6 })
+        );
+        // NEXT with variable 26, one past Z.
+        assert_eq!(
+            Program::from_tape(b"TBT1\0\0\x0d\x1a").err(),
+            Some(TapeError::InvalidVariable { value: 26, offset: 7 })
+        );
+        // PRINT with a string that's longer than the tape, then one that isn't UTF-8.
+        assert_eq!(
+            Program::from_tape(b"TBT1\0\0\0\0\x05AB\0").err(),
+            Some(TapeError::UnexpectedEnd { offset: 12 })
+        );
+        assert_eq!(
+            Program::from_tape(b"TBT1\0\0\0\0\x01\xff\0").err(),
+            Some(TapeError::InvalidString { offset: 8 })
+        );
+        // GOTO a number with too many bytes to fit.
+        let mut tape = b"TBT1\0\0\x02\0\x02".to_vec();
+        tape.extend([0xff; 11]);
+        tape.extend([0x01, 0x00, 0x00]);
+        assert_eq!(Program::from_tape(&tape).err(), Some(TapeError::NumberTooLarge { offset: 9 }));
+    }
+
+    #[test]
+    fn test_nesting_limit()
+    {
+        // GOTO (((((... with nothing to close them, far too deep to read recursively.
+        let mut tape = b"TBT1\0\0\x02".to_vec();
+        for _ in 0..200_000
+        {
+            tape.extend([0, 3]);
+        }
+        // The GOTO is the first level, its expression the second and each bracket another.
+        let offset = 7 + 2 * (MAX_TAPE_NESTING - 1);
+        assert_eq!(Program::from_tape(&tape).err(), Some(TapeError::TooDeep { offset }));
+
+        // Just under the limit still reads.
+        let mut source = String::from("GOTO ");
+        source.push_str(&"(".repeat(MAX_TAPE_NESTING - 2));
+        source.push('1');
+        source.push_str(&")".repeat(MAX_TAPE_NESTING - 2));
+        let tokens: Vec<Token> = create_lexer().parse_stream(&source).collect::<Result<_, _>>().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(Program::from_tape(&program.to_tape()).unwrap().to_string(), program.to_string());
+    }
+
+    #[test]
+    fn test_try_to_tape_line_numbers()
+    {
+        for (source, number) in [("0 PRINT 1", 0), ("70000 END", 70000)]
+        {
+            let tokens: Vec<Token> = create_lexer().parse_stream(source).collect::<Result<_, _>>().unwrap();
+            let program = Parser::new(tokens).parse().unwrap();
+            assert_eq!(program.try_to_tape(), Err(TapeError::LineNumberOutOfRange(number)));
+        }
+
+        let tokens: Vec<Token> = create_lexer().parse_stream("65535 END\nEND").collect::<Result<_, _>>().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(program.try_to_tape().unwrap(), program.to_tape());
+    }
+
+    #[test]
+    #[should_panic(expected = "Line number 70000 can't be written to a tape")]
+    fn test_line_number_too_large()
+    {
+        let mut program = Program::new();
+        program.add_line(Line::new(Some(70000), Statement::End)).unwrap();
+        program.to_tape();
+    }
+}
@@ -0,0 +1,34 @@
+#![cfg(test)]
+//! Helpers for writing lexer tests without spelling out every [Token].
+
+use crate::lang::create_lexer;
+use crate::lang::token::Token;
+
+/// A short description of `token`, like `num:10`, `kw:CLEAR`, `var:A`, `strvar:A`, `str:hi`,
+/// `sym:+` or `nl`.
+pub fn describe_token(token: &Token) -> String
+{
+    match token
+    {
+        Token::Keyword(keyword) => format!("kw:{}", keyword),
+        Token::Variable(variable) => format!("var:{}", variable),
+        Token::StringVariable(variable) => format!("strvar:{}", variable),
+        Token::Number(number) => format!("num:{}", number),
+        Token::String(string) => format!("str:{}", string),
+        Token::Symbol(symbol) => format!("sym:{}", symbol),
+        Token::NewLine => String::from("nl"),
+    }
+}
+
+/// Lexes `source` and checks the tokens match `expected`, written the way [describe_token] writes
+/// them. Panics if lexing fails.
+#[track_caller]
+pub fn assert_tokens(source: &str, expected: &[&str])
+{
+    let tokens: Vec<Token> = create_lexer()
+        .parse_stream(source)
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|error| panic!("{:?} failed to lex: {}", source, error));
+    let described: Vec<String> = tokens.iter().map(describe_token).collect();
+    assert_eq!(described, expected, "tokens for {:?}", source);
+}
@@ -1,16 +1,22 @@
 //! Defines the input stream language that gets parsed into an AST.
-use std::str::FromStr;
+use core::{fmt::Display, str::FromStr};
+use alloc::sync::Arc;
+use derive_more::Into;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
 use thiserror::Error;
-use crate::lang::ast::Variable;
 
 /// A token of some kind
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Token
 {
     Keyword(Keyword),
     Variable(Variable),
+    /// A string variable, like `A$`. An extension to Tiny BASIC.
+    StringVariable(Variable),
     Number(usize),
-    String(String),
+    String(Arc<str>),
     Symbol(Symbol),
     NewLine,
 }
@@ -18,16 +24,146 @@ pub enum Token
 impl Token
 {
     pub fn is_keyword(&self) -> bool
+    {
+        matches!(self, Self::Keyword(_))
+    }
+
+    pub fn is_variable(&self) -> bool
+    {
+        matches!(self, Self::Variable(_))
+    }
+
+    pub fn is_string_variable(&self) -> bool
+    {
+        matches!(self, Self::StringVariable(_))
+    }
+
+    pub fn is_number(&self) -> bool
+    {
+        matches!(self, Self::Number(_))
+    }
+
+    pub fn is_string(&self) -> bool
+    {
+        matches!(self, Self::String(_))
+    }
+
+    pub fn is_symbol(&self) -> bool
+    {
+        matches!(self, Self::Symbol(_))
+    }
+
+    pub fn is_newline(&self) -> bool
+    {
+        matches!(self, Self::NewLine)
+    }
+
+    /// Returns the keyword if this is a [Token::Keyword], [None] otherwise.
+    pub fn as_keyword(&self) -> Option<&Keyword>
+    {
+        match self
+        {
+            Self::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    /// Returns the variable if this is a [Token::Variable], [None] otherwise.
+    pub fn as_variable(&self) -> Option<Variable>
+    {
+        match self
+        {
+            Self::Variable(variable) => Some(*variable),
+            _ => None,
+        }
+    }
+
+    /// Returns the variable if this is a [Token::StringVariable], [None] otherwise.
+    pub fn as_string_variable(&self) -> Option<Variable>
     {
         match self
         {
-            Self::Keyword(_) => true,
-            _ => false,
+            Self::StringVariable(variable) => Some(*variable),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if this is a [Token::Number], [None] otherwise.
+    pub fn as_number(&self) -> Option<usize>
+    {
+        match self
+        {
+            Self::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of the string if this is a [Token::String], [None] otherwise.
+    pub fn as_string(&self) -> Option<&str>
+    {
+        match self
+        {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Returns the symbol if this is a [Token::Symbol], [None] otherwise.
+    pub fn as_symbol(&self) -> Option<Symbol>
+    {
+        match self
+        {
+            Self::Symbol(symbol) => Some(*symbol),
+            _ => None,
         }
     }
 }
+impl From<Keyword> for Token
+{
+    fn from(value: Keyword) -> Self
+    {
+        Self::Keyword(value)
+    }
+}
+
+impl From<Symbol> for Token
+{
+    fn from(value: Symbol) -> Self
+    {
+        Self::Symbol(value)
+    }
+}
+
+impl From<Variable> for Token
+{
+    fn from(value: Variable) -> Self
+    {
+        Self::Variable(value)
+    }
+}
+
+impl Display for Token
+{
+    /// Writes the token the way it would appear in source code, so `PRINT`, `A`, `A$`, `42`, `+`
+    /// or `"hi"`. A `"` inside a string is doubled, like `"SAY ""HI"""`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        match self
+        {
+            Self::Keyword(keyword) => write!(f, "{}", keyword),
+            Self::Variable(variable) => write!(f, "{}", variable),
+            Self::StringVariable(variable) => write!(f, "{}$", variable),
+            Self::Number(number) => write!(f, "{}", number),
+            Self::String(string) => write!(f, "\"{}\"", string.replace('"', "\"\"")),
+            Self::Symbol(symbol) => write!(f, "{}", symbol),
+            Self::NewLine => writeln!(f),
+        }
+    }
+}
+
 /// Language keywords, as defined [here](https://en.wikipedia.org/wiki/Tiny_BASIC#Formal_grammar)
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Keyword
 {
     Print,
@@ -41,7 +177,86 @@ pub enum Keyword
     Clear,
     List,
     Run,
-    End
+    End,
+    /// `FOR`, `TO`, `STEP` and `NEXT` aren't in Tiny BASIC proper. They're an extension for loops.
+    For,
+    To,
+    Step,
+    Next,
+    /// `ABS`, `INT` and `RND` are the names of
+    /// [built-in functions](crate::lang::ast::BuiltinFunction), not statements.
+    Abs,
+    Int,
+    Rnd,
+    /// `MOD` is an operator, like `A MOD 3`.
+    Mod,
+    /// `TAB(n)` moves to column `n` in a `PRINT`.
+    Tab,
+}
+
+/// Every keyword along with its canonical spelling.
+const KEYWORDS: [(&str, Keyword); 21] = [
+    ("PRINT", Keyword::Print),
+    ("IF", Keyword::If),
+    ("THEN", Keyword::Then),
+    ("GOTO", Keyword::Goto),
+    ("INPUT", Keyword::Input),
+    ("LET", Keyword::Let),
+    ("GOSUB", Keyword::GoSub),
+    ("RETURN", Keyword::Return),
+    ("CLEAR", Keyword::Clear),
+    ("LIST", Keyword::List),
+    ("RUN", Keyword::Run),
+    ("END", Keyword::End),
+    ("FOR", Keyword::For),
+    ("TO", Keyword::To),
+    ("STEP", Keyword::Step),
+    ("NEXT", Keyword::Next),
+    ("ABS", Keyword::Abs),
+    ("INT", Keyword::Int),
+    ("RND", Keyword::Rnd),
+    ("MOD", Keyword::Mod),
+    ("TAB", Keyword::Tab),
+];
+
+/// Every keyword, in the same order as [KEYWORDS].
+const VARIANTS: [Keyword; KEYWORDS.len()] = {
+    let mut variants = [Keyword::Print; KEYWORDS.len()];
+    let mut i = 0;
+    while i < KEYWORDS.len()
+    {
+        variants[i] = KEYWORDS[i].1;
+        i += 1;
+    }
+    variants
+};
+
+impl Keyword
+{
+    /// Returns the canonical (uppercase) spelling of the keyword.
+    pub fn as_str(&self) -> &'static str
+    {
+        KEYWORDS.iter()
+            .find(|(_, keyword)| keyword == self)
+            .map(|(name, _)| *name)
+            // Every keyword is in the table.
+            .unwrap()
+    }
+
+    /// Every keyword in the language.
+    pub fn variants() -> &'static [Keyword]
+    {
+        &VARIANTS
+    }
+}
+
+impl Display for Keyword
+{
+    /// Writes the keyword's canonical (uppercase) spelling.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 impl FromStr for Keyword
@@ -49,59 +264,12 @@ impl FromStr for Keyword
     /// Only returns one error: when a string was not one of the expected keywords.
     type Err = ();
 
+    /// Keywords are case insensitive, so `print`, `PRINT` and `PrInT` are all [Keyword::Print].
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.eq_ignore_ascii_case("print")
-        {
-            Ok(Self::Print)
-        }
-        else if s.eq_ignore_ascii_case("if")
-        {
-            Ok(Self::If)
-        }
-        else if s.eq_ignore_ascii_case("then")
-        {
-            Ok(Self::Then)
-        }
-        else if s.eq_ignore_ascii_case("goto")
-        {
-            Ok(Self::Goto)
-        }
-        else if s.eq_ignore_ascii_case("input")
-        {
-            Ok(Self::Input)
-        }
-        else if s.eq_ignore_ascii_case("let")
-        {
-            Ok(Self::Let)
-        }
-        else if s.eq_ignore_ascii_case("gosub")
-        {
-            Ok(Self::GoSub)
-        }
-        else if s.eq_ignore_ascii_case("return")
-        {
-            Ok(Self::Return)
-        }
-        else if s.eq_ignore_ascii_case("clear")
-        {
-            Ok(Self::Clear)
-        }
-        else if s.eq_ignore_ascii_case("list")
-        {
-            Ok(Self::List)
-        }
-        else if s.eq_ignore_ascii_case("run")
-        {
-            Ok(Self::Run)
-        }
-        else if s.eq_ignore_ascii_case("end")
-        {
-            Ok(Self::End)
-        }
-        else
-        {
-            Err(())
-        }
+        KEYWORDS.iter()
+            .find(|(name, _)| s.eq_ignore_ascii_case(name))
+            .map(|(_, keyword)| *keyword)
+            .ok_or(())
     }
 }
 
@@ -110,6 +278,7 @@ impl FromStr for Keyword
 /// All of the accepted symbols by the language?
 /// We don't want to interpret here, just parse.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum Symbol
 {
     LessThanSign,
@@ -120,6 +289,10 @@ pub enum Symbol
     Times,
     Divide,
     Comma,
+    LeftParen,
+    RightParen,
+    Semicolon,
+    Colon,
 }
 
 #[derive(Debug, Error)]
@@ -129,6 +302,48 @@ pub enum SymbolFromStrError
     UnrecognizedSymbol,
 }
 
+impl Symbol
+{
+    /// Returns the character this symbol is written as.
+    pub fn as_char(&self) -> char
+    {
+        match self
+        {
+            Self::LessThanSign => '<',
+            Self::GreaterThanSign => '>',
+            Self::EqualsSign => '=',
+            Self::Plus => '+',
+            Self::Minus => '-',
+            Self::Times => '*',
+            Self::Divide => '/',
+            Self::Comma => ',',
+            Self::LeftParen => '(',
+            Self::RightParen => ')',
+            Self::Semicolon => ';',
+            Self::Colon => ':',
+        }
+    }
+}
+
+impl Display for Symbol
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+impl TryFrom<char> for Symbol
+{
+    type Error = SymbolFromStrError;
+
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        // Every symbol is ASCII, so anything that doesn't fit in a byte can't be one.
+        let value = u8::try_from(value).map_err(|_| Self::Error::UnrecognizedSymbol)?;
+        Self::try_from(value)
+    }
+}
+
 impl TryFrom<u8> for Symbol
 {
     type Error = SymbolFromStrError;
@@ -144,8 +359,303 @@ impl TryFrom<u8> for Symbol
             b'*' => Ok(Self::Times),
             b'/' => Ok(Self::Divide),
             b',' => Ok(Self::Comma),
+            b'(' => Ok(Self::LeftParen),
+            b')' => Ok(Self::RightParen),
+            b';' => Ok(Self::Semicolon),
+            b':' => Ok(Self::Colon),
             _ => Err(Self::Error::UnrecognizedSymbol)
         }
     }
 }
 
+/// A variable is any single letter from A-Z.
+/// We'll convert it to 0-25 internally probably?
+#[derive(Debug, PartialEq, Eq, Into, Copy, Clone)]
+pub struct Variable(u8);
+
+#[derive(Debug, Error)]
+pub enum VariableFromU8Error
+{
+    #[error("Variable character out of range, must be an ASCII character between A and Z, upper case or lowercase.")]
+    CharacterOutOfRange,
+    #[error("A variable is exactly one letter long.")]
+    WrongLength,
+}
+
+impl TryFrom<u8> for Variable
+{
+    type Error = VariableFromU8Error;
+
+    /// Attempts to convert a u8 into a [Variable].
+    ///
+    /// A u8 can only be converted into a [Variable] if it represents an ASCII character between
+    /// 'A' and 'Z' (inclusive) or 'a' and 'z' (inclusive). Otherwise the conversion failes and a
+    /// [VariableFromU8Error] is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - A u8 representing a single ASCII character or byte. Must be a character
+    /// between 'A'-'Z' or 'a'-'z'
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tiny_basic_compiler::lang::token::Variable;
+    /// # use tiny_basic_compiler::lang::token::VariableFromU8Error;
+    /// let variable: Result<Variable, VariableFromU8Error> = b'A'.try_into();
+    /// assert!(variable.is_ok());
+    /// let variable = variable.unwrap();
+    /// let variable_u8: u8 = variable.into();
+    /// assert_eq!(variable_u8, 0);
+    /// let variable: Result<Variable, VariableFromU8Error> = 0.try_into();
+    /// assert!(variable.is_err());
+    /// ```
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value
+        {
+            x @ b'A'..=b'Z' => Ok(Self(x - b'A')),
+            x @ b'a'..=b'z' => Ok(Self(x - b'a')),
+            _ => Err(VariableFromU8Error::CharacterOutOfRange),
+        }
+    }
+}
+
+impl TryFrom<&str> for Variable
+{
+    type Error = VariableFromU8Error;
+
+    /// Converts a string holding exactly one ASCII letter, like `"A"` or `"z"`, into a
+    /// [Variable]. Empty strings and anything longer give [VariableFromU8Error::WrongLength].
+    fn try_from(value: &str) -> Result<Self, Self::Error>
+    {
+        match value.as_bytes()
+        {
+            [x] => Self::try_from(*x),
+            _ => Err(VariableFromU8Error::WrongLength),
+        }
+    }
+}
+
+impl Variable
+{
+    /// All 26 variables, A through Z.
+    pub fn all() -> impl Iterator<Item = Variable>
+    {
+        (0..26).map(Self)
+    }
+
+    /// The upper case letter for this variable.
+    pub fn to_char(self) -> char
+    {
+        (b'A' + self.0) as char
+    }
+
+    /// Gets the variable for an ASCII letter, upper case or lower case. Returns [None] for
+    /// anything else.
+    pub fn from_char(c: char) -> Option<Variable>
+    {
+        u8::try_from(c).ok()?.try_into().ok()
+    }
+}
+
+impl Display for Variable
+{
+    /// Writes the variable as its upper case letter, A-Z.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Variable
+{
+    /// Serializes the variable as its letter, the same as [Display].
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests
+{
+    use super::*;
+
+    /// Listed separately from [KEYWORDS] so we notice if a keyword is missing from the table.
+    const ALL_KEYWORDS: [Keyword; 21] = [
+        Keyword::Print,
+        Keyword::If,
+        Keyword::Then,
+        Keyword::Goto,
+        Keyword::Input,
+        Keyword::Let,
+        Keyword::GoSub,
+        Keyword::Return,
+        Keyword::Clear,
+        Keyword::List,
+        Keyword::Run,
+        Keyword::End,
+        Keyword::For,
+        Keyword::To,
+        Keyword::Step,
+        Keyword::Next,
+        Keyword::Abs,
+        Keyword::Int,
+        Keyword::Rnd,
+        Keyword::Mod,
+        Keyword::Tab,
+    ];
+
+    #[test]
+    fn test_token_is_helpers()
+    {
+        let variable = Token::Variable(Variable::try_from(b'A').unwrap());
+        assert!(Token::Keyword(Keyword::Print).is_keyword());
+        assert!(!Token::NewLine.is_keyword());
+        assert!(variable.is_variable());
+        assert!(!Token::Number(1).is_variable());
+        assert!(Token::Number(1).is_number());
+        assert!(!variable.is_number());
+        assert!(Token::String(Arc::from("hi")).is_string());
+        assert!(!Token::Number(1).is_string());
+        assert!(Token::Symbol(Symbol::Plus).is_symbol());
+        assert!(!Token::String(Arc::from("+")).is_symbol());
+        assert!(Token::NewLine.is_newline());
+        assert!(!Token::String(Arc::from("\n")).is_newline());
+    }
+
+    #[test]
+    fn test_token_as_helpers()
+    {
+        assert_eq!(Token::Keyword(Keyword::Print).as_keyword(), Some(&Keyword::Print));
+        assert_eq!(Token::NewLine.as_keyword(), None);
+        let a = Variable::try_from(b'A').unwrap();
+        assert_eq!(Token::Variable(a).as_variable(), Some(a));
+        assert_eq!(Token::NewLine.as_variable(), None);
+        assert_eq!(Token::Number(10).as_number(), Some(10));
+        assert_eq!(Token::Variable(a).as_number(), None);
+        assert_eq!(Token::String(Arc::from("hi")).as_string(), Some("hi"));
+        assert_eq!(Token::Number(10).as_string(), None);
+        assert_eq!(Token::Symbol(Symbol::Comma).as_symbol(), Some(Symbol::Comma));
+        assert_eq!(Token::Keyword(Keyword::Let).as_symbol(), None);
+    }
+
+    #[test]
+    fn test_keyword_as_str_round_trips()
+    {
+        for keyword in ALL_KEYWORDS
+        {
+            assert_eq!(Keyword::from_str(keyword.as_str()), Ok(keyword));
+            assert_eq!(Keyword::from_str(&keyword.as_str().to_lowercase()), Ok(keyword));
+        }
+    }
+
+    #[test]
+    fn test_keyword_variants()
+    {
+        assert_eq!(Keyword::variants(), ALL_KEYWORDS);
+    }
+
+    #[test]
+    fn test_keyword_and_symbol_display()
+    {
+        for keyword in Keyword::variants()
+        {
+            assert_eq!(keyword.to_string(), keyword.as_str());
+        }
+        assert_eq!(Keyword::GoSub.to_string(), "GOSUB");
+        assert_eq!(Symbol::LessThanSign.to_string(), "<");
+        assert_eq!(Symbol::Comma.to_string(), ",");
+    }
+
+    #[test]
+    fn test_keyword_as_str_is_uppercase()
+    {
+        for keyword in ALL_KEYWORDS
+        {
+            assert_eq!(keyword.as_str(), keyword.as_str().to_uppercase());
+        }
+    }
+
+    #[test]
+    fn test_keyword_from_str_is_case_insensitive()
+    {
+        assert_eq!(Keyword::from_str("GoSub"), Ok(Keyword::GoSub));
+    }
+
+    const ALL_SYMBOLS: [Symbol; 12] = [
+        Symbol::LessThanSign,
+        Symbol::GreaterThanSign,
+        Symbol::EqualsSign,
+        Symbol::Plus,
+        Symbol::Minus,
+        Symbol::Times,
+        Symbol::Divide,
+        Symbol::Comma,
+        Symbol::LeftParen,
+        Symbol::RightParen,
+        Symbol::Semicolon,
+        Symbol::Colon,
+    ];
+
+    #[test]
+    fn test_symbol_as_char_round_trips()
+    {
+        for symbol in ALL_SYMBOLS
+        {
+            assert_eq!(Symbol::try_from(symbol.as_char()).unwrap(), symbol);
+        }
+    }
+
+    #[test]
+    fn test_symbol_try_from_char_rejects_non_symbol()
+    {
+        assert!(Symbol::try_from('x').is_err());
+        assert!(Symbol::try_from('\u{2264}').is_err());
+    }
+
+    #[test]
+    fn test_keyword_from_str_rejects_non_keyword()
+    {
+        assert_eq!(Keyword::from_str("PRINTS"), Err(()));
+        assert_eq!(Keyword::from_str(""), Err(()));
+    }
+
+    #[test]
+    fn test_token_from()
+    {
+        let a = Variable::try_from(b'A').unwrap();
+        assert_eq!(Token::from(Keyword::Print), Token::Keyword(Keyword::Print));
+        assert_eq!(Token::from(Symbol::Plus), Token::Symbol(Symbol::Plus));
+        assert_eq!(Token::from(a), Token::Variable(a));
+    }
+
+    #[test]
+    fn test_token_display()
+    {
+        assert_eq!(Token::Number(42).to_string(), "42");
+        assert_eq!(Token::from(Variable::try_from(b'a').unwrap()).to_string(), "A");
+        assert_eq!(Token::from(Keyword::Print).to_string(), "PRINT");
+        assert_eq!(Token::from(Keyword::GoSub).to_string(), "GOSUB");
+        assert_eq!(Token::from(Symbol::Plus).to_string(), "+");
+        assert_eq!(Token::String(Arc::from("hi")).to_string(), "\"hi\"");
+        assert_eq!(Token::String(Arc::from("SAY \"HI\"")).to_string(), "\"SAY \"\"HI\"\"\"");
+        assert_eq!(Token::NewLine.to_string(), "\n");
+    }
+
+    #[test]
+    fn test_token_display_relexes()
+    {
+        let source = "10 IF A <= 5 THEN PRINT \"small\", A * 2\n";
+        let tokens: Vec<Token> = crate::lang::create_lexer()
+            .parse_stream(source)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let printed: Vec<String> = tokens.iter().map(Token::to_string).collect();
+        let relexed: Vec<Token> = crate::lang::create_lexer()
+            .parse_stream(&printed.join(" "))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tokens, relexed);
+    }
+}
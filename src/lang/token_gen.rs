@@ -0,0 +1,105 @@
+#![cfg(test)]
+//! Generates random (but valid) [Token] sequences for property tests of the lexer.
+//!
+//! The generators are `proptest` strategies, so a failing sequence gets shrunk down before it's
+//! reported.
+
+use std::sync::Arc;
+
+use proptest::prelude::*;
+
+use crate::lang::ast::Variable;
+use crate::lang::token::{Keyword, Symbol, Token};
+
+use super::*;
+
+const SYMBOLS: [Symbol; 12] = [
+    Symbol::LessThanSign,
+    Symbol::GreaterThanSign,
+    Symbol::EqualsSign,
+    Symbol::Plus,
+    Symbol::Minus,
+    Symbol::Times,
+    Symbol::Divide,
+    Symbol::Comma,
+    Symbol::LeftParen,
+    Symbol::RightParen,
+    Symbol::Semicolon,
+    Symbol::Colon,
+];
+
+/// Any [Token] the lexer can read back from its [Display](std::fmt::Display) form. String
+/// variables are left out, since [Token::String] is all the escaping there is to test.
+fn token() -> impl Strategy<Value = Token>
+{
+    prop_oneof![
+        prop::sample::select(Keyword::variants()).prop_map(Token::Keyword),
+        prop::sample::select(Variable::all().collect::<Vec<_>>()).prop_map(Token::Variable),
+        number().prop_map(Token::Number),
+        prop::sample::select(SYMBOLS.to_vec()).prop_map(Token::Symbol),
+        string().prop_map(Token::String),
+        Just(Token::NewLine),
+    ]
+}
+
+/// Mostly small numbers, with the occasional huge one.
+fn number() -> impl Strategy<Value = usize>
+{
+    prop_oneof![
+        3 => 0..1000usize,
+        1 => (0..10usize).prop_map(|x| usize::MAX - x),
+    ]
+}
+
+/// Printable ASCII, including `"`, which gets doubled when it's written out.
+fn string() -> impl Strategy<Value = Arc<str>>
+{
+    "[ -~]{0,7}".prop_map(Arc::from)
+}
+
+/// Keywords, variables and numbers run together if they aren't separated (`I` `F` would lex as
+/// `IF`), and so do two strings (`"A"` `"B"` would lex as `A"B`), so those need a space between
+/// them. Everything else can be written right up against its neighbours.
+fn needs_separator(previous: &Token, token: &Token) -> bool
+{
+    let is_word = |x: &Token| x.is_keyword() || x.is_variable() || x.is_number();
+    (is_word(previous) && is_word(token)) || (previous.is_string() && token.is_string())
+}
+
+/// Writes `tokens` out as source code that should lex back into the same tokens.
+fn to_source(tokens: &[Token]) -> String
+{
+    let mut source = String::new();
+    for (i, token) in tokens.iter().enumerate()
+    {
+        if i > 0 && needs_separator(&tokens[i - 1], token)
+        {
+            source.push(' ');
+        }
+        source.push_str(&token.to_string());
+    }
+    source
+}
+
+proptest!
+{
+    #[test]
+    fn test_random_tokens_round_trip(tokens in prop::collection::vec(token(), 0..=30))
+    {
+        let source = to_source(&tokens);
+        let relexed: Result<Vec<Token>, String> = create_lexer()
+            .parse_stream(&source)
+            .collect::<Result<_, _>>()
+            .map_err(|error| error.to_string());
+        prop_assert_eq!(relexed, Ok(tokens), "{:?}", source);
+    }
+}
+
+#[test]
+fn test_to_source_separates_words()
+{
+    let i = Variable::from_char('I').unwrap();
+    let f = Variable::from_char('F').unwrap();
+    let tokens = [Token::Variable(i), Token::Variable(f), Token::Symbol(Symbol::Plus), Token::Number(1)];
+    assert_eq!(to_source(&tokens), "I F+1");
+}
@@ -0,0 +1,145 @@
+//! Compiler-style error messages that point at the spot in the source that went wrong.
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use getset::{CopyGetters, Getters};
+
+use crate::lexer::LexError;
+
+/// A message about a spot in some source text, found from a byte offset.
+///
+/// Lines and columns start at 1. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Eq, Clone, CopyGetters, Getters)]
+pub struct Diagnostic
+{
+    #[getset(get_copy = "pub")]
+    line: usize,
+    #[getset(get_copy = "pub")]
+    column: usize,
+    #[getset(get = "pub")]
+    message: String,
+}
+
+impl Diagnostic
+{
+    /// Makes a diagnostic for the byte `offset` into `source`. An offset past the end points just
+    /// after the last character.
+    pub fn new(source: &str, offset: usize, message: impl Into<String>) -> Self
+    {
+        let (line, column) = line_column(source, offset);
+        Self { line, column, message: message.into() }
+    }
+
+    /// Writes the message, then the line it's about with a `^` under the column, like:
+    ///
+    /// ```text
+    /// line 2, column 10: Expected closing " character!
+    /// 2 | 20 PRINT "HELLO
+    ///   |          ^
+    /// ```
+    ///
+    /// `source` should be the same text the diagnostic was made from.
+    pub fn render(&self, source: &str) -> String
+    {
+        let text = source.split('\n').nth(self.line - 1).unwrap_or("");
+        let text = text.strip_suffix('\r').unwrap_or(text);
+
+        // Tabs are kept so the caret lines up however wide the terminal draws them.
+        let padding: String = text.chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let gutter = " ".repeat(self.line.to_string().len());
+
+        format!(
+            "line {}, column {}: {}\n{} | {}\n{} | {}^",
+            self.line, self.column, self.message,
+            self.line, text,
+            gutter, padding,
+        )
+    }
+
+    /// Makes a diagnostic pointing at where `error` happened in `source`.
+    pub fn from_lex_error(source: &str, error: &LexError) -> Self
+    {
+        Self::new(source, error.offset(), error.to_string())
+    }
+}
+
+/// Renders `error` as a [Diagnostic] pointing into `source`.
+pub fn render(source: &str, error: &LexError) -> String
+{
+    Diagnostic::from_lex_error(source, error).render(source)
+}
+
+/// Finds the line and column of the byte `offset` into `source` by counting newlines.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize)
+{
+    let mut line = 1;
+    let mut column = 1;
+    for (_, c) in source.char_indices().take_while(|(i, _)| *i < offset)
+    {
+        if c == '\n'
+        {
+            line += 1;
+            column = 1;
+        }
+        else
+        {
+            column += 1;
+        }
+    }
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_render_unterminated_string()
+    {
+        let source = "10 PRINT \"HI\"\n20 PRINT \"HELLO\n30 END\n";
+        let error = LexError::UnterminatedString { offset: source.find("\"HELLO").unwrap() };
+
+        assert_eq!(
+            render(source, &error),
+            "line 2, column 10: Expected closing \" character!\n\
+             2 | 20 PRINT \"HELLO\n  \
+               |          ^"
+        );
+    }
+
+    #[test]
+    fn test_line_column()
+    {
+        let source = "AB\r\nCé\nD";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 4), (2, 1));
+        // é is two bytes but one column.
+        assert_eq!(line_column(source, 7), (2, 3));
+        assert_eq!(line_column(source, 8), (3, 1));
+        assert_eq!(line_column(source, 100), (3, 2));
+    }
+
+    #[test]
+    fn test_render_keeps_tabs_and_wide_gutters()
+    {
+        let source = "\n\n\n\n\n\n\n\n\n\tX 99999999999999999999999";
+        let error = LexError::NumberOverflow
+        {
+            offset: source.find('9').unwrap(),
+            text: String::from("99999999999999999999999"),
+        };
+
+        let diagnostic = Diagnostic::from_lex_error(source, &error);
+        assert_eq!((diagnostic.line(), diagnostic.column()), (10, 4));
+        assert_eq!(
+            diagnostic.render(source),
+            "line 10, column 4: Number 99999999999999999999999 is too large\n\
+             10 | \tX 99999999999999999999999\n   \
+                | \t  ^"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Errors produced while lexing.
+use alloc::string::String;
+
+use thiserror::Error;
+
+/// Why a [LexerModule](super::LexerModule) couldn't lex a token it recognized.
+///
+/// Every variant has an `offset`, the byte the broken token starts at. [LexerModule]s report it
+/// relative to the stream they were handed, and the [Lexer](super::Lexer) turns that into an
+/// offset into the whole input.
+///
+/// [LexerModule]: super::LexerModule
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+pub enum LexError
+{
+    /// A string literal is missing its closing `"`.
+    #[error("Expected closing \" character!")]
+    UnterminatedString { offset: usize },
+    /// A number literal is too big to fit in a [usize].
+    #[error("Number {text} is too large")]
+    NumberOverflow { offset: usize, text: String },
+    /// Anything else a module wants to reject.
+    #[error("{message}")]
+    Invalid { offset: usize, message: &'static str },
+}
+
+impl LexError
+{
+    /// The byte the broken token starts at.
+    pub fn offset(&self) -> usize
+    {
+        match self
+        {
+            Self::UnterminatedString { offset }
+            | Self::NumberOverflow { offset, .. }
+            | Self::Invalid { offset, .. } => *offset,
+        }
+    }
+
+    /// Moves the error `by` bytes further into the input.
+    pub(crate) fn shifted(mut self, by: usize) -> Self
+    {
+        match &mut self
+        {
+            Self::UnterminatedString { offset }
+            | Self::NumberOverflow { offset, .. }
+            | Self::Invalid { offset, .. } => *offset += by,
+        }
+        self
+    }
+}
@@ -1,7 +1,9 @@
 //! Defines the core [Lexer] and [LexerBuilder] types.
+use alloc::{boxed::Box, string::String, vec::Vec};
+
 use crate::lexer::LexerModuleResult;
 
-use super::LexerModule;
+use super::{LexerModule, LexError};
 
 pub struct LexerBuilder<L>
 {
@@ -60,21 +62,86 @@ impl<L> Lexer<L>
     {
         TokenIterator { 
             lexer: self,
-            input_stream: input_stream
+            input_stream: input_stream,
+            consumed: 0,
+            line: 1,
+            column: 1,
+            token_position: Position { line: 1, column: 1 },
         }
     }
+
+    /// Lexes all of `source` and hands back the tokens, using up the lexer and the source. The
+    /// tokens don't borrow either of them, so they can be returned from wherever the source was
+    /// read in.
+    pub fn into_tokens(mut self, source: String) -> Result<Vec<L>, LexError>
+    {
+        self.parse_stream(&source).collect()
+    }
+}
+
+/// A line and column in the input, both starting at 1. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position
+{
+    pub line: usize,
+    pub column: usize,
 }
 
 pub struct TokenIterator<'a, L>
 {
     lexer: &'a mut Lexer<L>,
-    input_stream: &'a str
+    input_stream: &'a str,
+    /// How many bytes of the input we've lexed so far, for error offsets.
+    consumed: usize,
+    /// The line and column of the start of `input_stream`.
+    line: usize,
+    column: usize,
+    /// Where the last token we returned started.
+    token_position: Position,
 }
 
 impl<'a, L> TokenIterator<'a, L>
 {
+    /// Returns the part of the input stream that hasn't been lexed yet.
+    pub fn remainder(&self) -> &'a str
+    {
+        self.input_stream
+    }
+
+    /// Where the lexer is up to, which is the start of [TokenIterator::remainder].
+    ///
+    /// This isn't called `position` so it doesn't get mixed up with [Iterator::position].
+    pub fn current_position(&self) -> Position
+    {
+        Position { line: self.line, column: self.column }
+    }
+
+    /// Where the token last returned by [Iterator::next] started, not counting any whitespace in
+    /// front of it. Before the first token, this is the start of the input.
+    pub fn token_position(&self) -> Position
+    {
+        self.token_position
+    }
+
+    /// Moves the line and column past `text`.
+    fn advance_position(&mut self, text: &str)
+    {
+        for c in text.chars()
+        {
+            if c == '\n'
+            {
+                self.line += 1;
+                self.column = 1;
+            }
+            else
+            {
+                self.column += 1;
+            }
+        }
+    }
+
     /// Produces the first valid token and updates the input stream accordingly.
-    fn parse_stream(&mut self) -> Option<Result<L, anyhow::Error>>
+    fn parse_stream(&mut self) -> Option<Result<L, LexError>>
     {
         loop 
         {
@@ -105,36 +172,56 @@ impl<'a, L> TokenIterator<'a, L>
     ///     This failure means an unrecoverable error, so we want to return the error.
     ///
     /// Updates our stored position in the [input_stream].
-    fn try_parse_first_token(&mut self) -> Option<Result<L, anyhow::Error>>
+    fn try_parse_first_token(&mut self) -> Option<Result<L, LexError>>
     {
         let mut remainder = self.input_stream;
         let token = self.try_each_lexer(remainder);
         if token.is_failure()
         {
             // Halt and return the error.
-            return Some(Err(token.unwrap_err()));
+            return Some(Err(token.unwrap_err().shifted(self.consumed)));
         }
 
         if token.is_ignored()
         {
             // If nobody handled this character, silently consume it 
             // and move onto the next character.
-            remainder = &remainder[1..];
+            // Characters can be more than one byte long, so we can't just skip one byte.
+            let char_len = remainder.chars().next().map_or(1, char::len_utf8);
+            remainder = &remainder[char_len..];
+        }
+
+        if let LexerModuleResult::TokenSkipped(skipped_remainder) = &token
+        {
+            remainder = skipped_remainder;
         }
 
         if let LexerModuleResult::TokenSuccess(result) = &token
         {
-            println!("Remainder: {}", result.remainder);
             remainder = result.remainder;
         }
 
         // update input stream to strip the remaining input characters.
+        let consumed_len = self.input_stream.len() - remainder.len();
+        let consumed_text = &self.input_stream[..consumed_len];
+        // Some modules swallow the whitespace in front of their token, but the token itself starts
+        // after it. Newlines are tokens of their own, so they always count.
+        let token_text = consumed_text.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        self.advance_position(&consumed_text[..consumed_len - token_text.len()]);
+        let start = self.current_position();
+        self.advance_position(token_text);
+        self.consumed += consumed_len;
         self.input_stream = remainder;
         match token
         {
-            super::LexerModuleResult::TokenSuccess(success) => Some(Ok(success.token)),
+            super::LexerModuleResult::TokenSuccess(success) => {
+                self.token_position = start;
+                Some(Ok(success.token))
+            },
             super::LexerModuleResult::TokenIgnored => None,
-            super::LexerModuleResult::TokenFailed(error) => Some(Err(error)),
+            // Already handled above.
+            super::LexerModuleResult::TokenFailed(_) => unreachable!(),
+            super::LexerModuleResult::TokenSkipped(_) => None,
         }
     }
 
@@ -163,7 +250,7 @@ impl<'a, L> TokenIterator<'a, L>
 
 impl<'a, L> Iterator for TokenIterator<'a, L> {
     // Parsing the token stream could fail.
-    type Item = Result<L, anyhow::Error>;
+    type Item = Result<L, LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.parse_stream()
@@ -173,7 +260,9 @@ impl<'a, L> Iterator for TokenIterator<'a, L> {
 #[cfg(test)]
 mod tests
 {
-    use crate::lexer::{LexerModuleResult, LexerModuleSuccessResult};
+    use alloc::vec;
+
+    use crate::lexer::{LexError, LexerModuleResult, LexerModuleSuccessResult};
 
     use super::*;
 
@@ -184,7 +273,7 @@ mod tests
     // an input string at a time.
     struct TestLexerModule
     {
-        tokens_to_return: std::vec::IntoIter<MockLang>,
+        tokens_to_return: vec::IntoIter<MockLang>,
     }
 
     impl TestLexerModule
@@ -236,10 +325,96 @@ mod tests
         // Lol I love that we can just turn Vec<Result> into Result<Vec> with .collect().
         // Not sure how I feel about the token stream being an iterator over results but it's the
         // only thing I can think of ig.
-        let ret_tokens: Result<Vec<MockLang>, anyhow::Error> = lexer.parse_stream("A").collect();
+        let ret_tokens: Result<Vec<MockLang>, LexError> = lexer.parse_stream("A").collect();
         assert!(ret_tokens.is_ok());
         let ret_tokens = ret_tokens.unwrap();
         assert_eq!(ret_tokens.len(), 1);
         assert_eq!(ret_tokens[0], MockLang());
     }
+
+    // Always fails, to check errors make it out of the lexer.
+    struct FailingLexerModule();
+
+    impl LexerModule for FailingLexerModule
+    {
+        type Language = MockLang;
+
+        fn parse_stream<'a>(&mut self, _stream: &'a str) -> LexerModuleResult<'a, MockLang> {
+            LexerModuleResult::TokenFailed(LexError::Invalid { offset: 0, message: "nope" })
+        }
+    }
+
+    #[test]
+    fn test_lexer_returns_module_error()
+    {
+        let mut lexer = LexerBuilder::new()
+                    .add_module(Box::new(FailingLexerModule()))
+                    .build();
+
+        let ret_tokens: Result<Vec<MockLang>, LexError> = lexer.parse_stream("A").collect();
+        let error = ret_tokens.err().unwrap();
+        assert_eq!(error, LexError::Invalid { offset: 0, message: "nope" });
+        assert_eq!(alloc::format!("{}", error), "nope");
+    }
+
+    // Turns every character but whitespace into a token, so we can see where each one came from.
+    struct CharLexerModule();
+
+    impl LexerModule for CharLexerModule
+    {
+        type Language = char;
+
+        fn parse_stream<'a>(&mut self, stream: &'a str) -> LexerModuleResult<'a, char> {
+            let c = stream.chars().next().unwrap();
+            let remainder = &stream[c.len_utf8()..];
+            if c.is_whitespace()
+            {
+                return LexerModuleResult::TokenSkipped(remainder);
+            }
+            LexerModuleResult::TokenSuccess(LexerModuleSuccessResult { remainder, token: c })
+        }
+    }
+
+    #[test]
+    fn test_into_tokens_outlives_source()
+    {
+        fn lex(text: &str) -> Result<Vec<char>, LexError>
+        {
+            let lexer = LexerBuilder::new()
+                        .add_module(Box::new(CharLexerModule()))
+                        .build();
+            lexer.into_tokens(String::from(text))
+        }
+
+        // The source String and the lexer are both gone by the time we look at the tokens.
+        let tokens = lex("a b\nc").unwrap();
+        assert_eq!(tokens, vec!['a', 'b', 'c']);
+
+        let lexer = LexerBuilder::new()
+                    .add_module(Box::new(FailingLexerModule()))
+                    .build();
+        assert_eq!(lexer.into_tokens(String::from("A")), Err(LexError::Invalid { offset: 0, message: "nope" }));
+    }
+
+    #[test]
+    fn test_token_positions()
+    {
+        let mut lexer = LexerBuilder::new()
+                    .add_module(Box::new(CharLexerModule()))
+                    .build();
+
+        let mut tokens = lexer.parse_stream("ab\r\n\n  éx\ny");
+        assert_eq!(tokens.token_position(), Position { line: 1, column: 1 });
+
+        let mut positions = Vec::new();
+        while let Some(token) = tokens.next()
+        {
+            let position = tokens.token_position();
+            positions.push((token.unwrap(), position.line, position.column));
+        }
+
+        // é is two bytes but only one column, so x is still in column 4.
+        assert_eq!(positions, vec![('a', 1, 1), ('b', 1, 2), ('é', 3, 3), ('x', 3, 4), ('y', 4, 1)]);
+        assert_eq!(tokens.current_position(), Position { line: 4, column: 2 });
+    }
 }
@@ -4,10 +4,23 @@
 //! is built out of one or more of these [LexerModule]s. 
 //!
 //! To get started, construct a [lexer::LexerBuider], which is used to create a [lexer::Lexer]
+//!
+//! If the input arrives in pieces, wrap the [lexer::Lexer] in a [StreamingLexer].
+//!
+//! This module only needs `core` and `alloc`, so it's still available when the `std` feature is
+//! turned off.
 
+mod diagnostic;
+mod error;
 mod lexer;
+mod streaming_lexer;
+mod string_interner;
 
+pub use diagnostic::*;
+pub use error::*;
 pub use lexer::*;
+pub use streaming_lexer::*;
+pub use string_interner::*;
 
 /// Information contained when a token is successfully parsed out of an input stream.
 ///
@@ -26,7 +39,7 @@ pub struct LexerModuleSuccessResult<'a, L>
 ///
 /// L is the type that the [LexerModule]s should return.
 ///
-/// Handles the three possible cases:
+/// Handles the four possible cases:
 /// One: The [LexerModule] parses a token out of the start of the input stream successfully.
 /// Two: The character pattern at the start of the input stream is not a token we recognize and
 /// handle. We return this to allow the [lexer::Lexer] to continue down the chain of [LexerModule]s
@@ -35,6 +48,9 @@ pub struct LexerModuleSuccessResult<'a, L>
 /// parse, but is somehow invalid. This happens, notably, if a string doesn't end in a double
 /// quotation mark as expected, but anytime a module encounters an input string in an invalid
 /// format, it should return the [TokenFailed] variant.
+/// Four: The character pattern at the start of the input stream is something we recognize, but it
+/// doesn't produce a token (like a byte order mark). The module returns [TokenSkipped] with the
+/// rest of the input stream so the [lexer::Lexer] can move past it.
 #[derive(Debug)]
 pub enum LexerModuleResult<'a, L>
 {
@@ -43,36 +59,32 @@ pub enum LexerModuleResult<'a, L>
     /// The input prefix was not recognized.
     TokenIgnored,
     /// The input prefix was recognized, but failed to follow an expected pattern.
-    TokenFailed(anyhow::Error)
+    TokenFailed(LexError),
+    /// The input prefix was recognized and consumed, but doesn't produce a token. Contains the
+    /// remainder of the input stream.
+    TokenSkipped(&'a str),
 }
 
 impl<L> LexerModuleResult<'_, L>
 {
     pub fn is_success(&self) -> bool
     {
-        match self
-        {
-            Self::TokenSuccess(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::TokenSuccess(_))
     }
 
     pub fn is_ignored(&self) -> bool
     {
-        match self
-        {
-            Self::TokenIgnored => true,
-            _ => false,
-        }
+        matches!(self, Self::TokenIgnored)
     }
 
     pub fn is_failure(&self) -> bool
     {
-        match self
-        {
-            Self::TokenFailed(_) => true,
-            _ => false,
-        }
+        matches!(self, Self::TokenFailed(_))
+    }
+
+    pub fn is_skipped(&self) -> bool
+    {
+        matches!(self, Self::TokenSkipped(_))
     }
 
 }
@@ -88,7 +100,7 @@ impl<'a, L> LexerModuleResult<'a, L>
         }
     }
 
-    pub fn unwrap_err(self) -> anyhow::Error
+    pub fn unwrap_err(self) -> LexError
     {
         match self
         {
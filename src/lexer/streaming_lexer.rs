@@ -0,0 +1,169 @@
+//! Defines the [StreamingLexer] type, which lexes input that arrives in chunks.
+use alloc::{string::String, vec::Vec};
+
+use super::{Lexer, LexError};
+
+/// Wraps a [Lexer] so it can be fed input a piece at a time, for example while reading from a
+/// socket or an interactive prompt.
+///
+/// Input is buffered until we're sure the tokens in it can't change. A token that starts at the
+/// end of one chunk may end in the next one, so we only lex up to the last whitespace character
+/// we've seen. Anything after that could still be the start of a longer token (`PRI` might turn
+/// into `PRINT`), so it stays in the buffer until more input arrives.
+///
+/// If a [LexerModule](super::LexerModule) fails (like a string missing its closing `"`), we assume
+/// the rest of the token just hasn't arrived yet and hold onto it. Call [StreamingLexer::finish]
+/// once the input is done to lex whatever is left and report any real errors.
+pub struct StreamingLexer<L>
+{
+    lexer: Lexer<L>,
+    buffer: String,
+}
+
+impl<L> StreamingLexer<L>
+{
+    /// Creates a new [StreamingLexer] that uses `lexer` to turn the input into tokens.
+    pub fn new(lexer: Lexer<L>) -> Self
+    {
+        Self
+        {
+            lexer,
+            buffer: String::new(),
+        }
+    }
+
+    /// Appends a chunk of input to the end of the buffer.
+    pub fn push_chunk(&mut self, chunk: &str)
+    {
+        self.buffer.push_str(chunk);
+    }
+
+    /// Lexes as much of the buffered input as we can and returns the tokens we're sure about.
+    ///
+    /// Input that might still belong to an incomplete token is kept for the next call.
+    pub fn drain_tokens(&mut self) -> Vec<Result<L, LexError>>
+    {
+        // Everything up to and including the last whitespace character.
+        let safe_len = self.buffer
+            .trim_end_matches(|c: char| !c.is_whitespace())
+            .len();
+
+        let mut tokens = Vec::new();
+        let mut iter = self.lexer.parse_stream(&self.buffer[..safe_len]);
+        for token in iter.by_ref()
+        {
+            match token
+            {
+                Ok(token) => tokens.push(Ok(token)),
+                // The token might just be incomplete, so we stop here and wait for more input.
+                Err(_) => break,
+            }
+        }
+
+        let consumed = safe_len - iter.remainder().len();
+        self.buffer.drain(..consumed);
+        tokens
+    }
+
+    /// Lexes all of the remaining buffered input, including any trailing partial token.
+    ///
+    /// Call this once there's no more input coming. Lexing stops at the first error, since there's
+    /// no way for the rest of the token to show up anymore. The error's offset counts from the
+    /// start of whatever was still buffered, not the start of the whole input.
+    pub fn finish(&mut self) -> Vec<Result<L, LexError>>
+    {
+        let mut tokens = Vec::new();
+        for token in self.lexer.parse_stream(&self.buffer)
+        {
+            let is_err = token.is_err();
+            tokens.push(token);
+            if is_err
+            {
+                break;
+            }
+        }
+
+        self.buffer.clear();
+        tokens
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests
+{
+    use std::sync::Arc;
+
+    use crate::lang::create_lexer;
+    use crate::lang::token::{Keyword, Token};
+
+    use super::*;
+
+    /// Unwraps a list of token results, panicking if there were any errors.
+    fn unwrap_all(tokens: Vec<Result<Token, LexError>>) -> Vec<Token>
+    {
+        tokens.into_iter().map(|x| x.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_streaming_lexer_across_two_chunks()
+    {
+        let mut lexer = StreamingLexer::new(create_lexer());
+
+        lexer.push_chunk("PRINT ");
+        let first = unwrap_all(lexer.drain_tokens());
+        assert_eq!(first, vec![Token::Keyword(Keyword::Print)]);
+
+        lexer.push_chunk("\"hello\"\n");
+        let second = unwrap_all(lexer.drain_tokens());
+        assert_eq!(second, vec![
+            Token::String(Arc::from("hello")),
+            Token::NewLine,
+        ]);
+
+        assert!(lexer.finish().is_empty());
+    }
+
+    #[test]
+    fn test_streaming_lexer_holds_back_partial_keyword()
+    {
+        let mut lexer = StreamingLexer::new(create_lexer());
+
+        lexer.push_chunk("PRI");
+        assert!(lexer.drain_tokens().is_empty());
+
+        lexer.push_chunk("NT\n");
+        let tokens = unwrap_all(lexer.drain_tokens());
+        assert_eq!(tokens, vec![
+            Token::Keyword(Keyword::Print),
+            Token::NewLine,
+        ]);
+    }
+
+    #[test]
+    fn test_streaming_lexer_holds_back_unterminated_string()
+    {
+        let mut lexer = StreamingLexer::new(create_lexer());
+
+        lexer.push_chunk("PRINT \"hello ");
+        let first = unwrap_all(lexer.drain_tokens());
+        assert_eq!(first, vec![Token::Keyword(Keyword::Print)]);
+
+        lexer.push_chunk("world\"");
+        assert!(lexer.drain_tokens().is_empty());
+
+        let rest = unwrap_all(lexer.finish());
+        assert_eq!(rest, vec![Token::String(Arc::from("hello world"))]);
+    }
+
+    #[test]
+    fn test_streaming_lexer_finish_reports_errors()
+    {
+        let mut lexer = StreamingLexer::new(create_lexer());
+
+        lexer.push_chunk("PRINT \"never closed");
+        let tokens = lexer.finish();
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens[0].is_ok());
+        assert!(tokens[1].is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Deduplicates strings produced while lexing, so repeated literals share one allocation.
+
+use alloc::{collections::BTreeSet, sync::Arc};
+
+/// Hands out shared [Arc<str>]s for string contents, reusing the existing allocation whenever the
+/// same contents have been interned before.
+#[derive(Debug, Default)]
+pub struct StringInterner
+{
+    strings: BTreeSet<Arc<str>>,
+}
+
+impl StringInterner
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Returns the shared copy of `string`, allocating it the first time we see it.
+    pub fn intern(&mut self, string: &str) -> Arc<str>
+    {
+        if let Some(existing) = self.strings.get(string)
+        {
+            return existing.clone();
+        }
+
+        let interned: Arc<str> = Arc::from(string);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    /// How many distinct strings have been interned.
+    pub fn len(&self) -> usize
+    {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_intern_reuses_allocation()
+    {
+        let mut interner = StringInterner::new();
+        let first = interner.intern("hello");
+        let second = interner.intern("hello");
+        let other = interner.intern("world");
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -1,5 +1,12 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![recursion_limit = "256"]
+
+extern crate alloc;
+
 pub mod lexer;
+#[cfg(feature = "std")]
 pub mod grammar;
 pub mod lang;
 
-
+#[cfg(all(test, feature = "std"))]
+mod test_rng;
@@ -0,0 +1,12 @@
+#![cfg(test)]
+//! The random number generator for tests that want random input but the same input every run.
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+/// A [SmallRng] seeded with `seed`. Put the seed in the assertion message, so a failure can be
+/// replayed.
+pub fn test_rng(seed: u64) -> SmallRng
+{
+    SmallRng::seed_from_u64(seed)
+}